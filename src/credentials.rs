@@ -0,0 +1,149 @@
+use git2::{Cred, CredentialType, RemoteCallbacks, Repository};
+use std::path::{Path, PathBuf};
+
+use crate::config::{CredentialMethod, CredentialsConfig};
+use crate::error::Result;
+
+const DEFAULT_SSH_KEYS: [&str; 3] = ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Attempt {
+    SshAgent,
+    SshKey(PathBuf),
+    CredentialHelper,
+}
+
+// libgit2 calls back again after a rejected credential, so each attempt is
+// offered at most once per operation; replaying one would loop forever.
+pub fn remote_callbacks(
+    config: &CredentialsConfig,
+    repo: &Repository,
+) -> Result<RemoteCallbacks<'static>> {
+    let attempts: Vec<Attempt> = credential_attempts(config, dirs::home_dir().as_deref())
+        .into_iter()
+        .filter(|attempt| match attempt {
+            Attempt::SshKey(path) => path.exists(),
+            _ => true,
+        })
+        .collect();
+    let git_config = repo.config()?;
+    let mut next = 0;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        let username = username_from_url.unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+
+        while let Some(attempt) = attempts.get(next) {
+            next += 1;
+
+            let cred = match attempt {
+                Attempt::SshAgent if allowed.contains(CredentialType::SSH_KEY) => {
+                    Cred::ssh_key_from_agent(username)
+                }
+                Attempt::SshKey(path) if allowed.contains(CredentialType::SSH_KEY) => {
+                    Cred::ssh_key(username, None, path, None)
+                }
+                Attempt::CredentialHelper
+                    if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) =>
+                {
+                    Cred::credential_helper(&git_config, url, username_from_url)
+                }
+                _ => continue,
+            };
+
+            if let Ok(cred) = cred {
+                return Ok(cred);
+            }
+        }
+
+        Err(git2::Error::from_str(&format!(
+            "no configured credentials were accepted for {}. Check your ssh-agent, ssh keys, or git credential helper, or adjust [credentials] in .git-tidy.toml",
+            url
+        )))
+    });
+
+    Ok(callbacks)
+}
+
+fn credential_attempts(config: &CredentialsConfig, home: Option<&Path>) -> Vec<Attempt> {
+    let mut attempts = Vec::new();
+
+    for method in config.methods() {
+        match method {
+            CredentialMethod::SshAgent => attempts.push(Attempt::SshAgent),
+            CredentialMethod::SshKey => {
+                let keys: Vec<&str> = match &config.ssh_keys {
+                    Some(keys) => keys.iter().map(|k| k.as_str()).collect(),
+                    None => DEFAULT_SSH_KEYS.to_vec(),
+                };
+
+                for key in keys {
+                    match (key.strip_prefix("~/"), home) {
+                        (Some(rest), Some(home)) => attempts.push(Attempt::SshKey(home.join(rest))),
+                        (Some(_), None) => {}
+                        (None, _) => attempts.push(Attempt::SshKey(PathBuf::from(key))),
+                    }
+                }
+            }
+            CredentialMethod::CredentialHelper => attempts.push(Attempt::CredentialHelper),
+        }
+    }
+
+    attempts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credential_attempts_default_order() {
+        let attempts =
+            credential_attempts(&CredentialsConfig::default(), Some(Path::new("/home/u")));
+
+        assert_eq!(
+            attempts,
+            vec![
+                Attempt::SshAgent,
+                Attempt::SshKey(PathBuf::from("/home/u/.ssh/id_ed25519")),
+                Attempt::SshKey(PathBuf::from("/home/u/.ssh/id_ecdsa")),
+                Attempt::SshKey(PathBuf::from("/home/u/.ssh/id_rsa")),
+                Attempt::CredentialHelper,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_credential_attempts_configured() {
+        let config = CredentialsConfig {
+            methods: Some(vec![
+                CredentialMethod::CredentialHelper,
+                CredentialMethod::SshKey,
+            ]),
+            ssh_keys: Some(vec![
+                "~/.ssh/deploy".to_string(),
+                "/etc/keys/ci".to_string(),
+            ]),
+        };
+
+        assert_eq!(
+            credential_attempts(&config, Some(Path::new("/home/u"))),
+            vec![
+                Attempt::CredentialHelper,
+                Attempt::SshKey(PathBuf::from("/home/u/.ssh/deploy")),
+                Attempt::SshKey(PathBuf::from("/etc/keys/ci")),
+            ]
+        );
+        assert_eq!(
+            credential_attempts(&config, None),
+            vec![
+                Attempt::CredentialHelper,
+                Attempt::SshKey(PathBuf::from("/etc/keys/ci")),
+            ]
+        );
+    }
+}
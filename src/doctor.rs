@@ -0,0 +1,353 @@
+use git2::{BranchType, Repository};
+use glob::Pattern;
+use std::path::Path;
+
+use crate::config::{
+    Config, config_files, ignore_files, load_git_config, load_ignore_file, unknown_keys,
+};
+use crate::error::Result;
+use crate::git_operations::{
+    common_dir, ensure_no_operation_in_progress, newest_matching_tag, resolve_merge_targets,
+};
+use crate::lock::is_locked;
+#[cfg(feature = "providers")]
+use crate::provider::provider_for;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it.
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    fn ok(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn error(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Each config file that would be read, and the `[tidy]` git config section,
+/// with any keys git-tidy does not know.
+pub fn check_config_files(override_path: Option<&Path>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    match load_git_config() {
+        Ok(Some(_)) => diagnostics.push(Diagnostic::ok("Read [tidy] from git config")),
+        Ok(None) => {}
+        Err(e) => diagnostics.push(Diagnostic::error(
+            e.to_string(),
+            "Fix it with `git config --unset`, or `git config --edit`",
+        )),
+    }
+
+    for path in config_files(override_path) {
+        match unknown_keys(&path) {
+            Ok(keys) if keys.is_empty() => {
+                diagnostics.push(Diagnostic::ok(format!("Read {}", path.display())))
+            }
+            Ok(keys) => diagnostics.extend(keys.into_iter().map(|key| {
+                let hint = match &key.suggestion {
+                    Some(suggestion) => format!("Did you mean '{}'?", suggestion),
+                    None => "See `git-tidy config schema` for the supported settings".to_string(),
+                };
+                Diagnostic::error(
+                    format!("{}: unknown key '{}'", path.display(), key.key),
+                    hint,
+                )
+            })),
+            Err(e) => diagnostics.push(Diagnostic::error(
+                e.to_string(),
+                "Fix the file; git-tidy will not run until it parses",
+            )),
+        }
+    }
+
+    for path in ignore_files() {
+        match load_ignore_file(&path) {
+            Ok(_) => diagnostics.push(Diagnostic::ok(format!("Read {}", path.display()))),
+            Err(e) => diagnostics.push(Diagnostic::error(
+                e.to_string(),
+                "Fix the pattern; git-tidy will not run until it is valid",
+            )),
+        }
+    }
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::ok("No config files; using defaults"));
+    }
+
+    diagnostics
+}
+
+/// Settings that would stop a run, and a Jira lookup missing its token.
+pub fn check_settings(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = config
+        .problems()
+        .into_iter()
+        .map(|problem| {
+            Diagnostic::error(
+                problem.to_string(),
+                "Fix the setting; git-tidy will not run until it is valid",
+            )
+        })
+        .collect();
+
+    if config.jira.url.is_some() && std::env::var_os(config.jira.token_env()).is_none() {
+        diagnostics.push(Diagnostic::error(
+            format!("jira.url is set but {} is not set", config.jira.token_env()),
+            format!("Export {} with a Jira API token", config.jira.token_env()),
+        ));
+    }
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::ok("Settings are valid"));
+    }
+
+    diagnostics
+}
+
+/// Repository state that would stop or mislead a run: an operation in
+/// progress, a held lock, a shallow clone, merge targets and patterns that
+/// match no branch, and a forge integration that cannot be set up.
+pub fn check_repository(repo: &Repository, config: &Config) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+
+    if let Err(e) = ensure_no_operation_in_progress(repo) {
+        diagnostics.push(Diagnostic::error(
+            e.to_string(),
+            "--clean refuses to delete branches until then",
+        ));
+    }
+
+    if is_locked(&common_dir(repo)?) {
+        diagnostics.push(Diagnostic::warning(
+            "Another git-tidy run holds the repository lock",
+            "Wait for it to finish; a lock left by a crashed run clears itself",
+        ));
+    }
+
+    if repo.is_shallow() {
+        diagnostics.push(Diagnostic::warning(
+            "The repository is a shallow clone",
+            "Merge detection needs full history; run `git fetch --unshallow` or git-tidy --fetch-unshallow",
+        ));
+    }
+
+    let mut local = Vec::new();
+    let mut remote = Vec::new();
+    for branch in repo.branches(None)? {
+        let (branch, branch_type) = branch?;
+        if let Some(name) = branch.name()? {
+            match branch_type {
+                BranchType::Local => local.push(name.to_string()),
+                BranchType::Remote => remote.push(name.to_string()),
+            }
+        }
+    }
+
+    if let Some(merge_remote) = &config.merge_remote
+        && repo.find_remote(merge_remote).is_err()
+    {
+        diagnostics.push(Diagnostic::error(
+            format!("merge_remote '{}' is not a remote", merge_remote),
+            "Set it to one of `git remote`",
+        ));
+    }
+
+    // A merge target may be a remote branch when merge_remote is set.
+    let remote_prefix = config.merge_remote.as_ref().map(|r| format!("{}/", r));
+    let target_names: Vec<&str> = local
+        .iter()
+        .map(String::as_str)
+        .chain(remote.iter().filter_map(|name| {
+            remote_prefix
+                .as_deref()
+                .and_then(|prefix| name.strip_prefix(prefix))
+        }))
+        .collect();
+    for target in config.merge_targets.iter().flatten() {
+        if let Ok(pattern) = Pattern::new(target)
+            && !target_names.iter().any(|name| pattern.matches(name))
+        {
+            diagnostics.push(Diagnostic::error(
+                format!("merge target '{}' matches no branch", target),
+                "Branches merged only into it are reported as not merged; fix or remove it",
+            ));
+        }
+    }
+    if let Ok(Some(pattern)) = config.get_merge_tag()
+        && newest_matching_tag(repo, &pattern)?.is_none()
+    {
+        diagnostics.push(Diagnostic::error(
+            format!("merge_tag '{}' matches no tag", pattern),
+            "Branches released only in tags are reported as not merged; fix it or fetch the tags",
+        ));
+    }
+    if resolve_merge_targets(repo, config)?.is_empty() && config.merge_targets.is_none() {
+        diagnostics.push(Diagnostic::error(
+            "No default branch to check merges against",
+            "Set merge_targets to your default branch, or run 'git remote set-head origin --auto'",
+        ));
+    }
+
+    let globs = config
+        .get_protected_branches()
+        .into_iter()
+        .filter(|name| name.contains('*'))
+        .map(|glob| ("protected pattern", glob))
+        .chain(
+            config
+                .delete_only_patterns
+                .iter()
+                .flatten()
+                .map(|glob| ("delete_only_patterns entry", glob.clone())),
+        );
+    for (kind, glob) in globs {
+        if let Ok(pattern) = Pattern::new(&glob)
+            && !local.iter().any(|name| pattern.matches(name))
+        {
+            diagnostics.push(Diagnostic::warning(
+                format!("{} '{}' matches no local branch", kind, glob),
+                "Check it for typos, or ignore this if the branches come later",
+            ));
+        }
+    }
+
+    // Choosing the provider needs the remote, its host, and the token, but
+    // no request.
+    #[cfg(feature = "providers")]
+    if config.server_protection.enabled()
+        && let Err(e) = provider_for(repo, &config.server_protection)
+    {
+        diagnostics.push(Diagnostic::error(
+            e.to_string(),
+            "Fix [server_protection], or run without --sync-protection",
+        ));
+    }
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::ok("Repository is ready"));
+    }
+
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProtectedBranches;
+    use git2::{RepositoryInitOptions, Signature};
+
+    #[test]
+    fn test_check_repository() {
+        let path =
+            std::env::temp_dir().join(format!("git-tidy-test-doctor-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let repo = Repository::init_opts(&path, RepositoryInitOptions::new().initial_head("main"))
+            .unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+            .unwrap();
+
+        let config = Config {
+            merge_targets: Some(vec!["main".to_string(), "develop".to_string()]),
+            protected_branches: ProtectedBranches {
+                additional: Some(vec!["releas/*".to_string(), "ma*".to_string()]),
+                ..ProtectedBranches::default()
+            },
+            ..Config::new()
+        };
+
+        let messages: Vec<(Severity, String)> = check_repository(&repo, &config)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.severity, d.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Severity::Error,
+                    "merge target 'develop' matches no branch".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "protected pattern 'releas/*' matches no local branch".to_string()
+                ),
+            ]
+        );
+
+        // A run in the main checkout holds the lock for every worktree.
+        let worktree_path = path.with_extension("linked");
+        let _ = std::fs::remove_dir_all(&worktree_path);
+        repo.worktree("linked", &worktree_path, None).unwrap();
+        let linked = Repository::open(&worktree_path).unwrap();
+        let _lock = crate::lock::RepoLock::acquire(&common_dir(&repo).unwrap()).unwrap();
+        assert!(
+            check_repository(&linked, &Config::new())
+                .unwrap()
+                .iter()
+                .any(|d| d.message == "Another git-tidy run holds the repository lock")
+        );
+
+        let _ = std::fs::remove_dir_all(&worktree_path);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_check_settings() {
+        let config = Config {
+            older_than: Some("soon".to_string()),
+            protected_branches: ProtectedBranches {
+                patterns: Some(vec!["(unclosed".to_string()]),
+                ..ProtectedBranches::default()
+            },
+            ..Config::new()
+        };
+
+        let diagnostics = check_settings(&config);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert!(
+            diagnostics[0]
+                .message
+                .starts_with("Invalid regex '(unclosed'")
+        );
+        assert!(
+            diagnostics[1]
+                .message
+                .starts_with("Invalid older_than 'soon'")
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::config::{ServerProvider, parse_remote_url};
+use crate::output::{Plan, PlanAction, PlanEntry};
+use crate::tidy::Report;
+
+/// Formats for a `--report` written to share a cleanup, e.g. in an
+/// announcement or merge request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// Links to branch pages on the forge hosting a remote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchLinks {
+    base: String,
+    provider: ServerProvider,
+}
+
+impl BranchLinks {
+    /// `None` when the URL is not a forge's or the provider is unknown;
+    /// `provider` overrides guessing it from the host name.
+    pub fn from_remote_url(url: &str, provider: Option<ServerProvider>) -> Option<Self> {
+        let (host, project) = parse_remote_url(url)?;
+        let provider = provider.or_else(|| ServerProvider::detect(&host))?;
+        Some(BranchLinks {
+            base: format!("https://{}/{}", host, project),
+            provider,
+        })
+    }
+
+    pub fn url(&self, branch: &str) -> String {
+        let branch = encode_path(branch);
+        match self.provider {
+            ServerProvider::GitHub => format!("{}/tree/{}", self.base, branch),
+            ServerProvider::GitLab => format!("{}/-/tree/{}", self.base, branch),
+            ServerProvider::Gitea => format!("{}/src/branch/{}", self.base, branch),
+            ServerProvider::Bitbucket => format!("{}/branch/{}", self.base, branch),
+        }
+    }
+}
+
+/// A report of a cleanup plan and, after `--clean`, of what was deleted.
+pub struct Document<'a> {
+    /// Names the repository in the title.
+    pub repository: &'a str,
+    pub plan: &'a Plan,
+    pub report: Option<&'a Report>,
+    pub links: Option<&'a BranchLinks>,
+}
+
+impl Document<'_> {
+    pub fn render(&self, format: ReportFormat) -> String {
+        let mut writer: Box<dyn Writer> = match format {
+            ReportFormat::Markdown => Box::new(Markdown::default()),
+            ReportFormat::Html => Box::new(Html::default()),
+        };
+        let w = writer.as_mut();
+
+        w.heading(1, &format!("Branch cleanup: {}", self.repository));
+        w.paragraph(&format!(
+            "As of {}: {}.",
+            self.plan.as_of.format("%Y-%m-%d %H:%M UTC"),
+            self.plan.summary.describe()
+        ));
+
+        if let Some(report) = self.report {
+            w.paragraph(&self.outcome(report));
+        }
+
+        let delete: Vec<&PlanEntry> = self.entries(&[PlanAction::Delete, PlanAction::Archive]);
+        let verb = if self.plan.summary.archive > 0 {
+            "archive"
+        } else {
+            "delete"
+        };
+        w.heading(2, &format!("To {} ({})", verb, delete.len()));
+        if delete.is_empty() {
+            w.paragraph(&format!("No branches to {}.", verb));
+        }
+
+        // Grouped by owner, so each person can find their own branches.
+        let mut by_owner: BTreeMap<&str, Vec<&PlanEntry>> = BTreeMap::new();
+        for entry in &delete {
+            by_owner.entry(&entry.owner).or_default().push(entry);
+        }
+        for (owner, entries) in by_owner {
+            let owner = if owner.is_empty() {
+                "(no owner)"
+            } else {
+                owner
+            };
+            w.heading(3, &format!("{} ({})", owner, entries.len()));
+            let mut columns = vec!["Branch", "Last commit", "Age (days)", "Reason"];
+            if self.report.is_some() {
+                columns.push("Result");
+            }
+            let rows: Vec<Vec<Cell>> = entries
+                .iter()
+                .map(|entry| {
+                    let reason = match &entry.reason {
+                        Some(reason) => reason.clone(),
+                        None if entry.merged => "merged".to_string(),
+                        None => String::new(),
+                    };
+                    let mut row = vec![
+                        self.branch_cell(&entry.name),
+                        Cell::text(entry.last_commit.format("%Y-%m-%d").to_string()),
+                        Cell::text(entry.age_days.to_string()),
+                        Cell::text(reason),
+                    ];
+                    if let Some(report) = self.report {
+                        row.push(Cell::text(result(report, &entry.name)));
+                    }
+                    row
+                })
+                .collect();
+            w.table(&columns, &rows);
+        }
+
+        for (title, action) in [
+            ("Kept", PlanAction::Keep),
+            ("Protected", PlanAction::Protected),
+            ("Case collisions", PlanAction::CaseCollision),
+        ] {
+            let entries = self.entries(&[action]);
+            if entries.is_empty() {
+                continue;
+            }
+            w.heading(2, &format!("{} ({})", title, entries.len()));
+            let rows: Vec<Vec<Cell>> = entries
+                .iter()
+                .map(|entry| {
+                    vec![
+                        self.branch_cell(&entry.name),
+                        Cell::text(entry.owner.clone()),
+                        Cell::text(entry.last_commit.format("%Y-%m-%d").to_string()),
+                        Cell::text(entry.reason.clone().unwrap_or_default()),
+                    ]
+                })
+                .collect();
+            w.table(&["Branch", "Owner", "Last commit", "Reason"], &rows);
+        }
+
+        if let Some(report) = self.report
+            && !report.failed.is_empty()
+        {
+            w.heading(2, &format!("Failed ({})", report.failed.len()));
+            let rows: Vec<Vec<Cell>> = report
+                .failed
+                .iter()
+                .map(|failure| {
+                    vec![
+                        Cell::code(failure.branch.clone()),
+                        Cell::text(failure.error.clone()),
+                    ]
+                })
+                .collect();
+            w.table(&["Branch", "Error"], &rows);
+        }
+
+        writer.finish(self.repository)
+    }
+
+    fn entries(&self, actions: &[PlanAction]) -> Vec<&PlanEntry> {
+        self.plan
+            .branches
+            .iter()
+            .filter(|entry| actions.contains(&entry.action))
+            .collect()
+    }
+
+    fn branch_cell(&self, name: &str) -> Cell {
+        Cell {
+            text: name.to_string(),
+            code: true,
+            link: self.links.map(|links| links.url(name)),
+        }
+    }
+
+    fn outcome(&self, report: &Report) -> String {
+        let mut line = if self.plan.summary.archive > 0 {
+            format!("Archived {} branches.", report.archived.len())
+        } else if report.deleted.is_empty() && !report.remote_deleted.is_empty() {
+            format!("Deleted {} remote branches.", report.remote_deleted.len())
+        } else {
+            format!("Deleted {} branches.", report.deleted.len())
+        };
+        if !report.failed.is_empty() {
+            write!(line, " {} failed.", report.failed.len()).unwrap();
+        }
+        if !report.not_attempted.is_empty() {
+            write!(line, " {} not attempted.", report.not_attempted.len()).unwrap();
+        }
+        if report.cancelled {
+            line.push_str(" Cancelled.");
+        }
+        line
+    }
+}
+
+fn result(report: &Report, name: &str) -> String {
+    let name = name.to_string();
+    let remote_suffix = format!("/{}", name);
+    if report.deleted.contains(&name)
+        || report
+            .remote_deleted
+            .iter()
+            .any(|remote_branch| remote_branch.ends_with(&remote_suffix))
+    {
+        "deleted".to_string()
+    } else if report.archived.contains(&name) {
+        "archived".to_string()
+    } else if report.failed.iter().any(|failure| failure.branch == name) {
+        "failed".to_string()
+    } else if report.not_attempted.contains(&name) {
+        "not attempted".to_string()
+    } else {
+        "skipped".to_string()
+    }
+}
+
+// Branch names keep their slashes, which forges expect unencoded.
+fn encode_path(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+struct Cell {
+    text: String,
+    code: bool,
+    link: Option<String>,
+}
+
+impl Cell {
+    fn text(text: String) -> Self {
+        Cell {
+            text,
+            code: false,
+            link: None,
+        }
+    }
+
+    fn code(text: String) -> Self {
+        Cell {
+            text,
+            code: true,
+            link: None,
+        }
+    }
+}
+
+trait Writer {
+    fn heading(&mut self, level: usize, text: &str);
+    fn paragraph(&mut self, text: &str);
+    fn table(&mut self, columns: &[&str], rows: &[Vec<Cell>]);
+    fn finish(self: Box<Self>, title: &str) -> String;
+}
+
+#[derive(Default)]
+struct Markdown {
+    out: String,
+}
+
+impl Markdown {
+    fn cell(cell: &Cell) -> String {
+        let text = cell.text.replace('|', "\\|").replace('\n', " ");
+        let text = if cell.code {
+            format!("`{}`", text)
+        } else {
+            text
+        };
+        match &cell.link {
+            Some(url) => format!("[{}]({})", text, url),
+            None => text,
+        }
+    }
+}
+
+impl Writer for Markdown {
+    fn heading(&mut self, level: usize, text: &str) {
+        writeln!(self.out, "{} {}\n", "#".repeat(level), text).unwrap();
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        writeln!(self.out, "{}\n", text).unwrap();
+    }
+
+    fn table(&mut self, columns: &[&str], rows: &[Vec<Cell>]) {
+        writeln!(self.out, "| {} |", columns.join(" | ")).unwrap();
+        writeln!(self.out, "|{}", " --- |".repeat(columns.len())).unwrap();
+        for row in rows {
+            let cells: Vec<String> = row.iter().map(Markdown::cell).collect();
+            writeln!(self.out, "| {} |", cells.join(" | ")).unwrap();
+        }
+        self.out.push('\n');
+    }
+
+    fn finish(self: Box<Self>, _title: &str) -> String {
+        let mut out = self.out;
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+}
+
+#[derive(Default)]
+struct Html {
+    body: String,
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f4f4f4; }";
+
+impl Html {
+    fn cell(cell: &Cell) -> String {
+        let text = escape_html(&cell.text);
+        let text = if cell.code {
+            format!("<code>{}</code>", text)
+        } else {
+            text
+        };
+        match &cell.link {
+            Some(url) => format!("<a href=\"{}\">{}</a>", escape_html(url), text),
+            None => text,
+        }
+    }
+}
+
+impl Writer for Html {
+    fn heading(&mut self, level: usize, text: &str) {
+        writeln!(self.body, "<h{0}>{1}</h{0}>", level, escape_html(text)).unwrap();
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        writeln!(self.body, "<p>{}</p>", escape_html(text)).unwrap();
+    }
+
+    fn table(&mut self, columns: &[&str], rows: &[Vec<Cell>]) {
+        self.body.push_str("<table>\n<tr>");
+        for column in columns {
+            write!(self.body, "<th>{}</th>", escape_html(column)).unwrap();
+        }
+        self.body.push_str("</tr>\n");
+        for row in rows {
+            self.body.push_str("<tr>");
+            for cell in row {
+                write!(self.body, "<td>{}</td>", Html::cell(cell)).unwrap();
+            }
+            self.body.push_str("</tr>\n");
+        }
+        self.body.push_str("</table>\n");
+    }
+
+    fn finish(self: Box<Self>, title: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Branch cleanup: {}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(title),
+            HTML_STYLE,
+            self.body
+        )
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_operations::BranchInfo;
+    use crate::tidy::Failure;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn test_plan() -> Plan {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let branch = |name: &str, owner: &str, days_ago: i64| BranchInfo {
+            name: name.to_string(),
+            tip: git2::Oid::zero(),
+            is_merged: true,
+            merged_at: None,
+            last_commit_date: now - Duration::days(days_ago),
+            last_commit_subject: String::new(),
+            last_commit_author: String::new(),
+            short_sha: String::new(),
+            remote: None,
+            redundant: None,
+            is_empty: false,
+            is_orphan: false,
+            owner: owner.to_string(),
+        };
+
+        Plan::new(
+            now,
+            vec![
+                PlanEntry::new(
+                    &branch("feature/a|b", "alice@example.com", 40),
+                    PlanAction::Delete,
+                    None,
+                    now,
+                ),
+                PlanEntry::new(
+                    &branch("fix/<x>", "bob@example.com", 50),
+                    PlanAction::Delete,
+                    None,
+                    now,
+                ),
+                PlanEntry::new(
+                    &branch("main", "alice@example.com", 0),
+                    PlanAction::Protected,
+                    Some("current".to_string()),
+                    now,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_branch_links() {
+        let github = BranchLinks::from_remote_url("git@github.com:owner/repo.git", None).unwrap();
+        assert_eq!(
+            github.url("feature/a b"),
+            "https://github.com/owner/repo/tree/feature/a%20b"
+        );
+
+        let gitlab =
+            BranchLinks::from_remote_url("https://gitlab.example.com/group/project", None).unwrap();
+        assert_eq!(
+            gitlab.url("fix"),
+            "https://gitlab.example.com/group/project/-/tree/fix"
+        );
+
+        let gitea = BranchLinks::from_remote_url(
+            "https://git.example.com/owner/repo",
+            Some(ServerProvider::Gitea),
+        )
+        .unwrap();
+        assert_eq!(
+            gitea.url("fix"),
+            "https://git.example.com/owner/repo/src/branch/fix"
+        );
+
+        assert_eq!(
+            BranchLinks::from_remote_url("https://git.example.com/owner/repo", None),
+            None
+        );
+        assert_eq!(
+            BranchLinks::from_remote_url("/srv/git/repo.git", None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let plan = test_plan();
+        let links = BranchLinks::from_remote_url("git@github.com:owner/repo.git", None).unwrap();
+        let document = Document {
+            repository: "repo",
+            plan: &plan,
+            report: None,
+            links: Some(&links),
+        };
+
+        assert_eq!(
+            document.render(ReportFormat::Markdown),
+            "# Branch cleanup: repo\n\n\
+             As of 2024-06-01 12:00 UTC: 2 to delete, 1 protected (current 1).\n\n\
+             ## To delete (2)\n\n\
+             ### alice@example.com (1)\n\n\
+             | Branch | Last commit | Age (days) | Reason |\n\
+             | --- | --- | --- | --- |\n\
+             | [`feature/a\\|b`](https://github.com/owner/repo/tree/feature/a%7Cb) | 2024-04-22 | 40 | merged |\n\n\
+             ### bob@example.com (1)\n\n\
+             | Branch | Last commit | Age (days) | Reason |\n\
+             | --- | --- | --- | --- |\n\
+             | [`fix/<x>`](https://github.com/owner/repo/tree/fix/%3Cx%3E) | 2024-04-12 | 50 | merged |\n\n\
+             ## Protected (1)\n\n\
+             | Branch | Owner | Last commit | Reason |\n\
+             | --- | --- | --- | --- |\n\
+             | [`main`](https://github.com/owner/repo/tree/main) | alice@example.com | 2024-06-01 | current |\n"
+        );
+    }
+
+    #[test]
+    fn test_render_html_with_report() {
+        let plan = test_plan();
+        let report = Report {
+            deleted: vec!["feature/a|b".to_string()],
+            failed: vec![Failure {
+                branch: "fix/<x>".to_string(),
+                error: "tip moved".to_string(),
+            }],
+            ..Default::default()
+        };
+        let document = Document {
+            repository: "repo",
+            plan: &plan,
+            report: Some(&report),
+            links: None,
+        };
+
+        let html = document.render(ReportFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Branch cleanup: repo</title>"));
+        assert!(html.contains("<p>Deleted 1 branches. 1 failed.</p>"));
+        assert!(html.contains("<h2>To delete (2)</h2>"));
+        assert!(html.contains(
+            "<tr><td><code>feature/a|b</code></td><td>2024-04-22</td><td>40</td><td>merged</td><td>deleted</td></tr>"
+        ));
+        assert!(html.contains("<td><code>fix/&lt;x&gt;</code></td>"));
+        assert!(html.contains("<td>failed</td>"));
+        assert!(html.contains("<h2>Failed (1)</h2>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
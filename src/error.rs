@@ -0,0 +1,322 @@
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::config::UnknownKey;
+
+/// Errors returned by the git-tidy library. Refusals to delete a branch get
+/// their own variants so callers can tell a protected branch from a failure.
+#[derive(Debug, Error)]
+pub enum GitTidyError {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("Branch '{branch}' moved from {planned} to {actual} since the plan was shown")]
+    TipMoved {
+        branch: String,
+        planned: git2::Oid,
+        actual: git2::Oid,
+    },
+
+    #[error("Cannot archive '{branch}': {reference} already exists")]
+    ArchiveExists { branch: String, reference: String },
+
+    #[error("No archived branch named '{0}'. Run 'git-tidy unarchive' to list archived branches.")]
+    NotArchived(String),
+
+    #[error("A branch named '{0}' already exists")]
+    BranchExists(String),
+
+    #[error(
+        "Cannot create {reference}: {existing} is in the way, as a ref cannot also be a folder of refs. Rename or delete {existing} first."
+    )]
+    RefConflict { reference: String, existing: String },
+
+    #[error(
+        "{operation} is in progress. Finish it or run '{abort_command}' before deleting branches."
+    )]
+    OperationInProgress {
+        operation: &'static str,
+        abort_command: &'static str,
+    },
+
+    #[error("Worktree '{0}' is locked")]
+    WorktreeLocked(String),
+
+    #[error("Upstream of '{0}' is not valid UTF-8")]
+    InvalidUpstream(String),
+
+    #[error("Failed to fetch from '{remote}': {source}")]
+    Fetch { remote: String, source: git2::Error },
+
+    #[error("Failed to push to '{remote}': {source}")]
+    Push { remote: String, source: git2::Error },
+
+    #[error("'{0}' no longer exists on the remote")]
+    RemoteBranchGone(String),
+
+    #[error("'{remote}' rejected the deletion: {reason}")]
+    PushRejected { remote: String, reason: String },
+
+    #[error("Cannot determine the project for remote URL '{0}'")]
+    UnsupportedRemoteUrl(String),
+
+    #[error(
+        "Cannot tell whether {0} is GitHub, GitLab, Gitea, or Bitbucket. Map it under [server_protection.hosts] in your config, e.g. \"{0}\" = \"gitlab\"."
+    )]
+    UnknownProvider(String),
+
+    #[error(
+        "Environment variable {0} is not set. Set it to a token that can read branch protection rules and pull requests, store one with 'git-tidy auth login', or log in with gh or glab."
+    )]
+    MissingToken(String),
+
+    #[error("Could not use the system keyring: {0}")]
+    Keyring(String),
+
+    #[error(
+        "This provider has no public instance to default to. Pass --host with the server's host name."
+    )]
+    MissingHost,
+
+    #[error(
+        "Environment variable {0} is not set. Set it to a Jira API token that can browse the projects' issues."
+    )]
+    MissingJiraToken(String),
+
+    #[error(
+        "{0} has no deployments API git-tidy can read. Set deployments_url under [server_protection] to an endpoint listing your deployments."
+    )]
+    DeploymentsUnsupported(&'static str),
+
+    #[error("Request to {url} failed: {message}")]
+    Provider { url: String, message: String },
+
+    #[error("Offline, and no earlier response from {0} is cached")]
+    Offline(String),
+
+    #[error(
+        "user.email is not set, so --mine cannot tell which branches are yours. Set it with 'git config user.email'."
+    )]
+    NoUserEmail,
+
+    #[error("Could not determine home directory")]
+    NoHomeDirectory,
+
+    #[error("Could not determine local data directory")]
+    NoDataDirectory,
+
+    #[error("Config file {} does not exist. Check --config or GIT_TIDY_CONFIG.", .0.display())]
+    ConfigNotFound(PathBuf),
+
+    #[error("'git {command}' failed: {message}")]
+    Maintenance { command: String, message: String },
+
+    #[error("Failed to write backup bundle {}: {message}", .path.display())]
+    Bundle { path: PathBuf, message: String },
+
+    #[error("Failed to read branch list {}: {source}", .path.display())]
+    BranchListRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to read plan {}: {source}", .path.display())]
+    PlanRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error(
+        "Failed to parse plan {}: {source}. Save plans with 'git-tidy plan' or --output json.",
+        .path.display()
+    )]
+    PlanParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error("Failed to read config file {}: {source}", .path.display())]
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}", unknown_keys_message(.source_name, .keys))]
+    UnknownConfigKeys {
+        source_name: String,
+        keys: Vec<UnknownKey>,
+    },
+
+    #[error(
+        "{} sets {}, which decide where git-tidy sends requests and what they carry. Set them in your global config or a file passed with --config instead.",
+        .path.display(),
+        .keys.join(", ")
+    )]
+    UserOnlyConfigKeys { path: PathBuf, keys: Vec<String> },
+
+    #[error("Failed to parse config file {}: {source}", .path.display())]
+    ConfigParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid glob '{pattern}': {message}")]
+    InvalidGlob { pattern: String, message: String },
+
+    #[error("Invalid pattern '{pattern}' in {}, line {line}: {message}", .path.display())]
+    InvalidIgnorePattern {
+        path: PathBuf,
+        line: usize,
+        pattern: String,
+        message: String,
+    },
+
+    #[error("Invalid regex '{pattern}': {message}")]
+    InvalidRegex { pattern: String, message: String },
+
+    #[error(
+        "Unknown preset '{0}'. Define it under [presets.{0}] in your config, or use a built-in preset: bots"
+    )]
+    UnknownPreset(String),
+
+    #[error("Invalid older_than '{value}': {message}")]
+    InvalidOlderThan { value: String, message: String },
+
+    #[error("Invalid server_protection.delete_closed_after '{value}': {message}")]
+    InvalidDeleteClosedAfter { value: String, message: String },
+
+    #[error("Invalid age_colors.{key} '{value}': {message}")]
+    InvalidAgeColors {
+        key: &'static str,
+        value: String,
+        message: String,
+    },
+
+    #[error("Invalid archive_namespace '{0}': it does not give a valid ref name")]
+    InvalidArchiveNamespace(String),
+
+    #[error("Invalid naming.rename '{template}': {message}")]
+    InvalidRenameTemplate { template: String, message: String },
+
+    #[error("{}", unknown_profile_message(.name, .available))]
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+
+    #[error("Invalid older_than in preset '{name}': {message}")]
+    InvalidPreset { name: String, message: String },
+
+    #[error("Invalid exception date '{until}' for branch '{branch}'. Expected YYYY-MM-DD")]
+    InvalidException { branch: String, until: String },
+
+    #[error("{}", locked_message(.path, .owner))]
+    Locked {
+        path: PathBuf,
+        owner: Option<(u32, DateTime<Utc>)>,
+    },
+
+    #[error("Failed to create lock file {}: {source}", .path.display())]
+    LockCreate {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse cleanup history {}, line {line}: {source}", .path.display())]
+    HistoryParse {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[error("Failed to parse metrics file {}: {source}", .path.display())]
+    MetricsParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error("Failed to send metrics to {endpoint}: {message}")]
+    MetricsSend { endpoint: String, message: String },
+
+    // The webhook URL is left out because Slack-style URLs embed a secret.
+    #[error("Failed to send the run notification: {0}")]
+    NotifySend(String),
+
+    /// Some branches were cleaned up and others were not; each failure has
+    /// already been reported.
+    #[error("Failed to clean up {0} branches (see above)")]
+    PartialFailure(usize),
+
+    /// The user aborted a prompt (ctrl-c, Esc, or end of input) rather than
+    /// answering it.
+    #[error("Cancelled by user")]
+    Cancelled,
+}
+
+pub type Result<T, E = GitTidyError> = std::result::Result<T, E>;
+
+impl GitTidyError {
+    /// Whether git-tidy or the remote refused the deletion, so trying again
+    /// cannot succeed.
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self,
+            GitTidyError::TipMoved { .. }
+                | GitTidyError::ArchiveExists { .. }
+                | GitTidyError::RefConflict { .. }
+                | GitTidyError::RemoteBranchGone(_)
+                | GitTidyError::PushRejected { .. }
+        )
+    }
+}
+
+fn unknown_keys_message(source_name: &str, keys: &[UnknownKey]) -> String {
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+    format!(
+        "Unknown {} in {}: {}",
+        if keys.len() == 1 { "key" } else { "keys" },
+        source_name,
+        keys.join(", ")
+    )
+}
+
+fn unknown_profile_message(name: &str, available: &[String]) -> String {
+    if available.is_empty() {
+        format!(
+            "Unknown profile '{}'. Define it under [profile.{}] in your config.",
+            name, name
+        )
+    } else {
+        format!(
+            "Unknown profile '{}'. Available profiles: {}",
+            name,
+            available.join(", ")
+        )
+    }
+}
+
+fn locked_message(path: &Path, owner: &Option<(u32, DateTime<Utc>)>) -> String {
+    match owner {
+        Some((pid, created_at)) => format!(
+            "Another git-tidy process (pid {}) has been running on this repository since {}. Wait for it to finish, or remove {} if it is no longer running.",
+            pid,
+            created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            path.display()
+        ),
+        None => format!(
+            "Another git-tidy process is running on this repository. Wait for it to finish, or remove {} if it is no longer running.",
+            path.display()
+        ),
+    }
+}
@@ -1,171 +1,534 @@
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Utc};
 use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
 
+use crate::config::{Period, parse_duration};
 use crate::git_operations::BranchInfo;
 
-#[allow(dead_code)]
-pub fn filter_by_merge_status<'a>(
-    branches: &'a [&'a BranchInfo],
-    merged_only: bool,
-) -> Vec<&'a BranchInfo> {
-    branches
-        .iter()
-        .filter(|b| !merged_only || b.is_merged)
-        .copied()
-        .collect()
+// Filter expressions for --where, e.g.
+//   merged && age > 30d && name =~ "^feature/"
+//
+// expr    := and ("||" and)*
+// and     := unary ("&&" unary)*
+// unary   := "!" unary | "(" expr ")" | field [op value]
+// fields  := name (==, !=, =~, !~ against a string), age (==, !=, <, <=, >,
+//            >= against a duration), merged (bare boolean)
+#[derive(Debug, Clone)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Merged,
+    NameEquals(String),
+    NameMatches(Regex),
+    Age(CompareOp, Period),
 }
 
-pub fn filter_by_age<'a>(
-    branches: &'a [&'a BranchInfo],
-    older_than: Duration,
-) -> Vec<&'a BranchInfo> {
-    let cutoff = Utc::now() - older_than;
-
-    branches
-        .iter()
-        .filter(|b| b.last_commit_date <= cutoff)
-        .copied()
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
 }
 
-#[allow(dead_code)]
-pub fn filter_by_pattern<'a>(
-    branches: &'a [&'a BranchInfo],
-    pattern: &Regex,
-) -> Vec<&'a BranchInfo> {
-    branches
-        .iter()
-        .filter(|b| pattern.is_match(&b.name))
-        .copied()
-        .collect()
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
 }
 
-pub fn filter_out_protected<'a>(
-    branches: &'a [&'a BranchInfo],
-    protected_branches: &[String],
-    current_branch: Option<&str>,
-) -> Vec<&'a BranchInfo> {
-    branches
-        .iter()
-        .filter(|b| {
-            !protected_branches.contains(&b.name) && current_branch != Some(b.name.as_str())
-        })
-        .copied()
-        .collect()
+impl FilterExpr {
+    pub fn matches(&self, branch: &BranchInfo, now: DateTime<Utc>) -> bool {
+        match self {
+            FilterExpr::And(a, b) => a.matches(branch, now) && b.matches(branch, now),
+            FilterExpr::Or(a, b) => a.matches(branch, now) || b.matches(branch, now),
+            FilterExpr::Not(e) => !e.matches(branch, now),
+            FilterExpr::Merged => branch.is_merged,
+            FilterExpr::NameEquals(name) => branch.name == *name,
+            FilterExpr::NameMatches(regex) => regex.is_match(&branch.name),
+            FilterExpr::Age(op, period) => {
+                // Older branches were last committed to before the cutoff.
+                let cutoff = period.before(now);
+                let date = branch.last_commit_date;
+                match op {
+                    CompareOp::Eq => date == cutoff,
+                    CompareOp::Ne => date != cutoff,
+                    CompareOp::Lt => date > cutoff,
+                    CompareOp::Le => date >= cutoff,
+                    CompareOp::Gt => date < cutoff,
+                    CompareOp::Ge => date <= cutoff,
+                }
+            }
+        }
+    }
+}
+
+pub fn parse_filter_expr(input: &str) -> Result<FilterExpr, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+
+    let expr = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("Unexpected {:?} in filter expression", token));
+    }
+
+    Ok(expr)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 12] = [
+        "&&", "||", "==", "!=", "=~", "!~", ">=", "<=", ">", "<", "!", "=",
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c == '(' {
+            tokens.push(Token::LParen);
+            rest = &rest[1..];
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            rest = &rest[1..];
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let mut end = None;
+
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => break,
+                    },
+                    '"' => {
+                        end = Some(i + 2);
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+
+            let end = end.ok_or_else(|| "Unterminated string in filter expression".to_string())?;
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            if *op == "=" {
+                return Err("Unknown operator '=' in filter expression (use ==)".to_string());
+            }
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c.is_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("Unexpected character '{}' in filter expression", c));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.parse_and()?;
+        while self.eat_op("||") {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.eat_op("&&") {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr, String> {
+        if self.eat_op("!") {
+            return Ok(FilterExpr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("Expected ')' in filter expression".to_string()),
+                }
+            }
+            Some(Token::Ident(field)) => self.parse_field(&field),
+            Some(token) => Err(format!("Unexpected {:?} in filter expression", token)),
+            None => Err("Unexpected end of filter expression".to_string()),
+        }
+    }
+
+    fn parse_field(&mut self, field: &str) -> Result<FilterExpr, String> {
+        match field {
+            "merged" => Ok(FilterExpr::Merged),
+            "name" => {
+                let op = self.next_op()?;
+                let value = match self.next() {
+                    Some(Token::Str(value)) => value,
+                    _ => return Err(format!("Expected a quoted string after 'name {}'", op)),
+                };
+
+                match op {
+                    "==" => Ok(FilterExpr::NameEquals(value)),
+                    "!=" => Ok(FilterExpr::Not(Box::new(FilterExpr::NameEquals(value)))),
+                    "=~" | "!~" => {
+                        let regex = Regex::new(&value)
+                            .map_err(|e| format!("Invalid regex '{}': {}", value, e))?;
+                        let expr = FilterExpr::NameMatches(regex);
+                        if op == "!~" {
+                            Ok(FilterExpr::Not(Box::new(expr)))
+                        } else {
+                            Ok(expr)
+                        }
+                    }
+                    _ => Err(format!("Operator '{}' is not supported for name", op)),
+                }
+            }
+            "age" => {
+                let op = match self.next_op()? {
+                    "==" => CompareOp::Eq,
+                    "!=" => CompareOp::Ne,
+                    "<" => CompareOp::Lt,
+                    "<=" => CompareOp::Le,
+                    ">" => CompareOp::Gt,
+                    ">=" => CompareOp::Ge,
+                    op => return Err(format!("Operator '{}' is not supported for age", op)),
+                };
+                let duration = match self.next() {
+                    Some(Token::Ident(value)) => parse_duration(&value)?,
+                    _ => return Err("Expected a duration (e.g. 30d) after 'age'".to_string()),
+                };
+
+                Ok(FilterExpr::Age(op, duration))
+            }
+            _ => Err(format!(
+                "Unknown field '{}' in filter expression. Use name, age, or merged",
+                field
+            )),
+        }
+    }
+
+    fn next_op(&mut self) -> Result<&'static str, String> {
+        match self.next() {
+            Some(Token::Op(op)) => Ok(op),
+            _ => Err("Expected a comparison operator in filter expression".to_string()),
+        }
+    }
+}
+
+/// Whether a branch was last committed to within `since..=until`. Empty
+/// branches are always in range: their last commit date says when the
+/// commit they were created from was made, not how long they have sat.
+pub fn in_date_range(
+    branch: &BranchInfo,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> bool {
+    branch.is_empty
+        || (since.is_none_or(|since| branch.last_commit_date >= since)
+            && until.is_none_or(|until| branch.last_commit_date <= until))
+}
+
+/// Branch names from a list, one per line. Blank lines and `#` comments are
+/// skipped, and `git branch` markers (`* `, `+ `) and `refs/heads/` are
+/// stripped so its output can be piped in directly.
+pub fn parse_branch_list(input: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+
+    for line in input.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line
+            .strip_prefix("* ")
+            .or_else(|| line.strip_prefix("+ "))
+            .unwrap_or(line)
+            .trim();
+        let name = line.strip_prefix("refs/heads/").unwrap_or(line);
+
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    names
+}
+
+// Buckets branches by the part of their name up to and including the first
+// `/` (e.g. `feature/`), keeping each group in input order. Branches without
+// a prefix share the "" group, which sorts last.
+pub fn group_by_prefix<'a>(branches: &[&'a BranchInfo]) -> Vec<(String, Vec<&'a BranchInfo>)> {
+    group_branches(branches, |name| {
+        name.split_once('/')
+            .map(|(prefix, _)| format!("{}/", prefix))
+    })
+}
+
+// Buckets branches by the issue key in their name, like `group_by_prefix`.
+pub fn group_by_issue<'a>(
+    branches: &[&'a BranchInfo],
+    projects: &[String],
+) -> Vec<(String, Vec<&'a BranchInfo>)> {
+    group_branches(branches, |name| issue_key(name, projects))
+}
+
+fn group_branches<'a>(
+    branches: &[&'a BranchInfo],
+    key: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, Vec<&'a BranchInfo>)> {
+    let mut groups: BTreeMap<String, Vec<&BranchInfo>> = BTreeMap::new();
+
+    for branch in branches {
+        groups
+            .entry(key(&branch.name).unwrap_or_default())
+            .or_default()
+            .push(branch);
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    if groups.first().is_some_and(|(key, _)| key.is_empty()) {
+        groups.rotate_left(1);
+    }
+
+    groups
+}
+
+static ISSUE_KEY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[^A-Za-z0-9])([A-Za-z][A-Za-z0-9_]+)-([0-9]+)(?:$|[^0-9])").unwrap()
+});
+
+/// The first issue key (e.g. `ABC-123`) in a branch name, upper-cased. With
+/// `projects`, keys of those projects are found in any case and others are
+/// ignored; without, only keys already in upper case count, so names like
+/// `release-2024` are not mistaken for issues.
+pub fn issue_key(branch_name: &str, projects: &[String]) -> Option<String> {
+    ISSUE_KEY.captures_iter(branch_name).find_map(|captures| {
+        let project = &captures[1];
+        let is_project = if projects.is_empty() {
+            project.chars().all(|c| !c.is_ascii_lowercase())
+        } else {
+            projects.iter().any(|p| p.eq_ignore_ascii_case(project))
+        };
+
+        is_project.then(|| format!("{}-{}", project.to_ascii_uppercase(), &captures[2]))
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{Duration, TimeZone, Utc};
+    use chrono::{Duration, Utc};
 
     fn create_test_branch(name: &str, is_merged: bool, days_ago: i64) -> BranchInfo {
         BranchInfo {
             name: name.to_string(),
+            tip: git2::Oid::zero(),
             is_merged,
+            merged_at: None,
             last_commit_date: Utc::now() - Duration::days(days_ago),
-            is_remote: false,
+            last_commit_subject: String::new(),
+            last_commit_author: String::new(),
+            short_sha: String::new(),
+            remote: None,
+            redundant: None,
+            is_empty: false,
+            is_orphan: false,
+            owner: String::new(),
         }
     }
 
     #[test]
-    fn test_filter_by_age() {
-        let now = Utc::now();
-        let branches = vec![
-            create_test_branch("old-feature", true, 45),
-            create_test_branch("new-feature", true, 15),
-            create_test_branch("ancient-feature", true, 90),
+    fn test_group_by_prefix() {
+        let branches = [
+            create_test_branch("feature/b", true, 30),
+            create_test_branch("hotfix", true, 30),
+            create_test_branch("bugfix/login", true, 30),
+            create_test_branch("feature/a/nested", true, 30),
         ];
 
         let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_by_age(&branches_vec, Duration::days(30));
+        let groups = group_by_prefix(&branches_vec);
 
-        assert_eq!(filtered.len(), 2);
-        assert!(filtered.iter().any(|b| b.name == "old-feature"));
-        assert!(filtered.iter().any(|b| b.name == "ancient-feature"));
-        assert!(!filtered.iter().any(|b| b.name == "new-feature"));
+        let summary: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(prefix, branches)| {
+                (
+                    prefix.as_str(),
+                    branches.iter().map(|b| b.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("bugfix/", vec!["bugfix/login"]),
+                ("feature/", vec!["feature/b", "feature/a/nested"]),
+                ("", vec!["hotfix"]),
+            ]
+        );
     }
 
     #[test]
-    fn test_filter_by_age_exact_cutoff() {
-        let branches = vec![
-            create_test_branch("exactly-30-days", true, 30),
-            create_test_branch("31-days", true, 31),
-        ];
-
-        let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_by_age(&branches_vec, Duration::days(30));
+    fn test_issue_key() {
+        assert_eq!(
+            issue_key("feature/ABC-123-login", &[]),
+            Some("ABC-123".to_string())
+        );
+        assert_eq!(issue_key("PAY2-7", &[]), Some("PAY2-7".to_string()));
+        assert_eq!(issue_key("feature/abc-123-login", &[]), None);
+        assert_eq!(issue_key("release-2024", &[]), None);
+        assert_eq!(
+            issue_key("feature/ABC-123x", &[]),
+            Some("ABC-123".to_string())
+        );
+        assert_eq!(
+            issue_key("feature/ABC-12345678x9", &[]),
+            Some("ABC-12345678".to_string())
+        );
 
-        assert_eq!(filtered.len(), 2);
-        assert!(filtered.iter().any(|b| b.name == "31-days"));
-        assert!(filtered.iter().any(|b| b.name == "exactly-30-days"));
+        let projects = ["abc".to_string()];
+        assert_eq!(
+            issue_key("feature/abc-123-login", &projects),
+            Some("ABC-123".to_string())
+        );
+        assert_eq!(
+            issue_key("fix/OPS-9-then-abc-4", &projects),
+            Some("ABC-4".to_string())
+        );
+        assert_eq!(issue_key("fix/OPS-9", &projects), None);
     }
 
     #[test]
-    fn test_filter_out_protected() {
-        let branches = vec![
-            create_test_branch("master", true, 1),
-            create_test_branch("develop", true, 1),
-            create_test_branch("feature-1", true, 1),
-            create_test_branch("feature-2", true, 1),
+    fn test_group_by_issue() {
+        let branches = [
+            create_test_branch("feature/ABC-2-api", true, 30),
+            create_test_branch("spike", true, 30),
+            create_test_branch("ABC-1", true, 30),
+            create_test_branch("fix/ABC-2-tests", true, 30),
         ];
 
-        let protected = vec!["master".to_string(), "develop".to_string()];
-        let current_branch = Some("feature-1");
-
         let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_out_protected(&branches_vec, &protected, current_branch);
-
-        assert_eq!(filtered.len(), 1);
-        assert_eq!(filtered[0].name, "feature-2");
+        let groups: Vec<(String, usize)> = group_by_issue(&branches_vec, &[])
+            .into_iter()
+            .map(|(key, branches)| (key, branches.len()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("ABC-1".to_string(), 1),
+                ("ABC-2".to_string(), 2),
+                (String::new(), 1),
+            ]
+        );
     }
 
     #[test]
-    fn test_filter_out_protected_current_branch() {
-        let branches = vec![
-            create_test_branch("master", true, 1),
-            create_test_branch("feature-1", true, 1),
-            create_test_branch("feature-2", true, 1),
-        ];
+    fn test_parse_filter_expr() {
+        let now = Utc::now();
+        let expr = parse_filter_expr(r#"merged && age > 30d && name =~ "^feature/""#).unwrap();
 
-        let protected = vec!["master".to_string()];
-        let current_branch = Some("feature-1");
+        assert!(expr.matches(&create_test_branch("feature/auth", true, 45), now));
+        assert!(!expr.matches(&create_test_branch("feature/auth", false, 45), now));
+        assert!(!expr.matches(&create_test_branch("feature/auth", true, 10), now));
+        assert!(!expr.matches(&create_test_branch("bugfix/login", true, 45), now));
+    }
 
-        let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_out_protected(&branches_vec, &protected, current_branch);
+    #[test]
+    fn test_parse_filter_expr_precedence() {
+        let now = Utc::now();
+        let expr =
+            parse_filter_expr(r#"!merged || age >= 2w && (name == "a" || name != "b")"#).unwrap();
 
-        assert_eq!(filtered.len(), 1);
-        assert_eq!(filtered[0].name, "feature-2");
+        assert!(expr.matches(&create_test_branch("b", false, 1), now));
+        assert!(expr.matches(&create_test_branch("a", true, 20), now));
+        assert!(!expr.matches(&create_test_branch("b", true, 20), now));
+        assert!(!expr.matches(&create_test_branch("a", true, 1), now));
     }
 
     #[test]
-    fn test_filter_by_merge_status() {
-        let branches = vec![
-            create_test_branch("merged-feature", true, 30),
-            create_test_branch("unmerged-feature", false, 30),
-            create_test_branch("another-merged", true, 30),
-        ];
+    fn test_parse_filter_expr_invalid() {
+        assert!(parse_filter_expr("").is_err());
+        assert!(parse_filter_expr("merged &&").is_err());
+        assert!(parse_filter_expr("author == \"x\"").is_err());
+        assert!(parse_filter_expr("age > soon").is_err());
+        assert!(parse_filter_expr("name =~ \"(unclosed\"").is_err());
+        assert!(parse_filter_expr("name = \"x\"").is_err());
+        assert!(parse_filter_expr("(merged").is_err());
+        assert!(parse_filter_expr("name == \"unterminated").is_err());
+    }
 
-        let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_by_merge_status(&branches_vec, true);
+    #[test]
+    fn test_parse_branch_list() {
+        let input = "feature/a\n\n# stale spikes\n  spike/x  \n* main\n+ review\nrefs/heads/fix/b\nfeature/a\n";
 
-        assert_eq!(filtered.len(), 2);
-        assert!(filtered.iter().all(|b| b.is_merged));
+        assert_eq!(
+            parse_branch_list(input),
+            vec!["feature/a", "spike/x", "main", "review", "fix/b"]
+        );
     }
 
     #[test]
-    fn test_filter_by_pattern() {
-        let branches = vec![
-            create_test_branch("feature/auth", true, 30),
-            create_test_branch("feature/api", true, 30),
-            create_test_branch("bugfix/login", true, 30),
-        ];
+    fn test_in_date_range() {
+        let now = Utc::now();
+        let old = create_test_branch("old", true, 400);
+        let mid = create_test_branch("mid", true, 250);
+        let recent = create_test_branch("recent", true, 30);
+        let since = Some(now - Duration::days(365));
+        let until = Some(now - Duration::days(180));
 
-        let pattern = Regex::new(r"^feature/").unwrap();
-        let branches_vec: Vec<_> = branches.iter().collect();
-        let filtered = filter_by_pattern(&branches_vec, &pattern);
+        assert!(!in_date_range(&old, since, until));
+        assert!(in_date_range(&mid, since, until));
+        assert!(!in_date_range(&recent, since, until));
+        assert!(in_date_range(&recent, since, None));
+        assert!(in_date_range(&old, None, until));
 
-        assert_eq!(filtered.len(), 2);
-        assert!(filtered.iter().all(|b| b.name.starts_with("feature/")));
+        let mut empty = recent.clone();
+        empty.is_empty = true;
+        assert!(in_date_range(&empty, since, until));
     }
 }
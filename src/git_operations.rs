@@ -1,167 +1,3182 @@
-use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
-use git2::{BranchType, Repository};
+use git2::{
+    BranchType, Direction, ErrorCode, FetchOptions, FetchPrune, Oid, PushOptions, Repository,
+    RepositoryState, Sort, StatusOptions, WorktreeLockStatus, WorktreePruneOptions,
+};
+use glob::Pattern;
+use log::{debug, trace};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-use crate::config::Config;
+use crate::config::{Config, CredentialsConfig};
+use crate::credentials::remote_callbacks;
+use crate::error::{GitTidyError, Result};
+#[cfg(feature = "cli")]
+use crate::progress::progress_bar;
 
 #[derive(Clone)]
 pub struct BranchInfo {
     pub name: String,
+    /// The commit the branch pointed at when listed, so a deletion can
+    /// refuse a branch that moved after the plan was shown.
+    pub tip: Oid,
     pub is_merged: bool,
+    /// When a merged branch landed on a merge target, filled in by
+    /// [`apply_merge_dates`] for --older-than-merge.
+    pub merged_at: Option<DateTime<Utc>>,
     pub last_commit_date: DateTime<Utc>,
-    #[allow(dead_code)]
-    pub is_remote: bool,
+    /// The first line of the tip commit's message.
+    pub last_commit_subject: String,
+    /// The tip commit's author name.
+    pub last_commit_author: String,
+    /// The tip abbreviated as `git log --oneline` would, e.g. `a1b2c3d`.
+    pub short_sha: String,
+    /// For a remote-tracking branch listed by --remote-only, its remote; the
+    /// name is then the branch's name on that remote.
+    pub remote: Option<String>,
+    /// Set when the branch's tip is the same commit as a merge target or
+    /// another branch, so deleting it loses no work.
+    pub redundant: Option<Redundancy>,
+    /// The tip is on a merge target's first-parent history: the branch was
+    /// created but never committed to, so its last commit date is that of
+    /// the commit it was created from.
+    pub is_empty: bool,
+    /// The branch shares no history with any merge target, e.g. an imported
+    /// project or a `gh-pages` branch.
+    pub is_orphan: bool,
+    /// The email of whoever the branch belongs to: its last committer, or
+    /// the config's `owners` entry for it.
+    pub owner: String,
 }
 
-pub fn list_branches(repo: &Repository) -> Result<Vec<BranchInfo>> {
-    let mut branches = Vec::new();
+impl BranchInfo {
+    /// Merged or redundant: deleting the branch loses no commits.
+    pub fn has_no_unique_work(&self) -> bool {
+        self.is_merged || self.redundant.is_some()
+    }
 
-    for branch_type in [BranchType::Local] {
-        let branch_names = repo.branches(Some(branch_type))?;
+    /// When the branch was merged, or its last commit date for branches
+    /// without a merge date (unmerged, redundant, or not yet dated).
+    pub fn merge_date(&self) -> DateTime<Utc> {
+        self.merged_at.unwrap_or(self.last_commit_date)
+    }
+}
 
-        for branch in branch_names {
-            let (branch_obj, _branch_type) = branch?;
-            let name = branch_obj.name()?.unwrap_or("unknown").to_string();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redundancy {
+    MergeTarget,
+    Branch(String),
+}
 
-            let commit = branch_obj.get().peel_to_commit()?;
-            let time = commit.time();
-            let last_commit_date = Utc.timestamp_opt(time.seconds(), 0).unwrap();
+impl fmt::Display for Redundancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redundancy::MergeTarget => write!(f, "same commit as the merge target"),
+            Redundancy::Branch(name) => write!(f, "same commit as {}", name),
+        }
+    }
+}
 
-            let is_merged = is_branch_merged(repo, &name)?;
+/// Where a branch is checked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checkout {
+    /// The main worktree, or HEAD of a bare repository.
+    Main,
+    /// A linked worktree, by name.
+    Worktree(String),
+}
 
-            branches.push(BranchInfo {
-                name,
-                is_merged,
-                last_commit_date,
-                is_remote: branch_type == BranchType::Remote,
-            });
+impl fmt::Display for Checkout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Checkout::Main => write!(f, "the main worktree"),
+            Checkout::Worktree(name) => write!(f, "worktree {}", name),
         }
     }
+}
+
+/// A branch some worktree's HEAD points at, which git refuses to delete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckedOutBranch {
+    pub branch: String,
+    pub checkout: Checkout,
+    /// The checkout git-tidy is running in.
+    pub is_current: bool,
+}
+
+/// How much work deleting a branch would discard: its commits not on any
+/// merge target, and its diff against the merge base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchSize {
+    pub unique_commits: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl fmt::Display for BranchSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} commit{}, +{} -{}",
+            self.unique_commits,
+            if self.unique_commits == 1 { "" } else { "s" },
+            self.insertions,
+            self.deletions
+        )
+    }
+}
+
+/// Objects only the deleted branches reached, which `git gc` can drop once
+/// the reflogs that still mention them expire.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reclaimable {
+    pub objects: usize,
+    /// Uncompressed object sizes, so an upper bound on what the pack shrinks by.
+    pub bytes: u64,
+}
+
+impl fmt::Display for Reclaimable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} object{} ({})",
+            self.objects,
+            if self.objects == 1 { "" } else { "s" },
+            format_bytes(self.bytes)
+        )
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub struct BranchPreview {
+    pub ahead: usize,
+    pub recent_subjects: Vec<String>,
+}
+
+pub struct WorktreeInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub branch: Option<String>,
+    pub is_missing: bool,
+    pub is_locked: bool,
+    pub is_dirty: bool,
+}
+
+/// A ref left behind by a history rewrite: a `git filter-branch` backup
+/// under refs/original/, or a `git replace` or filter-repo replacement under
+/// refs/replace/.
+#[derive(Debug, Clone)]
+pub struct LeftoverRef {
+    pub name: String,
+    /// When the ref was written: its newest reflog entry, else the loose
+    /// ref file's modification time, else the commit it points to.
+    pub date: DateTime<Utc>,
+}
+
+// Where history rewrites leave refs behind.
+const LEFTOVER_REF_PREFIXES: [&str; 2] = ["refs/original/", "refs/replace/"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteBranch {
+    pub remote: String,
+    pub branch: String,
+    /// Where the remote-tracking ref pointed when planned; the deletion is
+    /// only pushed if the remote still has the branch there.
+    pub tip: Oid,
+}
+
+impl fmt::Display for RemoteBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.remote, self.branch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamStatus {
+    None,
+    Tracking,
+    Gone,
+}
+
+pub fn list_branches(repo: &Repository, merge_targets: &[Oid]) -> Result<Vec<BranchInfo>> {
+    list_refs(repo, None, merge_targets)
+}
+
+/// The remote's branches as its remote-tracking refs last saw them, named
+/// without the remote, whether or not a local branch of that name exists.
+pub fn list_remote_branches(
+    repo: &Repository,
+    remote: &str,
+    merge_targets: &[Oid],
+) -> Result<Vec<BranchInfo>> {
+    list_refs(repo, Some(remote), merge_targets)
+}
+
+fn list_refs(
+    repo: &Repository,
+    remote: Option<&str>,
+    merge_targets: &[Oid],
+) -> Result<Vec<BranchInfo>> {
+    let mut branches = Vec::new();
+    scan_branches(repo, remote, merge_targets, &mut |branch| {
+        branches.push(branch);
+        Ok(())
+    })?;
 
-    branches.sort_by(|a, b| b.last_commit_date.cmp(&a.last_commit_date));
+    match remote {
+        Some(remote) => debug!("Listed {} branches on {}", branches.len(), remote),
+        None => debug!("Listed {} local branches", branches.len()),
+    }
+    branches.sort_by_key(|b| std::cmp::Reverse(b.last_commit_date));
 
     Ok(branches)
 }
 
-pub fn safe_delete_branch(
-    repo: &mut git2::Repository,
-    branch_name: &str,
-    config: &Config,
-    current_branch: Option<&str>,
-    force: bool,
+/// Hands each local branch, or each of `remote`'s branches, to `on_branch`
+/// as soon as it is analyzed, in name order rather than by age.
+pub fn scan_branches(
+    repo: &Repository,
+    remote: Option<&str>,
+    merge_targets: &[Oid],
+    on_branch: &mut dyn FnMut(BranchInfo) -> Result<()>,
 ) -> Result<()> {
-    if current_branch == Some(branch_name) {
-        anyhow::bail!(
-            "Cannot delete current branch '{}'. Switch to another branch first.",
-            branch_name
-        );
+    let prefix = ref_prefix(remote);
+
+    // A single pass over the refs reads packed-refs once instead of
+    // resolving each branch (and the merge target) by name.
+    let mut references = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", prefix))? {
+        let reference = reference?;
+        let name = reference
+            .name()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .unwrap_or("unknown")
+            .to_string();
+        // refs/remotes/<remote>/HEAD names the remote's default branch.
+        if remote.is_some() && name == "HEAD" {
+            continue;
+        }
+        let tip = match reference.target() {
+            Some(oid) => oid,
+            None => reference.peel_to_commit()?.id(),
+        };
+        references.push((name, tip));
     }
+    let mainline = first_parent_history(repo, merge_targets)?;
+    let merged_commits = reachable_commits(repo, merge_targets)?;
+    // A shallow clone's cut-off history shares no merge base with anything,
+    // so sharing none there does not make a branch an orphan.
+    let shallow = repo.is_shallow();
 
-    if config
-        .get_protected_branches()
-        .iter()
-        .any(|b| b == branch_name)
-    {
-        anyhow::bail!(
-            "Branch '{}' is protected and cannot be deleted. Update your config if you want to delete it.",
-            branch_name
-        );
+    // Of several branches at the same commit the first by name keeps the
+    // work, so marking the rest redundant never loses it.
+    let mut first_at_tip: HashMap<Oid, &str> = HashMap::new();
+    for (name, tip) in &references {
+        let first = first_at_tip.entry(*tip).or_insert(name);
+        if name.as_str() < *first {
+            *first = name;
+        }
     }
 
-    if config.is_protected(branch_name) {
-        anyhow::bail!(
-            "Branch '{}' is protected by a glob pattern and cannot be deleted. Update your config if you want to delete it.",
-            branch_name
+    #[cfg(feature = "cli")]
+    let progress = progress_bar(references.len(), "Analyzing branches");
+
+    for (name, tip) in &references {
+        #[cfg(feature = "cli")]
+        progress.inc(1);
+
+        let commit = repo.find_commit(*tip)?;
+        let time = commit.time();
+        let last_commit_date = Utc.timestamp_opt(time.seconds(), 0).unwrap();
+        let owner = commit.committer().email().unwrap_or_default().to_string();
+        let last_commit_subject = commit.summary().unwrap_or_default().to_string();
+        let last_commit_author = commit.author().name().unwrap_or_default().to_string();
+        let short_sha = commit
+            .as_object()
+            .short_id()
+            .ok()
+            .and_then(|id| id.as_str().map(str::to_string))
+            .unwrap_or_else(|| tip.to_string()[..7].to_string());
+
+        let is_merged = merged_commits.contains(tip);
+        let is_empty = mainline.contains(tip);
+        let is_orphan = !is_merged
+            && !shallow
+            && !merge_targets.is_empty()
+            && merge_targets.iter().all(|target| {
+                matches!(repo.merge_base(*target, *tip), Err(e) if e.code() == ErrorCode::NotFound)
+            });
+        trace!(
+            "{}: tip {}, merged: {}, empty: {}, orphan: {}",
+            name, tip, is_merged, is_empty, is_orphan
         );
+
+        let redundant = if merge_targets.contains(tip) {
+            Some(Redundancy::MergeTarget)
+        } else {
+            first_at_tip
+                .get(tip)
+                .filter(|first| *first != name)
+                .map(|first| Redundancy::Branch(first.to_string()))
+        };
+        if let Some(redundant) = &redundant {
+            trace!("{}: {}", name, redundant);
+        }
+
+        on_branch(BranchInfo {
+            name: name.clone(),
+            tip: *tip,
+            is_merged,
+            merged_at: None,
+            last_commit_date,
+            last_commit_subject,
+            last_commit_author,
+            short_sha,
+            remote: remote.map(str::to_string),
+            redundant,
+            is_empty,
+            is_orphan,
+            owner,
+        })?;
     }
 
-    if !is_branch_merged(repo, branch_name)? {
-        anyhow::bail!(
-            "Branch '{}' is not merged. Refusing to delete unmerged branch. Use 'git branch -D {}' if you really want to delete it.",
-            branch_name,
-            branch_name
-        );
+    #[cfg(feature = "cli")]
+    progress.finish_and_clear();
+
+    Ok(())
+}
+
+/// The names of the local branches, or of `remote`'s branches, without
+/// analyzing them.
+pub fn list_branch_names(repo: &Repository, remote: Option<&str>) -> Result<Vec<String>> {
+    let prefix = ref_prefix(remote);
+    let mut names = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", prefix))? {
+        let reference = reference?;
+        if let Some(name) = reference.name().and_then(|n| n.strip_prefix(&prefix))
+            && !(remote.is_some() && name == "HEAD")
+        {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Each remote's default branch, as its `refs/remotes/<remote>/HEAD` names
+/// it (set by `git clone` or `git remote set-head`), as (remote, branch).
+pub fn remote_default_branches(repo: &Repository) -> Result<Vec<(String, String)>> {
+    let mut defaults = Vec::new();
+    for remote in repo.remotes()?.iter().flatten() {
+        let prefix = ref_prefix(Some(remote));
+        let Ok(head) = repo.find_reference(&format!("{}HEAD", prefix)) else {
+            continue;
+        };
+        if let Some(branch) = head
+            .symbolic_target()
+            .and_then(|target| target.strip_prefix(&prefix))
+        {
+            defaults.push((remote.to_string(), branch.to_string()));
+        }
+    }
+    Ok(defaults)
+}
+
+fn ref_prefix(remote: Option<&str>) -> String {
+    match remote {
+        Some(remote) => format!("refs/remotes/{}/", remote),
+        None => "refs/heads/".to_string(),
+    }
+}
+
+/// Dates each branch by the last time its ref moved, per its reflog, when
+/// that is more recent than its tip's commit date. A branch rebased or reset
+/// yesterday onto old commits then counts as active yesterday. Branches
+/// without a reflog (e.g. expired entries) keep their commit date.
+pub fn apply_reflog_dates(repo: &Repository, branches: &mut [BranchInfo]) -> Result<()> {
+    for branch in branches.iter_mut() {
+        let reflog = repo.reflog(&format!("refs/heads/{}", branch.name))?;
+        let Some(newest) = reflog.get(0) else {
+            continue;
+        };
+
+        let moved = newest.committer().when();
+        if let Some(moved_at) = Utc.timestamp_opt(moved.seconds(), 0).single()
+            && moved_at > branch.last_commit_date
+        {
+            trace!("{}: ref moved at {}", branch.name, moved_at);
+            branch.last_commit_date = moved_at;
+        }
+    }
+
+    branches.sort_by_key(|b| std::cmp::Reverse(b.last_commit_date));
+
+    Ok(())
+}
+
+/// Dates each merged branch by when it landed on a merge target: the
+/// commit time of the oldest commit on the target's first-parent history
+/// that contains the branch's tip. That is the merge commit of a merged pull
+/// request, or the tip itself after a fast-forward. With several targets the
+/// earliest landing counts.
+pub fn apply_merge_dates(
+    repo: &Repository,
+    branches: &mut [BranchInfo],
+    merge_targets: &[Oid],
+) -> Result<()> {
+    let merge_dates = MergeDates::new(repo, merge_targets)?;
+    for branch in branches.iter_mut().filter(|b| b.is_merged) {
+        branch.merged_at = merge_dates.merged_at(repo, branch.tip)?;
+        trace!("{}: merged at {:?}", branch.name, branch.merged_at);
     }
 
-    if !force {
-        confirm_deletion(branch_name)?;
+    Ok(())
+}
+
+/// The merge targets' first-parent histories, walked once to date any
+/// number of merged branches as [`apply_merge_dates`] does.
+pub struct MergeDates {
+    mainlines: Vec<Vec<Oid>>,
+}
+
+impl MergeDates {
+    pub fn new(repo: &Repository, merge_targets: &[Oid]) -> Result<Self> {
+        let mut mainlines = Vec::new();
+        for target in merge_targets {
+            let mut revwalk = repo.revwalk()?;
+            revwalk.simplify_first_parent()?;
+            revwalk.push(*target)?;
+            mainlines.push(revwalk.collect::<Result<Vec<Oid>, _>>()?);
+        }
+        Ok(MergeDates { mainlines })
+    }
+
+    /// When `tip` landed on a merge target, if it did.
+    pub fn merged_at(&self, repo: &Repository, tip: Oid) -> Result<Option<DateTime<Utc>>> {
+        let mut landed: Option<DateTime<Utc>> = None;
+
+        // Newest first, the commits containing the tip come before those
+        // that do not.
+        for mainline in &self.mainlines {
+            let contained = mainline.partition_point(|commit| {
+                *commit == tip || repo.graph_descendant_of(*commit, tip).unwrap_or(false)
+            });
+            let Some(merge) = contained.checked_sub(1).map(|i| mainline[i]) else {
+                continue;
+            };
+            let time = repo.find_commit(merge)?.time();
+            if let Some(merged_at) = Utc.timestamp_opt(time.seconds(), 0).single()
+                && landed.is_none_or(|landed| merged_at < landed)
+            {
+                landed = Some(merged_at);
+            }
+        }
+
+        Ok(landed)
+    }
+}
+
+/// Deletes a branch that a plan already found removable, by its full ref
+/// name, e.g. `refs/heads/feature`, refusing only if it moved away from
+/// `planned_tip` since. Protections and merge status are not checked again.
+pub fn delete_planned_branch(repo: &Repository, reference: &str, planned_tip: Oid) -> Result<()> {
+    delete_unmoved_branch(repo, reference, Some(planned_tip))
+}
+
+/// Like `delete_planned_branch`, moving the branch to `archive_ref`.
+pub fn archive_planned_branch(
+    repo: &Repository,
+    reference: &str,
+    planned_tip: Oid,
+    archive_ref: &str,
+) -> Result<()> {
+    archive_unmoved_branch(repo, reference, Some(planned_tip), archive_ref)
+}
+
+// Checked last, after any prompt, so work committed or pushed in while the
+// user was reading the plan is never deleted.
+fn find_unmoved_branch<'r>(
+    repo: &'r Repository,
+    reference: &str,
+    planned_tip: Option<Oid>,
+) -> Result<(git2::Branch<'r>, Oid)> {
+    let branch = git2::Branch::wrap(repo.find_reference(reference)?);
+    let tip = branch.get().peel_to_commit()?.id();
+    if let Some(planned) = planned_tip
+        && planned != tip
+    {
+        return Err(GitTidyError::TipMoved {
+            branch: short_name(reference).to_string(),
+            planned,
+            actual: tip,
+        });
     }
 
-    let mut branch = repo.find_branch(branch_name, BranchType::Local)?;
+    Ok((branch, tip))
+}
+
+fn short_name(reference: &str) -> &str {
+    reference.strip_prefix("refs/heads/").unwrap_or(reference)
+}
+
+fn delete_unmoved_branch(
+    repo: &Repository,
+    reference: &str,
+    planned_tip: Option<Oid>,
+) -> Result<()> {
+    let (mut branch, tip) = find_unmoved_branch(repo, reference, planned_tip)?;
+    let branch_name = short_name(reference);
+
+    debug!("Deleting {} at {}", branch_name, tip);
     branch.delete()?;
 
     Ok(())
 }
 
-#[allow(dead_code)]
-pub fn delete_branch(repo: &mut git2::Repository, branch_name: &str) -> Result<()> {
-    let mut branch = repo.find_branch(branch_name, BranchType::Local)?;
+fn archive_unmoved_branch(
+    repo: &Repository,
+    reference: &str,
+    planned_tip: Option<Oid>,
+    archive_ref: &str,
+) -> Result<()> {
+    let (mut branch, tip) = find_unmoved_branch(repo, reference, planned_tip)?;
+    let branch_name = short_name(reference);
+
+    if repo.find_reference(archive_ref).is_ok() {
+        return Err(GitTidyError::ArchiveExists {
+            branch: branch_name.to_string(),
+            reference: archive_ref.to_string(),
+        });
+    }
+    ensure_no_ref_in_the_way(repo, archive_ref, None)?;
+
+    debug!("Archiving {} at {} to {}", branch_name, tip, archive_ref);
+    repo.reference(
+        archive_ref,
+        tip,
+        false,
+        &format!("git-tidy: archive {}", branch_name),
+    )?;
     branch.delete()?;
+
     Ok(())
 }
 
-pub fn get_current_branch(repo: &Repository) -> Result<Option<String>> {
-    let head = repo.head()?;
+/// A branch moved aside by --archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedBranch {
+    pub name: String,
+    pub reference: String,
+    pub tip: Oid,
+}
 
-    if head.is_branch() {
-        let branch_name = head.shorthand().map(|s| s.to_string());
-        Ok(branch_name)
-    } else {
-        Ok(None)
+/// Every ref in the archive namespace, sorted by name and then by ref, so
+/// the last of several archives of one name is the newest `{year}`.
+pub fn list_archived(repo: &Repository, config: &Config) -> Result<Vec<ArchivedBranch>> {
+    let mut archived = Vec::new();
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        let Some(full_name) = reference.name() else {
+            continue;
+        };
+        if let Some(name) = config.archived_name(full_name)
+            && let Ok(commit) = reference.peel_to_commit()
+        {
+            archived.push(ArchivedBranch {
+                name,
+                reference: full_name.to_string(),
+                tip: commit.id(),
+            });
+        }
+    }
+
+    archived.sort_by(|a, b| (&a.name, &a.reference).cmp(&(&b.name, &b.reference)));
+    Ok(archived)
+}
+
+/// Recreates an archived branch at its archived commit and removes the
+/// archive ref.
+pub fn unarchive_branch(repo: &Repository, archived: &ArchivedBranch) -> Result<()> {
+    if repo.find_branch(&archived.name, BranchType::Local).is_ok() {
+        return Err(GitTidyError::BranchExists(archived.name.clone()));
+    }
+
+    ensure_no_ref_in_the_way(repo, &format!("refs/heads/{}", archived.name), None)?;
+
+    let commit = repo.find_commit(archived.tip)?;
+    repo.branch(&archived.name, &commit, false)?;
+    repo.find_reference(&archived.reference)?.delete()?;
+    debug!("Restored {} from {}", archived.name, archived.reference);
+
+    Ok(())
+}
+
+/// Renames a local branch like `git branch -m`, taking its upstream and
+/// other `branch.<name>.*` settings and its reflog along.
+pub fn rename_branch(repo: &Repository, from: &str, to: &str) -> Result<()> {
+    if repo.find_branch(to, BranchType::Local).is_ok() {
+        return Err(GitTidyError::BranchExists(to.to_string()));
+    }
+
+    // Renaming feature to feature/login moves the ref out of its own way.
+    let from_ref = format!("refs/heads/{}", from);
+    ensure_no_ref_in_the_way(repo, &format!("refs/heads/{}", to), Some(&from_ref))?;
+
+    repo.find_branch(from, BranchType::Local)?
+        .rename(to, false)?;
+    debug!("Renamed {} to {}", from, to);
+
+    Ok(())
+}
+
+/// Whether two refs cannot both exist because one would be a folder holding
+/// the other, like `feature` and `feature/login`.
+pub fn refs_conflict(a: &str, b: &str) -> bool {
+    let holds = |folder: &str, name: &str| {
+        name.strip_prefix(folder)
+            .is_some_and(|rest| rest.starts_with('/'))
+    };
+    holds(a, b) || holds(b, a)
+}
+
+// git2 reports a ref that is in the way as a failure to remove or lock a
+// directory; this names the ref instead.
+fn ensure_no_ref_in_the_way(
+    repo: &Repository,
+    reference: &str,
+    moving: Option<&str>,
+) -> Result<()> {
+    let in_the_way = |existing: &str| -> Result<()> {
+        if moving == Some(existing) {
+            return Ok(());
+        }
+        Err(GitTidyError::RefConflict {
+            reference: reference.to_string(),
+            existing: existing.to_string(),
+        })
+    };
+
+    let mut folder = reference;
+    while let Some((parent, _)) = folder.rsplit_once('/') {
+        if repo.find_reference(parent).is_ok() {
+            in_the_way(parent)?;
+        }
+        folder = parent;
     }
+    for existing in repo.references_glob(&format!("{}/*", reference))?.names() {
+        in_the_way(existing?)?;
+    }
+
+    Ok(())
+}
+
+/// Branches checked out in the main worktree (or as a bare repository's
+/// HEAD) and in every linked worktree, including ones whose directory is
+/// gone but which have not been pruned. Unborn branches are included.
+pub fn get_checked_out_branches(repo: &Repository) -> Result<Vec<CheckedOutBranch>> {
+    let common_dir = common_dir(repo)?;
+    // A linked worktree's admin directory is named after the worktree.
+    let current_worktree = repo
+        .is_worktree()
+        .then(|| repo.path().file_name())
+        .flatten()
+        .map(|name| name.to_string_lossy().to_string());
+
+    let mut checked_out = Vec::new();
+
+    if let Some(branch) = read_head_branch(&common_dir.join("HEAD")) {
+        checked_out.push(CheckedOutBranch {
+            branch,
+            checkout: Checkout::Main,
+            is_current: current_worktree.is_none(),
+        });
+    }
+
+    for name in repo.worktrees()?.iter().flatten() {
+        let head_path = common_dir.join("worktrees").join(name).join("HEAD");
+        if let Some(branch) = read_head_branch(&head_path) {
+            checked_out.push(CheckedOutBranch {
+                branch,
+                checkout: Checkout::Worktree(name.to_string()),
+                is_current: current_worktree.as_deref() == Some(name),
+            });
+        }
+    }
+
+    Ok(checked_out)
+}
+
+// HEAD files are read directly so unborn branches, and worktrees whose
+// directory has been deleted, are still seen.
+fn read_head_branch(head_path: &std::path::Path) -> Option<String> {
+    std::fs::read_to_string(head_path).ok().and_then(|head| {
+        head.trim()
+            .strip_prefix("ref: refs/heads/")
+            .map(|b| b.to_string())
+    })
+}
+
+pub fn get_detached_head(repo: &Repository) -> Result<Option<String>> {
+    if !repo.head_detached()? {
+        return Ok(None);
+    }
+
+    let commit = repo.head()?.peel_to_commit()?;
+    let short_id = commit.as_object().short_id()?;
+
+    Ok(short_id.as_str().map(|s| s.to_string()))
 }
 
-fn is_branch_merged(repo: &Repository, branch_name: &str) -> Result<bool> {
+pub fn get_branch_author(repo: &Repository, branch_name: &str) -> Result<String> {
     let branch = repo.find_branch(branch_name, BranchType::Local)?;
-    let branch_commit = branch.get().peel_to_commit()?;
+    let commit = branch.get().peel_to_commit()?;
+
+    Ok(commit.author().name().unwrap_or("unknown").to_string())
+}
+
+pub fn get_branch_description(repo: &Repository, branch_name: &str) -> Result<Option<String>> {
+    match repo
+        .config()?
+        .get_string(&format!("branch.{}.description", branch_name))
+    {
+        Ok(description) => Ok(Some(description)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn has_description_marker(repo: &Repository, branch_name: &str, marker: &str) -> Result<bool> {
+    Ok(get_branch_description(repo, branch_name)?.is_some_and(|d| d.contains(marker)))
+}
 
-    if let Ok(main) = repo.find_branch("main", BranchType::Local) {
-        let main_commit = main.get().peel_to_commit()?;
+pub fn get_upstream_status(repo: &Repository, branch_name: &str) -> Result<UpstreamStatus> {
+    let upstream_name = match repo.branch_upstream_name(&format!("refs/heads/{}", branch_name)) {
+        Ok(name) => name,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(UpstreamStatus::None),
+        Err(e) => return Err(e.into()),
+    };
 
-        return Ok(repo
-            .graph_descendant_of(branch_commit.id(), main_commit.id())
-            .unwrap_or(false));
+    let upstream_name = upstream_name
+        .as_str()
+        .ok_or_else(|| GitTidyError::InvalidUpstream(branch_name.to_string()))?;
+
+    if repo.find_reference(upstream_name).is_ok() {
+        Ok(UpstreamStatus::Tracking)
+    } else {
+        Ok(UpstreamStatus::Gone)
     }
+}
 
-    if let Ok(master) = repo.find_branch("master", BranchType::Local) {
-        let master_commit = master.get().peel_to_commit()?;
+// The branch's upstream when it is tracking one, otherwise a branch of the
+// same name on origin. Only branches with an existing remote-tracking ref are
+// returned, so nothing is pushed for branches that were never published.
+pub fn get_remote_branch(repo: &Repository, branch_name: &str) -> Result<Option<RemoteBranch>> {
+    let refname = format!("refs/heads/{}", branch_name);
 
-        return Ok(repo
-            .graph_descendant_of(branch_commit.id(), master_commit.id())
-            .unwrap_or(false));
+    if let (Ok(remote), Ok(upstream)) = (
+        repo.branch_upstream_remote(&refname),
+        repo.branch_upstream_name(&refname),
+    ) && let (Some(remote), Some(upstream)) = (remote.as_str(), upstream.as_str())
+        && let Some(branch) = upstream.strip_prefix(&format!("refs/remotes/{}/", remote))
+        && let Some(remote_branch) = tracked_remote_branch(repo, remote, branch)
+    {
+        return Ok(Some(remote_branch));
     }
 
-    Ok(false)
+    Ok(tracked_remote_branch(repo, "origin", branch_name))
 }
 
-fn confirm_deletion(branch_name: &str) -> Result<bool> {
-    println!("Delete branch '{}'? [y/N]: ", branch_name);
+/// The branch's counterparts on `remotes`: its upstream where that is on one
+/// of them, otherwise a branch of the same name. With no remotes given, the
+/// one from [`get_remote_branch`]. Only counterparts at `tip`, or merged
+/// into one of `merge_targets`, are returned, so a same-named branch with
+/// someone else's work on it is left alone.
+pub fn get_remote_branches(
+    repo: &Repository,
+    branch_name: &str,
+    tip: Oid,
+    remotes: &[String],
+    merge_targets: &[Oid],
+) -> Result<Vec<RemoteBranch>> {
+    let refname = format!("refs/heads/{}", branch_name);
+    let upstream = match (
+        repo.branch_upstream_remote(&refname),
+        repo.branch_upstream_name(&refname),
+    ) {
+        (Ok(remote), Ok(upstream)) => {
+            remote
+                .as_str()
+                .zip(upstream.as_str())
+                .and_then(|(remote, upstream)| {
+                    let branch = upstream.strip_prefix(&format!("refs/remotes/{}/", remote))?;
+                    Some((remote.to_string(), branch.to_string()))
+                })
+        }
+        _ => None,
+    };
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
+    let mut remote_branches = Vec::new();
+    if remotes.is_empty() {
+        remote_branches.extend(get_remote_branch(repo, branch_name)?);
+    }
+    for remote in remotes {
+        let branch = match &upstream {
+            Some((upstream_remote, branch)) if upstream_remote == remote => branch.as_str(),
+            _ => branch_name,
+        };
+        remote_branches.extend(tracked_remote_branch(repo, remote, branch));
+    }
 
-    if input.trim().to_lowercase() != "y" {
-        anyhow::bail!("Deletion cancelled by user");
+    let mut safe = Vec::new();
+    for remote_branch in remote_branches {
+        let merged = merge_targets.iter().any(|target| {
+            *target == remote_branch.tip
+                || repo
+                    .graph_descendant_of(*target, remote_branch.tip)
+                    .unwrap_or(false)
+        });
+        if remote_branch.tip == tip || merged {
+            safe.push(remote_branch);
+        } else {
+            debug!(
+                "{}: {} has diverged and is not merged, keeping it",
+                branch_name, remote_branch
+            );
+        }
     }
 
-    Ok(true)
+    Ok(safe)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn tracked_remote_branch(repo: &Repository, remote: &str, branch: &str) -> Option<RemoteBranch> {
+    let tip = repo
+        .find_reference(&format!("refs/remotes/{}/{}", remote, branch))
+        .ok()?
+        .target()?;
 
-    #[test]
-    fn test_branch_info_clone() {
-        let branch = BranchInfo {
-            name: "test-branch".to_string(),
-            is_merged: true,
-            last_commit_date: Utc::now(),
-            is_remote: false,
+    Some(RemoteBranch {
+        remote: remote.to_string(),
+        branch: branch.to_string(),
+        tip,
+    })
+}
+
+/// Deletes the remote branches with one push per remote, returning a result
+/// for each in the order given. A branch the remote no longer has at its
+/// planned tip is not pushed, as it moved since the plan was made.
+pub fn delete_remote_branches(
+    repo: &Repository,
+    remote_branches: &[RemoteBranch],
+    credentials: &CredentialsConfig,
+) -> Vec<Result<()>> {
+    let mut results: Vec<Option<Result<()>>> = remote_branches.iter().map(|_| None).collect();
+
+    let mut remotes: Vec<&str> = remote_branches
+        .iter()
+        .map(|rb| rb.remote.as_str())
+        .collect();
+    remotes.sort();
+    remotes.dedup();
+
+    for remote_name in remotes {
+        let indices: Vec<usize> = (0..remote_branches.len())
+            .filter(|i| remote_branches[*i].remote == remote_name)
+            .collect();
+        // One failed connection or push fails every ref in it.
+        let push_error = |e: &git2::Error| GitTidyError::Push {
+            remote: remote_name.to_string(),
+            source: git2::Error::new(e.code(), e.class(), e.message()),
         };
 
-        let cloned = branch.clone();
-        assert_eq!(cloned.name, branch.name);
-        assert_eq!(cloned.is_merged, branch.is_merged);
+        let heads = match list_remote_heads(repo, remote_name, credentials) {
+            Ok(heads) => heads,
+            Err(e) => {
+                for i in indices {
+                    results[i] = Some(Err(push_error(&e)));
+                }
+                continue;
+            }
+        };
+
+        let mut unchanged = Vec::new();
+        for i in indices {
+            let remote_branch = &remote_branches[i];
+            match heads.get(&remote_branch.branch) {
+                Some(actual) if *actual == remote_branch.tip => unchanged.push(i),
+                Some(actual) => {
+                    results[i] = Some(Err(GitTidyError::TipMoved {
+                        branch: remote_branch.to_string(),
+                        planned: remote_branch.tip,
+                        actual: *actual,
+                    }))
+                }
+                None => {
+                    results[i] = Some(Err(GitTidyError::RemoteBranchGone(
+                        remote_branch.to_string(),
+                    )))
+                }
+            }
+        }
+        if unchanged.is_empty() {
+            continue;
+        }
+
+        let branches: Vec<&str> = unchanged
+            .iter()
+            .map(|i| remote_branches[*i].branch.as_str())
+            .collect();
+        debug!("Pushing {} deletions to {}", branches.len(), remote_name);
+
+        let pushed = push_deletions(repo, remote_name, &branches, credentials);
+
+        for i in unchanged {
+            let remote_branch = &remote_branches[i];
+            results[i] = Some(match &pushed {
+                Ok(rejections) => match rejections.get(&remote_branch.branch) {
+                    Some(reason) => Err(GitTidyError::PushRejected {
+                        remote: remote_branch.remote.clone(),
+                        reason: reason.clone(),
+                    }),
+                    None => delete_tracking_ref(repo, remote_branch),
+                },
+                Err(e) => Err(push_error(e)),
+            });
+        }
+    }
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every remote branch belongs to a remote"))
+        .collect()
+}
+
+// The branches the remote advertises now, by name, and their tips.
+fn list_remote_heads(
+    repo: &Repository,
+    remote_name: &str,
+    credentials: &CredentialsConfig,
+) -> std::result::Result<HashMap<String, Oid>, git2::Error> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let callbacks =
+        remote_callbacks(credentials, repo).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+    let connection = remote.connect_auth(Direction::Push, Some(callbacks), None)?;
+
+    let heads = connection
+        .list()?
+        .iter()
+        .filter_map(|head| {
+            let branch = head.name().strip_prefix("refs/heads/")?;
+            Some((branch.to_string(), head.oid()))
+        })
+        .collect();
+
+    Ok(heads)
+}
+
+// Returns the rejected branches and why. Rejections are only reported
+// through this callback, not as a push error.
+fn push_deletions(
+    repo: &Repository,
+    remote_name: &str,
+    branches: &[&str],
+    credentials: &CredentialsConfig,
+) -> std::result::Result<HashMap<String, String>, git2::Error> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let refspecs: Vec<String> = branches
+        .iter()
+        .map(|branch| format!(":refs/heads/{}", branch))
+        .collect();
+
+    let mut rejections = HashMap::new();
+    {
+        let mut callbacks = remote_callbacks(credentials, repo)
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(status) = status {
+                let branch = refname.strip_prefix("refs/heads/").unwrap_or(refname);
+                rejections.insert(branch.to_string(), status.to_string());
+            }
+            Ok(())
+        });
+
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+
+        remote.push(&refspecs, Some(&mut options))?;
+    }
+
+    Ok(rejections)
+}
+
+fn delete_tracking_ref(repo: &Repository, remote_branch: &RemoteBranch) -> Result<()> {
+    if let Ok(mut tracking) = repo.find_reference(&format!(
+        "refs/remotes/{}/{}",
+        remote_branch.remote, remote_branch.branch
+    )) {
+        tracking.delete()?;
+    }
+
+    Ok(())
+}
+
+/// Fetches `remotes`, or every remote when none are given, pruning branches
+/// the remotes no longer have.
+pub fn fetch_and_prune(
+    repo: &Repository,
+    remotes: &[String],
+    credentials: &CredentialsConfig,
+) -> Result<Vec<String>> {
+    fetch_remotes(repo, remotes, credentials, false)
+}
+
+/// Like `fetch_and_prune`, also fetching the history a shallow clone left
+/// out.
+pub fn fetch_unshallow(
+    repo: &Repository,
+    remotes: &[String],
+    credentials: &CredentialsConfig,
+) -> Result<Vec<String>> {
+    fetch_remotes(repo, remotes, credentials, true)
+}
+
+fn fetch_remotes(
+    repo: &Repository,
+    remotes: &[String],
+    credentials: &CredentialsConfig,
+    unshallow: bool,
+) -> Result<Vec<String>> {
+    let mut fetched = Vec::new();
+
+    for remote_name in repo.remotes()?.iter().flatten() {
+        if !remotes.is_empty() && !remotes.iter().any(|r| r == remote_name) {
+            continue;
+        }
+
+        let mut remote = repo.find_remote(remote_name)?;
+        let mut options = FetchOptions::new();
+        options
+            .prune(FetchPrune::On)
+            .remote_callbacks(remote_callbacks(credentials, repo)?);
+        if unshallow {
+            // libgit2's GIT_FETCH_DEPTH_UNSHALLOW.
+            options.depth(i32::MAX);
+        }
+
+        remote
+            .fetch(&[] as &[&str], Some(&mut options), None)
+            .map_err(|source| GitTidyError::Fetch {
+                remote: remote_name.to_string(),
+                source,
+            })?;
+
+        fetched.push(remote_name.to_string());
+    }
+
+    Ok(fetched)
+}
+
+pub fn get_tagged_commits(repo: &Repository) -> Result<Vec<Oid>> {
+    let mut commits = Vec::new();
+
+    for tag_name in repo.tag_names(None)?.iter().flatten() {
+        let reference = repo.find_reference(&format!("refs/tags/{}", tag_name))?;
+
+        if let Ok(commit) = reference.peel_to_commit() {
+            commits.push(commit.id());
+        }
+    }
+
+    Ok(commits)
+}
+
+/// Whether a tag points at `tip` or, unless it is merged, was cut from a
+/// later commit, so the tag is the only other thing keeping it.
+pub fn is_tip_tagged(repo: &Repository, tip: Oid, is_merged: bool, tagged_commits: &[Oid]) -> bool {
+    if tagged_commits.contains(&tip) {
+        return true;
+    }
+
+    if is_merged {
+        return false;
+    }
+
+    tagged_commits
+        .iter()
+        .any(|tagged| repo.graph_descendant_of(*tagged, tip).unwrap_or(false))
+}
+
+pub fn list_stashed_branches(repo: &Repository) -> Result<Vec<String>> {
+    // stash_foreach needs a mutable handle; open a second one rather than
+    // forcing every caller to hold the repository mutably.
+    let mut stash_repo = Repository::open(repo.path())?;
+
+    let mut stashes: Vec<(String, Oid)> = Vec::new();
+    stash_repo.stash_foreach(|_index, message, oid| {
+        stashes.push((message.to_string(), *oid));
+        true
+    })?;
+
+    let mut tips: Vec<(String, Oid)> = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let (Some(name), Some(tip)) = (branch.name()?, branch.get().target()) {
+            tips.push((name.to_string(), tip));
+        }
+    }
+
+    let mut stashed = Vec::new();
+    for (message, oid) in stashes {
+        if let Some(name) = stash_branch_name(&message)
+            && tips.iter().any(|(branch, _)| branch == name)
+        {
+            stashed.push(name.to_string());
+        }
+
+        let base = repo.find_commit(oid)?.parent_id(0).ok();
+        for (branch, tip) in &tips {
+            if base == Some(*tip) {
+                stashed.push(branch.clone());
+            }
+        }
+    }
+
+    stashed.sort();
+    stashed.dedup();
+
+    Ok(stashed)
+}
+
+fn stash_branch_name(message: &str) -> Option<&str> {
+    let rest = message
+        .strip_prefix("WIP on ")
+        .or_else(|| message.strip_prefix("On "))?;
+    let (branch, _) = rest.split_once(": ")?;
+
+    if branch == "(no branch)" {
+        None
+    } else {
+        Some(branch)
+    }
+}
+
+pub fn ensure_no_operation_in_progress(repo: &Repository) -> Result<()> {
+    let (operation, abort_command) = match repo.state() {
+        RepositoryState::Clean => return Ok(()),
+        RepositoryState::Merge => ("A merge", "git merge --abort"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => {
+            ("A revert", "git revert --abort")
+        }
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            ("A cherry-pick", "git cherry-pick --abort")
+        }
+        RepositoryState::Bisect => ("A bisect", "git bisect reset"),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => ("A rebase", "git rebase --abort"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+            ("An am session", "git am --abort")
+        }
+    };
+
+    Err(GitTidyError::OperationInProgress {
+        operation,
+        abort_command,
+    })
+}
+
+pub fn preview_branch(
+    repo: &Repository,
+    tip: Oid,
+    limit: usize,
+    merge_targets: &[Oid],
+) -> Result<BranchPreview> {
+    let mut ahead_walk = repo.revwalk()?;
+    ahead_walk.push(tip)?;
+    for target in merge_targets {
+        ahead_walk.hide(*target)?;
+    }
+    let ahead = ahead_walk.count();
+
+    let mut recent_walk = repo.revwalk()?;
+    recent_walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    recent_walk.push(tip)?;
+
+    let mut recent_subjects = Vec::new();
+    for oid in recent_walk.take(limit) {
+        let commit = repo.find_commit(oid?)?;
+        recent_subjects.push(commit.summary().unwrap_or("").to_string());
+    }
+
+    Ok(BranchPreview {
+        ahead,
+        recent_subjects,
+    })
+}
+
+pub fn branch_size(repo: &Repository, tip: Oid, merge_targets: &[Oid]) -> Result<BranchSize> {
+    let tip = repo.find_commit(tip)?;
+
+    let mut walk = repo.revwalk()?;
+    walk.push(tip.id())?;
+    for target in merge_targets {
+        walk.hide(*target)?;
+    }
+    let unique_commits = walk.count();
+
+    // Of several merge targets the newest merge base is the closest one; a
+    // branch sharing no history with any of them is diffed against nothing.
+    let mut merge_base: Option<git2::Commit> = None;
+    for target in merge_targets {
+        let base = match repo.merge_base(tip.id(), *target) {
+            Ok(oid) => repo.find_commit(oid)?,
+            Err(e) if e.code() == ErrorCode::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if merge_base
+            .as_ref()
+            .is_none_or(|newest| base.time().seconds() > newest.time().seconds())
+        {
+            merge_base = Some(base);
+        }
+    }
+
+    let base_tree = merge_base.map(|base| base.tree()).transpose()?;
+    let diff = repo.diff_tree_to_tree(base_tree.as_ref(), Some(&tip.tree()?), None)?;
+    let stats = diff.stats()?;
+
+    Ok(BranchSize {
+        unique_commits,
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+/// Estimates what becomes unreachable now that branches at `tips` are gone:
+/// the commits no remaining ref reaches, plus the trees and blobs they
+/// introduced over the kept commits they were built on. A file that some
+/// unrelated kept branch also contains is still counted, hence "estimate".
+pub fn unreachable_objects(repo: &Repository, tips: &[Oid]) -> Result<Reclaimable> {
+    let commits = unreachable_commits(repo, tips)?
+        .into_iter()
+        .map(|oid| Ok(repo.find_commit(oid)?))
+        .collect::<Result<Vec<_>>>()?;
+    let commit_ids: HashSet<Oid> = commits.iter().map(|c| c.id()).collect();
+
+    // Everything in the trees of the kept commits the lost ones grew from
+    // survives the deletion.
+    let mut kept = HashSet::new();
+    for commit in &commits {
+        for parent in commit.parents() {
+            if !commit_ids.contains(&parent.id()) {
+                collect_tree(repo, parent.tree_id(), &mut kept, &HashSet::new())?;
+            }
+        }
+    }
+
+    let mut lost: HashSet<Oid> = commit_ids.clone();
+    for commit in &commits {
+        collect_tree(repo, commit.tree_id(), &mut lost, &kept)?;
+    }
+
+    let odb = repo.odb()?;
+    let mut bytes = 0;
+    for oid in &lost {
+        let (size, _) = odb.read_header(*oid)?;
+        bytes += size as u64;
+    }
+    trace!(
+        "{} commits only reachable from deleted branches",
+        commits.len()
+    );
+
+    Ok(Reclaimable {
+        objects: lost.len(),
+        bytes,
+    })
+}
+
+// The commits reachable from `tips` that no ref (or a detached HEAD) reaches.
+fn unreachable_commits(repo: &Repository, tips: &[Oid]) -> Result<Vec<Oid>> {
+    let mut walk = repo.revwalk()?;
+    for tip in tips {
+        walk.push(*tip)?;
+    }
+    for reference in repo.references()? {
+        if let Ok(commit) = reference?.peel_to_commit() {
+            walk.hide(commit.id())?;
+        }
+    }
+    // A detached HEAD keeps its commits alive without any ref under refs/.
+    if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+        walk.hide(commit.id())?;
+    }
+
+    Ok(walk.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Drops what still pins the work of deleted branches (`names`, which
+/// pointed at `tips`): their own reflogs, if any are left, and HEAD's
+/// entries for commits no ref reaches any more, e.g. from when one of them
+/// was checked out. Returns how many HEAD entries were dropped.
+pub fn expire_deleted_reflogs(repo: &Repository, names: &[String], tips: &[Oid]) -> Result<usize> {
+    for name in names {
+        repo.reflog_delete(&format!("refs/heads/{}", name))?;
+    }
+
+    let lost: HashSet<Oid> = unreachable_commits(repo, tips)?.into_iter().collect();
+    let mut reflog = repo.reflog("HEAD")?;
+    let mut dropped = 0;
+    for index in (0..reflog.len()).rev() {
+        if reflog
+            .get(index)
+            .is_some_and(|entry| lost.contains(&entry.id_new()))
+        {
+            reflog.remove(index, true)?;
+            dropped += 1;
+        }
+    }
+    if dropped > 0 {
+        reflog.write()?;
+    }
+
+    Ok(dropped)
+}
+
+/// Reflog entries older than a cutoff in one ref's reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogExpiry {
+    pub reference: String,
+    pub expired: usize,
+    pub total: usize,
+}
+
+/// The reflogs with entries older than `cutoff`, HEAD's first, like `git
+/// reflog expire --all` would trim them.
+pub fn plan_reflog_expiry(repo: &Repository, cutoff: DateTime<Utc>) -> Result<Vec<ReflogExpiry>> {
+    let mut names = vec!["HEAD".to_string()];
+    for reference in repo.references()? {
+        names.extend(reference?.name().map(str::to_string));
+    }
+
+    let mut plan = Vec::new();
+    for name in names {
+        let reflog = repo.reflog(&name)?;
+        let expired = reflog
+            .iter()
+            .filter(|entry| entry.committer().when().seconds() <= cutoff.timestamp())
+            .count();
+        if expired > 0 {
+            plan.push(ReflogExpiry {
+                reference: name,
+                expired,
+                total: reflog.len(),
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Removes the entries older than `cutoff` from `reference`'s reflog and
+/// returns how many there were.
+pub fn expire_reflog(repo: &Repository, reference: &str, cutoff: DateTime<Utc>) -> Result<usize> {
+    let mut reflog = repo.reflog(reference)?;
+    let mut expired = 0;
+    for index in (0..reflog.len()).rev() {
+        if reflog
+            .get(index)
+            .is_some_and(|entry| entry.committer().when().seconds() <= cutoff.timestamp())
+        {
+            reflog.remove(index, true)?;
+            expired += 1;
+        }
+    }
+    if expired > 0 {
+        reflog.write()?;
+    }
+
+    Ok(expired)
+}
+
+// Adds the tree and everything below it to `seen`, skipping subtrees already
+// in `seen` or `skip`.
+fn collect_tree(
+    repo: &Repository,
+    tree_id: Oid,
+    seen: &mut HashSet<Oid>,
+    skip: &HashSet<Oid>,
+) -> Result<()> {
+    if skip.contains(&tree_id) || !seen.insert(tree_id) {
+        return Ok(());
+    }
+
+    for entry in repo.find_tree(tree_id)?.iter() {
+        match entry.kind() {
+            Some(git2::ObjectType::Tree) => collect_tree(repo, entry.id(), seen, skip)?,
+            // Submodule commits live in another repository.
+            Some(git2::ObjectType::Commit) => {}
+            _ => {
+                if !skip.contains(&entry.id()) {
+                    seen.insert(entry.id());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Loose reference files under `refs/`, the ones `git pack-refs` folds into
+/// `packed-refs`.
+pub fn count_loose_refs(repo: &Repository) -> Result<usize> {
+    fn count(dir: &std::path::Path) -> std::io::Result<usize> {
+        let mut total = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                total += count(&entry.path())?;
+            } else {
+                total += 1;
+            }
+        }
+        Ok(total)
+    }
+
+    Ok(count(&common_dir(repo)?.join("refs"))?)
+}
+
+/// Packs references and runs `git gc --auto`, which only repacks objects
+/// when git thinks it is worthwhile. libgit2 has no gc, so this needs git.
+pub fn compact_repository(repo: &Repository) -> Result<()> {
+    let git_dir = common_dir(repo)?;
+
+    for args in [&["pack-refs", "--all"][..], &["gc", "--auto", "--quiet"]] {
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(&git_dir)
+            .args(args)
+            .output()
+            .map_err(|e| GitTidyError::Maintenance {
+                command: args.join(" "),
+                message: e.to_string(),
+            })?;
+
+        if !output.status.success() {
+            return Err(GitTidyError::Maintenance {
+                command: args.join(" "),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a bundle of the branches and their full history to `path`, so they
+/// can be restored with `git fetch <path> refs/heads/<name>:refs/heads/<name>`
+/// even after the repository is gone.
+pub fn write_bundle(repo: &Repository, path: &Path, branches: &[&str]) -> Result<()> {
+    let git_dir = common_dir(repo)?;
+    let refs: Vec<String> = branches
+        .iter()
+        .map(|name| format!("refs/heads/{}", name))
+        .collect();
+    let failed = |message: String| GitTidyError::Bundle {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    debug!("Bundling {} branches into {}", refs.len(), path.display());
+    let output = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(&git_dir)
+        .args(["bundle", "create", "--quiet"])
+        .arg(path)
+        .args(&refs)
+        .output()
+        .map_err(|e| failed(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether git found the repository's filesystem to ignore case
+/// (`core.ignorecase`, set by `git init` and `git clone` on macOS and
+/// Windows). Branch names differing only in case then share a ref file.
+pub fn ignores_case(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_bool("core.ignorecase"))
+        .unwrap_or(false)
+}
+
+/// Each name that differs from another only in case, with the first such
+/// other name, e.g. ("Feature", "feature") and ("feature", "Feature").
+pub fn case_collisions(names: &[String]) -> Vec<(String, String)> {
+    names
+        .iter()
+        .filter_map(|name| {
+            names
+                .iter()
+                .find(|other| *other != name && other.eq_ignore_ascii_case(name))
+                .map(|other| (name.clone(), other.clone()))
+        })
+        .collect()
+}
+
+/// `user.email`, which --mine compares branch owners against.
+pub fn current_user_email(repo: &Repository) -> Result<String> {
+    match repo.config()?.get_string("user.email") {
+        Ok(email) => Ok(email),
+        Err(e) if e.code() == ErrorCode::NotFound => Err(GitTidyError::NoUserEmail),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn list_worktrees(repo: &Repository) -> Result<Vec<WorktreeInfo>> {
+    let mut worktrees = Vec::new();
+    let common_dir = common_dir(repo)?;
+
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        let is_missing = !worktree.path().exists();
+        let is_locked = !matches!(worktree.is_locked()?, WorktreeLockStatus::Unlocked);
+
+        // The branch is read from the admin directory so it is still known
+        // when the worktree's directory has been deleted.
+        let head_path = common_dir.join("worktrees").join(name).join("HEAD");
+        let branch = read_head_branch(&head_path);
+
+        let is_dirty = !is_missing && {
+            let worktree_repo = Repository::open_from_worktree(&worktree)?;
+            let mut options = StatusOptions::new();
+            options.include_untracked(true).include_ignored(false);
+            !worktree_repo.statuses(Some(&mut options))?.is_empty()
+        };
+
+        worktrees.push(WorktreeInfo {
+            name: name.to_string(),
+            path: worktree.path().to_path_buf(),
+            branch,
+            is_missing,
+            is_locked,
+            is_dirty,
+        });
+    }
+
+    worktrees.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(worktrees)
+}
+
+// When git-tidy runs inside a linked worktree, repo.path() is that
+// worktree's admin directory; the shared `.git` is named in its commondir file.
+/// The repository's git directory, shared by all its worktrees.
+pub fn common_dir(repo: &Repository) -> Result<PathBuf> {
+    if !repo.is_worktree() {
+        return Ok(repo.path().to_path_buf());
+    }
+
+    let commondir = std::fs::read_to_string(repo.path().join("commondir"))?;
+    Ok(repo.path().join(commondir.trim()))
+}
+
+// Equivalent to `git worktree remove` for worktrees that still exist, and to
+// `git worktree prune` for ones whose directory is already gone.
+pub fn remove_worktree(repo: &Repository, name: &str) -> Result<()> {
+    let worktree = repo.find_worktree(name)?;
+
+    if !matches!(worktree.is_locked()?, WorktreeLockStatus::Unlocked) {
+        return Err(GitTidyError::WorktreeLocked(name.to_string()));
+    }
+
+    worktree.prune(Some(
+        WorktreePruneOptions::new().valid(true).working_tree(true),
+    ))?;
+
+    Ok(())
+}
+
+pub fn list_leftover_refs(repo: &Repository) -> Result<Vec<LeftoverRef>> {
+    let mut leftovers = Vec::new();
+    let common_dir = common_dir(repo)?;
+
+    for prefix in LEFTOVER_REF_PREFIXES {
+        for reference in repo.references_glob(&format!("{}*", prefix))? {
+            let reference = reference?;
+            let Some(name) = reference.name().map(str::to_string) else {
+                continue;
+            };
+
+            let logged = repo
+                .reflog(&name)
+                .ok()
+                .and_then(|reflog| reflog.get(0).map(|entry| entry.committer().when()))
+                .and_then(|when| Utc.timestamp_opt(when.seconds(), 0).single());
+            let written = || {
+                std::fs::metadata(common_dir.join(&name))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .map(DateTime::<Utc>::from)
+            };
+            let committed = || {
+                reference
+                    .peel_to_commit()
+                    .ok()
+                    .and_then(|commit| Utc.timestamp_opt(commit.time().seconds(), 0).single())
+            };
+            let Some(date) = logged.or_else(written).or_else(committed) else {
+                debug!("{}: cannot tell when it was written", name);
+                continue;
+            };
+
+            leftovers.push(LeftoverRef { name, date });
+        }
+    }
+
+    leftovers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(leftovers)
+}
+
+pub fn delete_leftover_ref(repo: &Repository, name: &str) -> Result<()> {
+    repo.find_reference(name)?.delete()?;
+    Ok(())
+}
+
+// One walk over the targets' history classifies every branch by lookup,
+// instead of a graph_descendant_of walk per branch.
+fn reachable_commits(repo: &Repository, merge_targets: &[Oid]) -> Result<HashSet<Oid>> {
+    let mut reachable = HashSet::new();
+    if merge_targets.is_empty() {
+        return Ok(reachable);
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    for target in merge_targets {
+        revwalk.push(*target)?;
+    }
+
+    for oid in revwalk {
+        reachable.insert(oid?);
+    }
+    debug!(
+        "{} commits reachable from the merge targets",
+        reachable.len()
+    );
+
+    Ok(reachable)
+}
+
+fn first_parent_history(repo: &Repository, merge_targets: &[Oid]) -> Result<HashSet<Oid>> {
+    let mut history = HashSet::new();
+    if merge_targets.is_empty() {
+        return Ok(history);
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.simplify_first_parent()?;
+    for target in merge_targets {
+        revwalk.push(*target)?;
+    }
+
+    for oid in revwalk {
+        history.insert(oid?);
+    }
+
+    Ok(history)
+}
+
+/// The tag matching `pattern` whose commit is newest, with that commit.
+/// Between commits made the same second, the descendant wins.
+pub fn newest_matching_tag(repo: &Repository, pattern: &Pattern) -> Result<Option<(String, Oid)>> {
+    let mut newest: Option<(String, Oid, i64)> = None;
+
+    for tag_name in repo.tag_names(None)?.iter().flatten() {
+        if !pattern.matches(tag_name) {
+            continue;
+        }
+        let reference = repo.find_reference(&format!("refs/tags/{}", tag_name))?;
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        let time = commit.time().seconds();
+
+        let is_newer = match &newest {
+            None => true,
+            Some((_, oid, newest_time)) => {
+                time > *newest_time
+                    || (time == *newest_time && repo.graph_descendant_of(commit.id(), *oid)?)
+            }
+        };
+        if is_newer {
+            newest = Some((tag_name.to_string(), commit.id(), time));
+        }
+    }
+
+    Ok(newest.map(|(name, oid, _)| (name, oid)))
+}
+
+// The names the mainline may go by, most likely first: the default branch
+// `remote`'s HEAD names (e.g. trunk), init.defaultBranch, then main and
+// master.
+fn mainline_names(repo: &Repository, remote: &str) -> Result<Vec<String>> {
+    let remote_head = remote_default_branches(repo)?
+        .into_iter()
+        .filter(|(name, _)| name == remote)
+        .map(|(_, branch)| branch);
+    let init_default = repo.config()?.get_string("init.defaultBranch").ok();
+
+    let mut names = Vec::new();
+    for name in remote_head
+        .chain(init_default)
+        .chain(["main".to_string(), "master".to_string()])
+    {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// The local mainline (see `mainline_names`), or every local branch matching
+// merge_targets, plus the same branches on merge_remote when configured, so
+// clones with a stale or missing local base still classify merged branches
+// correctly.
+pub fn resolve_merge_targets(repo: &Repository, config: &Config) -> Result<Vec<Oid>> {
+    let patterns = config.get_merge_targets()?;
+    let mut targets = Vec::new();
+
+    if patterns.is_empty() {
+        let names = mainline_names(repo, config.merge_remote.as_deref().unwrap_or("origin"))?;
+        for target in &names {
+            if let Ok(branch) = repo.find_branch(target, BranchType::Local) {
+                let oid = branch.get().peel_to_commit()?.id();
+                debug!("Merge target {} at {}", target, oid);
+                targets.push(oid);
+                break;
+            }
+        }
+
+        if let Some(remote) = config.merge_remote.as_deref() {
+            for target in &names {
+                let name = format!("{}/{}", remote, target);
+                if let Ok(branch) = repo.find_branch(&name, BranchType::Remote) {
+                    let oid = branch.get().peel_to_commit()?.id();
+                    debug!("Merge target {} at {}", name, oid);
+                    targets.push(oid);
+                    break;
+                }
+            }
+        }
+    } else {
+        let remote_prefix = config
+            .merge_remote
+            .as_deref()
+            .map(|remote| format!("{}/", remote));
+
+        for branch in repo.branches(None)? {
+            let (branch, branch_type) = branch?;
+            let Some(name) = branch.name()? else {
+                continue;
+            };
+
+            let short_name = match branch_type {
+                BranchType::Local => Some(name),
+                BranchType::Remote => remote_prefix
+                    .as_deref()
+                    .and_then(|prefix| name.strip_prefix(prefix)),
+            };
+
+            if short_name.is_some_and(|n| patterns.iter().any(|p| p.matches(n))) {
+                let oid = branch.get().peel_to_commit()?.id();
+                debug!("Merge target {} at {}", name, oid);
+                if !targets.contains(&oid) {
+                    targets.push(oid);
+                }
+            }
+        }
+    }
+
+    if let Some(pattern) = config.get_merge_tag()? {
+        match newest_matching_tag(repo, &pattern)? {
+            Some((name, oid)) => {
+                debug!("Merge target tag {} at {}", name, oid);
+                if !targets.contains(&oid) {
+                    targets.push(oid);
+                }
+            }
+            None => debug!("No tag matches merge_tag {}", pattern),
+        }
+    }
+
+    targets.dedup();
+
+    if targets.is_empty() {
+        debug!("No merge target branch found; no branch counts as merged");
+    }
+
+    Ok(targets)
+}
+
+/// The commits reachable from `tip` that no merge target has, from one walk
+/// hiding the merge targets.
+pub fn unmerged_commits(
+    repo: &Repository,
+    tip: Oid,
+    merge_targets: &[Oid],
+) -> Result<HashSet<Oid>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(tip)?;
+    for target in merge_targets {
+        revwalk.hide(*target)?;
+    }
+
+    let mut commits = HashSet::new();
+    for oid in revwalk {
+        commits.insert(oid?);
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_on_head, init_test_repo};
+    use git2::{RepositoryInitOptions, Signature};
+
+    fn commit_on_branch(repo: &Repository, branch_name: &str, message: &str) -> Oid {
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let parent = repo
+            .find_branch(branch_name, BranchType::Local)
+            .unwrap()
+            .get()
+            .peel_to_commit()
+            .unwrap();
+
+        repo.commit(
+            Some(&format!("refs/heads/{}", branch_name)),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &[&parent],
+        )
+        .unwrap()
+    }
+
+    fn create_branch(repo: &Repository, branch_name: &str) {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch(branch_name, &head, false).unwrap();
+    }
+
+    fn is_commit_merged(repo: &Repository, tip: Oid, merge_targets: &[Oid]) -> bool {
+        merge_targets
+            .iter()
+            .any(|target| *target == tip || repo.graph_descendant_of(*target, tip).unwrap())
+    }
+
+    fn branch_tip(repo: &Repository, branch_name: &str) -> Oid {
+        repo.find_branch(branch_name, BranchType::Local)
+            .unwrap()
+            .get()
+            .target()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_list_and_remove_worktrees() {
+        let (path, repo) = init_test_repo("worktrees");
+        let worktree_root = path.with_extension("worktrees");
+        let _ = std::fs::remove_dir_all(&worktree_root);
+        std::fs::create_dir_all(&worktree_root).unwrap();
+
+        for name in ["kept", "gone"] {
+            create_branch(&repo, name);
+            let reference = repo
+                .find_reference(&format!("refs/heads/{}", name))
+                .unwrap();
+            repo.worktree(
+                name,
+                &worktree_root.join(name),
+                Some(git2::WorktreeAddOptions::new().reference(Some(&reference))),
+            )
+            .unwrap();
+        }
+        std::fs::remove_dir_all(worktree_root.join("gone")).unwrap();
+        std::fs::write(worktree_root.join("kept").join("scratch.txt"), "wip").unwrap();
+
+        let worktrees = list_worktrees(&repo).unwrap();
+        assert_eq!(worktrees.len(), 2);
+
+        let gone = worktrees.iter().find(|w| w.name == "gone").unwrap();
+        assert!(gone.is_missing);
+        assert!(!gone.is_dirty);
+        assert_eq!(gone.branch.as_deref(), Some("gone"));
+
+        let kept = worktrees.iter().find(|w| w.name == "kept").unwrap();
+        assert!(!kept.is_missing);
+        assert!(kept.is_dirty);
+        assert_eq!(kept.branch.as_deref(), Some("kept"));
+
+        remove_worktree(&repo, "gone").unwrap();
+        remove_worktree(&repo, "kept").unwrap();
+        assert!(list_worktrees(&repo).unwrap().is_empty());
+        assert!(!worktree_root.join("kept").exists());
+        assert!(repo.find_branch("kept", BranchType::Local).is_ok());
+
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_dir_all(&worktree_root);
+    }
+
+    #[test]
+    fn test_get_checked_out_branches() {
+        let (path, repo) = init_test_repo("checked-out");
+        let worktree_path = path.with_extension("review");
+        let _ = std::fs::remove_dir_all(&worktree_path);
+
+        create_branch(&repo, "review");
+        let reference = repo.find_reference("refs/heads/review").unwrap();
+        repo.worktree(
+            "review",
+            &worktree_path,
+            Some(git2::WorktreeAddOptions::new().reference(Some(&reference))),
+        )
+        .unwrap();
+
+        let main = CheckedOutBranch {
+            branch: "main".to_string(),
+            checkout: Checkout::Main,
+            is_current: true,
+        };
+        let review = CheckedOutBranch {
+            branch: "review".to_string(),
+            checkout: Checkout::Worktree("review".to_string()),
+            is_current: false,
+        };
+        assert_eq!(
+            get_checked_out_branches(&repo).unwrap(),
+            vec![main.clone(), review.clone()]
+        );
+
+        let from_worktree = Repository::open(&worktree_path).unwrap();
+        let checked_out = get_checked_out_branches(&from_worktree).unwrap();
+        assert!(!checked_out[0].is_current);
+        assert!(checked_out[1].is_current);
+
+        let bare_path = path.with_extension("bare");
+        let _ = std::fs::remove_dir_all(&bare_path);
+        let bare = Repository::init_opts(
+            &bare_path,
+            RepositoryInitOptions::new()
+                .bare(true)
+                .initial_head("trunk"),
+        )
+        .unwrap();
+        let checked_out = get_checked_out_branches(&bare).unwrap();
+        assert_eq!(checked_out.len(), 1);
+        assert_eq!(checked_out[0].branch, "trunk");
+        assert_eq!(checked_out[0].checkout, Checkout::Main);
+
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_dir_all(&worktree_path);
+        let _ = std::fs::remove_dir_all(&bare_path);
+    }
+
+    #[test]
+    fn test_branch_info_clone() {
+        let branch = BranchInfo {
+            name: "test-branch".to_string(),
+            tip: Oid::zero(),
+            is_merged: true,
+            merged_at: None,
+            last_commit_date: Utc::now(),
+            last_commit_subject: "Add test".to_string(),
+            last_commit_author: "Test".to_string(),
+            short_sha: "0000000".to_string(),
+            remote: None,
+            redundant: None,
+            is_empty: false,
+            is_orphan: false,
+            owner: "test@example.com".to_string(),
+        };
+
+        let cloned = branch.clone();
+        assert_eq!(cloned.name, branch.name);
+        assert_eq!(cloned.is_merged, branch.is_merged);
+    }
+
+    #[test]
+    fn test_ensure_no_operation_in_progress() {
+        let (path, repo) = init_test_repo("in-progress");
+
+        assert!(ensure_no_operation_in_progress(&repo).is_ok());
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        std::fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", head.id())).unwrap();
+
+        let result = ensure_no_operation_in_progress(&repo);
+        assert!(matches!(
+            result,
+            Err(GitTidyError::OperationInProgress {
+                operation: "A merge",
+                ..
+            })
+        ));
+        assert!(
+            result
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("git merge --abort")
+        );
+
+        std::fs::remove_file(repo.path().join("MERGE_HEAD")).unwrap();
+        std::fs::create_dir(repo.path().join("rebase-merge")).unwrap();
+        assert!(ensure_no_operation_in_progress(&repo).is_err());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_archive_and_unarchive() {
+        let (path, repo) = init_test_repo("archive");
+        let config = Config::new();
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+
+        create_branch(&repo, "feature/x");
+        let tip = commit_on_branch(&repo, "feature/x", "work");
+
+        let archive_ref = config.archive_ref("feature/x", now);
+        archive_planned_branch(&repo, "refs/heads/feature/x", tip, &archive_ref).unwrap();
+        assert!(repo.find_branch("feature/x", BranchType::Local).is_err());
+
+        let archived = list_archived(&repo, &config).unwrap();
+        assert_eq!(
+            archived,
+            vec![ArchivedBranch {
+                name: "feature/x".to_string(),
+                reference: "refs/archived/feature/x".to_string(),
+                tip,
+            }]
+        );
+
+        create_branch(&repo, "feature/x");
+        assert!(matches!(
+            unarchive_branch(&repo, &archived[0]),
+            Err(GitTidyError::BranchExists(_))
+        ));
+        let result = archive_planned_branch(
+            &repo,
+            "refs/heads/feature/x",
+            branch_tip(&repo, "feature/x"),
+            &archive_ref,
+        );
+        assert!(matches!(result, Err(GitTidyError::ArchiveExists { .. })));
+
+        repo.find_branch("feature/x", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        unarchive_branch(&repo, &archived[0]).unwrap();
+        let restored = repo.find_branch("feature/x", BranchType::Local).unwrap();
+        assert_eq!(restored.get().target(), Some(tip));
+        assert!(list_archived(&repo, &config).unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_write_bundle() {
+        let (path, repo) = init_test_repo("bundle");
+        create_branch(&repo, "feature/a");
+        let tip = commit_on_branch(&repo, "feature/a", "work");
+
+        let bundle = path.join("backup.bundle");
+        write_bundle(&repo, &bundle, &["feature/a"]).unwrap();
+
+        let output = std::process::Command::new("git")
+            .args(["bundle", "list-heads"])
+            .arg(&bundle)
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            format!("{} refs/heads/feature/a", tip)
+        );
+
+        let result = write_bundle(&repo, &path.join("missing/backup.bundle"), &["nope"]);
+        assert!(matches!(result, Err(GitTidyError::Bundle { .. })));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_branches() {
+        let (path, repo) = init_test_repo("list");
+
+        create_branch(&repo, "feature/nested/merged");
+        commit_on_branch(&repo, "feature/nested/merged", "merged work");
+        let merged_tip = repo
+            .find_branch("feature/nested/merged", BranchType::Local)
+            .unwrap()
+            .get()
+            .peel_to_commit()
+            .unwrap();
+        repo.reference("refs/heads/main", merged_tip.id(), true, "fast-forward")
+            .unwrap();
+        commit_on_head(&repo, "more work on main");
+
+        create_branch(&repo, "unmerged");
+        commit_on_branch(&repo, "unmerged", "unmerged work");
+
+        // Merged through a merge commit's second parent, off main's
+        // first-parent history.
+        create_branch(&repo, "side");
+        let side_tip = commit_on_branch(&repo, "side", "side work");
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let main_tip = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "merge side",
+            &tree,
+            &[&main_tip, &repo.find_commit(side_tip).unwrap()],
+        )
+        .unwrap();
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let branches = list_branches(&repo, &targets).unwrap();
+        let names: Vec<_> = branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(branches.len(), 4);
+        assert!(names.contains(&"main"));
+
+        let merged = branches
+            .iter()
+            .find(|b| b.name == "feature/nested/merged")
+            .unwrap();
+        assert!(merged.is_merged);
+
+        let side = branches.iter().find(|b| b.name == "side").unwrap();
+        assert!(side.is_merged);
+        assert!(!side.is_empty);
+
+        let unmerged = branches.iter().find(|b| b.name == "unmerged").unwrap();
+        assert!(!unmerged.is_merged);
+        assert_eq!(unmerged.last_commit_subject, "unmerged work");
+        assert_eq!(unmerged.last_commit_author, "Test");
+        assert!(unmerged.tip.to_string().starts_with(&unmerged.short_sha));
+        assert!(unmerged.short_sha.len() >= 7);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_branches_orphan() {
+        let (path, repo) = init_test_repo("orphan");
+
+        create_branch(&repo, "unmerged");
+        commit_on_branch(&repo, "unmerged", "unmerged work");
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let root = repo
+            .commit(None, &signature, &signature, "imported", &tree, &[])
+            .unwrap();
+        repo.branch("imported", &repo.find_commit(root).unwrap(), false)
+            .unwrap();
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let branches = list_branches(&repo, &targets).unwrap();
+        let orphans: Vec<_> = branches
+            .iter()
+            .filter(|b| b.is_orphan)
+            .map(|b| b.name.as_str())
+            .collect();
+        assert_eq!(orphans, ["imported"]);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_branches_shallow() {
+        let (path, repo) = init_test_repo("shallow");
+
+        create_branch(&repo, "feature");
+        let feature_tip = commit_on_branch(&repo, "feature", "feature work");
+        repo.reference("refs/heads/main", feature_tip, true, "fast-forward")
+            .unwrap();
+        let cut = commit_on_head(&repo, "more work on main");
+        // What `git clone --depth 1` leaves: main's history ends at `cut`.
+        std::fs::write(repo.path().join("shallow"), format!("{}\n", cut)).unwrap();
+        let repo = Repository::open(&path).unwrap();
+        assert!(repo.is_shallow());
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let branches = list_branches(&repo, &targets).unwrap();
+        let feature = branches.iter().find(|b| b.name == "feature").unwrap();
+        assert!(!feature.is_merged);
+        assert!(!feature.is_orphan);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_leftover_refs() {
+        let (path, repo) = init_test_repo("leftover-refs");
+
+        let head = repo.head().unwrap().target().unwrap();
+        repo.reference(
+            "refs/original/refs/heads/main",
+            head,
+            false,
+            "filter-branch",
+        )
+        .unwrap();
+        repo.reference(&format!("refs/replace/{}", head), head, false, "replace")
+            .unwrap();
+        repo.reference("refs/notes/commits", head, false, "notes")
+            .unwrap();
+
+        let leftovers = list_leftover_refs(&repo).unwrap();
+        let names: Vec<_> = leftovers.iter().map(|r| r.name.clone()).collect();
+        assert_eq!(
+            names,
+            [
+                "refs/original/refs/heads/main".to_string(),
+                format!("refs/replace/{}", head)
+            ]
+        );
+        assert!(
+            leftovers
+                .iter()
+                .all(|r| r.date > Utc::now() - chrono::Duration::hours(1))
+        );
+
+        delete_leftover_ref(&repo, "refs/original/refs/heads/main").unwrap();
+        assert_eq!(list_leftover_refs(&repo).unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_expire_deleted_reflogs() {
+        let (path, repo) = init_test_repo("expire-deleted-reflogs");
+
+        create_branch(&repo, "gone");
+        let tip = commit_on_branch(&repo, "gone", "lost work");
+        repo.set_head("refs/heads/gone").unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        repo.find_branch("gone", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        let head_entries = repo.reflog("HEAD").unwrap().len();
+
+        let dropped = expire_deleted_reflogs(&repo, &["gone".to_string()], &[tip]).unwrap();
+        assert_eq!(dropped, 1);
+        let reflog = repo.reflog("HEAD").unwrap();
+        assert_eq!(reflog.len(), head_entries - 1);
+        assert!(reflog.iter().all(|entry| entry.id_new() != tip));
+        assert!(!path.join(".git/logs/refs/heads/gone").exists());
+
+        let plan = plan_reflog_expiry(&repo, Utc::now() - chrono::Duration::hours(1)).unwrap();
+        assert!(plan.is_empty());
+        let plan = plan_reflog_expiry(&repo, Utc::now() + chrono::Duration::hours(1)).unwrap();
+        assert_eq!(plan[0].reference, "HEAD");
+        assert_eq!(plan[0].expired, head_entries - 1);
+        assert_eq!(
+            expire_reflog(&repo, "HEAD", Utc::now() + chrono::Duration::hours(1)).unwrap(),
+            head_entries - 1
+        );
+        assert!(repo.reflog("HEAD").unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_remote_branches() {
+        let (path, repo) = init_test_repo("list-remote");
+
+        create_branch(&repo, "unmerged");
+        commit_on_branch(&repo, "unmerged", "unmerged work");
+        let head = repo.head().unwrap().target().unwrap();
+        let unmerged = branch_tip(&repo, "unmerged");
+        repo.reference("refs/remotes/origin/main", head, true, "fetch")
+            .unwrap();
+        repo.reference("refs/remotes/origin/remote/only", unmerged, true, "fetch")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            true,
+            "clone",
+        )
+        .unwrap();
+        repo.reference("refs/remotes/fork/elsewhere", head, true, "fetch")
+            .unwrap();
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let branches = list_remote_branches(&repo, "origin", &targets).unwrap();
+        let mut names: Vec<_> = branches.iter().map(|b| b.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["main", "remote/only"]);
+        assert!(
+            branches
+                .iter()
+                .all(|b| b.remote.as_deref() == Some("origin"))
+        );
+
+        let remote_only = branches.iter().find(|b| b.name == "remote/only").unwrap();
+        assert!(!remote_only.is_merged);
+        assert_eq!(remote_only.tip, unmerged);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_apply_reflog_dates() {
+        let (path, repo) = init_test_repo("reflog-dates");
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let old = Signature::new(
+            "Test",
+            "test@example.com",
+            &git2::Time::new(1_600_000_000, 0),
+        )
+        .unwrap();
+        let old_commit = repo
+            .commit(
+                None,
+                &old,
+                &old,
+                "old work",
+                &head.tree().unwrap(),
+                &[&head],
+            )
+            .unwrap();
+        let old_commit = repo.find_commit(old_commit).unwrap();
+        repo.branch("rebased", &old_commit, false).unwrap();
+        repo.branch("expired", &old_commit, false).unwrap();
+        std::fs::remove_file(repo.path().join("logs/refs/heads/expired")).unwrap();
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let mut branches = list_branches(&repo, &targets).unwrap();
+        let commit_date = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        assert!(
+            branches
+                .iter()
+                .all(|b| b.name == "main" || b.last_commit_date == commit_date)
+        );
+
+        apply_reflog_dates(&repo, &mut branches).unwrap();
+
+        let rebased = branches.iter().find(|b| b.name == "rebased").unwrap();
+        assert!(rebased.last_commit_date > Utc::now() - chrono::Duration::hours(1));
+        let expired = branches.iter().find(|b| b.name == "expired").unwrap();
+        assert_eq!(expired.last_commit_date, commit_date);
+        assert_eq!(branches.last().unwrap().name, "expired");
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_apply_merge_dates() {
+        let (path, repo) = init_test_repo("merge-dates");
+
+        let at = |seconds: i64| {
+            Signature::new("Test", "test@example.com", &git2::Time::new(seconds, 0)).unwrap()
+        };
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let tree = head.tree().unwrap();
+
+        // feature is merged with a merge commit, then fast-forward lands on
+        // top of it, and main moves on.
+        let feature = repo
+            .commit(
+                None,
+                &at(1_600_000_000),
+                &at(1_600_000_000),
+                "feature",
+                &tree,
+                &[&head],
+            )
+            .unwrap();
+        let feature = repo.find_commit(feature).unwrap();
+        repo.branch("feature", &feature, false).unwrap();
+        let merge = repo
+            .commit(
+                Some("HEAD"),
+                &at(1_700_000_000),
+                &at(1_700_000_000),
+                "Merge feature",
+                &tree,
+                &[&head, &feature],
+            )
+            .unwrap();
+        let merge = repo.find_commit(merge).unwrap();
+        let fast_forward = repo
+            .commit(
+                Some("HEAD"),
+                &at(1_650_000_000),
+                &at(1_710_000_000),
+                "fast-forward",
+                &tree,
+                &[&merge],
+            )
+            .unwrap();
+        repo.branch(
+            "fast-forward",
+            &repo.find_commit(fast_forward).unwrap(),
+            false,
+        )
+        .unwrap();
+        commit_on_head(&repo, "later work on main");
+        create_branch(&repo, "unmerged");
+        commit_on_branch(&repo, "unmerged", "unmerged work");
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let mut branches = list_branches(&repo, &targets).unwrap();
+        apply_merge_dates(&repo, &mut branches, &targets).unwrap();
+        let merged_at = |name: &str| branches.iter().find(|b| b.name == name).unwrap().merged_at;
+
+        assert_eq!(
+            merged_at("feature"),
+            Utc.timestamp_opt(1_700_000_000, 0).single()
+        );
+        assert_eq!(
+            merged_at("fast-forward"),
+            Utc.timestamp_opt(1_710_000_000, 0).single()
+        );
+        assert_eq!(merged_at("unmerged"), None);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_preview_branch() {
+        let (path, repo) = init_test_repo("preview");
+
+        create_branch(&repo, "feature");
+        commit_on_branch(&repo, "feature", "first change");
+        commit_on_branch(&repo, "feature", "second change");
+        commit_on_branch(&repo, "feature", "third change");
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        let preview = preview_branch(&repo, branch_tip(&repo, "feature"), 2, &targets).unwrap();
+        assert_eq!(preview.ahead, 3);
+        assert_eq!(
+            preview.recent_subjects,
+            vec!["third change".to_string(), "second change".to_string()]
+        );
+
+        create_branch(&repo, "merged");
+        let preview = preview_branch(&repo, branch_tip(&repo, "merged"), 5, &targets).unwrap();
+        assert_eq!(preview.ahead, 0);
+        assert_eq!(preview.recent_subjects, vec!["initial commit".to_string()]);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_unreachable_objects() {
+        let (path, repo) = init_test_repo("unreachable");
+
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        let blob = repo.blob(b"one\ntwo\nthree\n").unwrap();
+        tree.insert("notes.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let tip = repo
+            .commit(
+                Some("refs/heads/feature"),
+                &signature,
+                &signature,
+                "add notes",
+                &tree,
+                &[&parent],
+            )
+            .unwrap();
+
+        assert_eq!(
+            unreachable_objects(&repo, &[tip]).unwrap(),
+            Reclaimable::default()
+        );
+
+        repo.find_branch("feature", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        let reclaimable = unreachable_objects(&repo, &[tip]).unwrap();
+        // The commit, its tree, and the new blob.
+        assert_eq!(reclaimable.objects, 3);
+        assert!(reclaimable.bytes > 14);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_branch_size() {
+        let (path, repo) = init_test_repo("size");
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+
+        create_branch(&repo, "feature");
+        assert_eq!(
+            branch_size(&repo, branch_tip(&repo, "feature"), &targets).unwrap(),
+            BranchSize {
+                unique_commits: 0,
+                insertions: 0,
+                deletions: 0,
+            }
+        );
+
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        let blob = repo.blob(b"one\ntwo\nthree\n").unwrap();
+        tree.insert("notes.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("refs/heads/feature"),
+            &signature,
+            &signature,
+            "add notes",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+        commit_on_head(&repo, "main moves on");
+
+        let size = branch_size(&repo, branch_tip(&repo, "feature"), &targets).unwrap();
+        assert_eq!(size.unique_commits, 1);
+        assert_eq!(size.insertions, 3);
+        assert_eq!(size.deletions, 0);
+        assert_eq!(size.to_string(), "1 commit, +3 -0");
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_count_loose_refs() {
+        let (path, repo) = init_test_repo("loose-refs");
+
+        assert_eq!(count_loose_refs(&repo).unwrap(), 1);
+        create_branch(&repo, "feature/a");
+        create_branch(&repo, "feature/b");
+        assert_eq!(count_loose_refs(&repo).unwrap(), 3);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_stash_branch_name() {
+        assert_eq!(
+            stash_branch_name("WIP on feature/login: a1b2c3d add form"),
+            Some("feature/login")
+        );
+        assert_eq!(stash_branch_name("On main: experiment"), Some("main"));
+        assert_eq!(
+            stash_branch_name("WIP on (no branch): a1b2c3d detached"),
+            None
+        );
+        assert_eq!(stash_branch_name("custom message"), None);
+    }
+
+    #[test]
+    fn test_list_stashed_branches() {
+        let (path, mut repo) = init_test_repo("stash");
+
+        create_branch(&repo, "no-stash");
+        commit_on_branch(&repo, "no-stash", "other work");
+        create_branch(&repo, "stashed");
+        repo.set_head("refs/heads/stashed").unwrap();
+
+        std::fs::write(path.join("file.txt"), "uncommitted").unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        repo.stash_save(&signature, "wip", Some(git2::StashFlags::INCLUDE_UNTRACKED))
+            .unwrap();
+
+        let stashed = list_stashed_branches(&repo).unwrap();
+        assert!(stashed.contains(&"stashed".to_string()));
+        assert!(!stashed.contains(&"no-stash".to_string()));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_detached_head() {
+        let (path, repo) = init_test_repo("detached");
+
+        let checked_out = get_checked_out_branches(&repo).unwrap();
+        assert_eq!(checked_out.len(), 1);
+        assert_eq!(checked_out[0].branch, "main");
+        assert_eq!(get_detached_head(&repo).unwrap(), None);
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.set_head_detached(head.id()).unwrap();
+
+        assert!(get_checked_out_branches(&repo).unwrap().is_empty());
+        let detached = get_detached_head(&repo).unwrap().unwrap();
+        assert!(head.id().to_string().starts_with(&detached));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_current_branch_unborn() {
+        let path =
+            std::env::temp_dir().join(format!("git-tidy-test-repo-unborn-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let repo = Repository::init_opts(&path, RepositoryInitOptions::new().initial_head("trunk"))
+            .unwrap();
+
+        let checked_out = get_checked_out_branches(&repo).unwrap();
+        assert_eq!(checked_out[0].branch, "trunk");
+        assert_eq!(get_detached_head(&repo).unwrap(), None);
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        assert!(targets.is_empty());
+        assert!(list_branches(&repo, &targets).unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_get_upstream_status() {
+        let (path, repo) = init_test_repo("upstream");
+
+        create_branch(&repo, "local-only");
+        create_branch(&repo, "tracking");
+        create_branch(&repo, "gone");
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.reference("refs/remotes/origin/tracking", head.id(), true, "test")
+            .unwrap();
+
+        let mut config = repo.config().unwrap();
+        config
+            .set_str("remote.origin.url", "https://example.com/repo.git")
+            .unwrap();
+        config
+            .set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")
+            .unwrap();
+        for name in ["tracking", "gone"] {
+            config
+                .set_str(&format!("branch.{}.remote", name), "origin")
+                .unwrap();
+            config
+                .set_str(
+                    &format!("branch.{}.merge", name),
+                    &format!("refs/heads/{}", name),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            get_upstream_status(&repo, "local-only").unwrap(),
+            UpstreamStatus::None
+        );
+        assert_eq!(
+            get_upstream_status(&repo, "tracking").unwrap(),
+            UpstreamStatus::Tracking
+        );
+        assert_eq!(
+            get_upstream_status(&repo, "gone").unwrap(),
+            UpstreamStatus::Gone
+        );
+        assert_eq!(get_branch_author(&repo, "tracking").unwrap(), "Test");
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_fetch_and_prune() {
+        let (server_path, server) = init_test_repo("fetch-server");
+        create_branch(&server, "feature");
+
+        let (path, repo) = init_test_repo("fetch-client");
+        repo.remote("origin", server_path.to_str().unwrap())
+            .unwrap();
+
+        let fetched = fetch_and_prune(&repo, &[], &CredentialsConfig::default()).unwrap();
+        assert_eq!(fetched, vec!["origin".to_string()]);
+        assert!(repo.find_reference("refs/remotes/origin/feature").is_ok());
+
+        server
+            .find_branch("feature", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        fetch_and_prune(&repo, &[], &CredentialsConfig::default()).unwrap();
+        assert!(repo.find_reference("refs/remotes/origin/feature").is_err());
+        assert!(repo.find_reference("refs/remotes/origin/main").is_ok());
+
+        let _ = std::fs::remove_dir_all(&server_path);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_has_description_marker() {
+        let (path, repo) = init_test_repo("description");
+        create_branch(&repo, "spike");
+        create_branch(&repo, "notes");
+        create_branch(&repo, "plain");
+
+        let mut config = repo.config().unwrap();
+        config
+            .set_str(
+                "branch.spike.description",
+                "Prototype for the demo\ngit-tidy: keep\n",
+            )
+            .unwrap();
+        config
+            .set_str("branch.notes.description", "Just some notes\n")
+            .unwrap();
+
+        assert!(has_description_marker(&repo, "spike", "git-tidy: keep").unwrap());
+        assert!(!has_description_marker(&repo, "notes", "git-tidy: keep").unwrap());
+        assert!(!has_description_marker(&repo, "plain", "git-tidy: keep").unwrap());
+        assert_eq!(
+            get_branch_description(&repo, "notes").unwrap().as_deref(),
+            Some("Just some notes\n")
+        );
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_get_remote_branches() {
+        let (path, repo) = init_test_repo("remote-branches");
+        repo.remote("origin", "https://example.com/origin.git")
+            .unwrap();
+        repo.remote("fork", "https://example.com/fork.git").unwrap();
+
+        let head = repo.head().unwrap().target().unwrap();
+        for name in [
+            "origin/feature",
+            "origin/renamed",
+            "fork/feature",
+            "fork/tracking",
+        ] {
+            repo.reference(&format!("refs/remotes/{}", name), head, true, "fetch")
+                .unwrap();
+        }
+        create_branch(&repo, "feature");
+        create_branch(&repo, "tracking");
+        repo.find_branch("tracking", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/renamed"))
+            .unwrap();
+
+        let names = |branch: &str, remotes: &[&str]| -> Vec<String> {
+            let remotes: Vec<String> = remotes.iter().map(|r| r.to_string()).collect();
+            get_remote_branches(&repo, branch, head, &remotes, &[])
+                .unwrap()
+                .iter()
+                .map(|r| r.to_string())
+                .collect()
+        };
+        assert_eq!(names("feature", &[]), vec!["origin/feature"]);
+        assert_eq!(
+            names("feature", &["fork", "origin"]),
+            vec!["fork/feature", "origin/feature"]
+        );
+        assert_eq!(names("tracking", &[]), vec!["origin/renamed"]);
+        assert_eq!(
+            names("tracking", &["origin", "fork"]),
+            vec!["origin/renamed", "fork/tracking"]
+        );
+        assert!(names("feature", &["upstream"]).is_empty());
+
+        // A same-named branch on origin with work the local branch lacks is
+        // someone else's, and is kept unless that work is merged.
+        create_branch(&repo, "diverged");
+        let theirs = commit_on_branch(&repo, "diverged", "their work");
+        repo.reference("refs/remotes/origin/diverged", theirs, true, "fetch")
+            .unwrap();
+        repo.reference("refs/heads/diverged", head, true, "reset")
+            .unwrap();
+        assert!(
+            get_remote_branches(&repo, "diverged", head, &[], &[])
+                .unwrap()
+                .is_empty()
+        );
+        let merged = get_remote_branches(&repo, "diverged", head, &[], &[theirs]).unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].tip, theirs);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_delete_remote_branch() {
+        // libgit2 can only push to bare repositories over the local transport.
+        let server_path = std::env::temp_dir().join(format!(
+            "git-tidy-test-repo-push-server-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&server_path);
+        let server = Repository::init_bare(&server_path).unwrap();
+
+        let (path, repo) = init_test_repo("push-client");
+        create_branch(&repo, "feature");
+        create_branch(&repo, "renamed");
+        create_branch(&repo, "moved");
+        repo.remote("origin", server_path.to_str().unwrap())
+            .unwrap()
+            .push(
+                &[
+                    "refs/heads/feature",
+                    "refs/heads/renamed",
+                    "refs/heads/moved",
+                ],
+                None,
+            )
+            .unwrap();
+        fetch_and_prune(&repo, &[], &CredentialsConfig::default()).unwrap();
+
+        create_branch(&repo, "local");
+        create_branch(&repo, "tracking");
+        repo.find_branch("tracking", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/renamed"))
+            .unwrap();
+
+        assert_eq!(get_remote_branch(&repo, "local").unwrap(), None);
+
+        let tracking = get_remote_branch(&repo, "tracking").unwrap().unwrap();
+        assert_eq!(tracking.to_string(), "origin/renamed");
+
+        let feature = get_remote_branch(&repo, "feature").unwrap().unwrap();
+        assert_eq!(feature.to_string(), "origin/feature");
+
+        let results =
+            delete_remote_branches(&repo, &[feature, tracking], &CredentialsConfig::default());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(server.find_branch("feature", BranchType::Local).is_err());
+        assert!(server.find_branch("renamed", BranchType::Local).is_err());
+        assert!(repo.find_reference("refs/remotes/origin/feature").is_err());
+        assert!(repo.find_reference("refs/remotes/origin/renamed").is_err());
+
+        // Someone pushed to "moved" after it was planned, so it is kept.
+        let moved = get_remote_branch(&repo, "moved").unwrap().unwrap();
+        let pushed = commit_on_branch(&repo, "moved", "pushed since");
+        repo.find_remote("origin")
+            .unwrap()
+            .push(&["refs/heads/moved"], None)
+            .unwrap();
+        let results = delete_remote_branches(&repo, &[moved], &CredentialsConfig::default());
+        assert!(matches!(
+            results[0],
+            Err(GitTidyError::TipMoved { actual, .. }) if actual == pushed
+        ));
+        assert!(server.find_branch("moved", BranchType::Local).is_ok());
+
+        let missing = RemoteBranch {
+            remote: "upstream".to_string(),
+            branch: "feature".to_string(),
+            tip: repo.head().unwrap().target().unwrap(),
+        };
+        let results = delete_remote_branches(&repo, &[missing], &CredentialsConfig::default());
+        assert!(matches!(results[0], Err(GitTidyError::Push { .. })));
+
+        let _ = std::fs::remove_dir_all(&server_path);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_resolve_merge_targets_remote() {
+        let (path, repo) = init_test_repo("remote-target");
+
+        create_branch(&repo, "feature");
+        let feature_tip = commit_on_branch(&repo, "feature", "feature work");
+        repo.reference("refs/remotes/origin/main", feature_tip, true, "test")
+            .unwrap();
+
+        let local_only = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        assert_eq!(local_only.len(), 1);
+        assert!(!is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &local_only
+        ));
+
+        let mut config = Config::new();
+        config.merge_remote = Some("origin".to_string());
+        let with_remote = resolve_merge_targets(&repo, &config).unwrap();
+        assert_eq!(with_remote.len(), 2);
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &with_remote
+        ));
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "main"),
+            &with_remote
+        ));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_resolve_merge_targets_default_branch() {
+        let (path, repo) = init_test_repo("default-branch");
+
+        create_branch(&repo, "trunk");
+        create_branch(&repo, "feature");
+        let feature_tip = commit_on_branch(&repo, "feature", "feature work");
+        repo.reference("refs/heads/trunk", feature_tip, true, "merge")
+            .unwrap();
+
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        assert!(!is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &targets
+        ));
+
+        repo.config()
+            .unwrap()
+            .set_str("init.defaultBranch", "trunk")
+            .unwrap();
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &targets
+        ));
+
+        // The remote's HEAD beats init.defaultBranch.
+        repo.config()
+            .unwrap()
+            .set_str("init.defaultBranch", "main")
+            .unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/trunk", feature_tip, true, "fetch")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/trunk",
+            true,
+            "clone",
+        )
+        .unwrap();
+        let targets = resolve_merge_targets(&repo, &Config::new()).unwrap();
+        assert_eq!(targets, vec![feature_tip]);
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_resolve_merge_targets_tag() {
+        let (path, repo) = init_test_repo("merge-tag");
+        let initial = branch_tip(&repo, "main");
+
+        create_branch(&repo, "feature");
+        create_branch(&repo, "wip");
+        let feature_tip = commit_on_branch(&repo, "feature", "feature work");
+        let wip_tip = commit_on_branch(&repo, "wip", "unreleased work");
+        repo.reference("refs/tags/v1.0", initial, false, "test")
+            .unwrap();
+        repo.reference("refs/tags/v1.1", feature_tip, false, "test")
+            .unwrap();
+        repo.reference("refs/tags/nightly", wip_tip, false, "test")
+            .unwrap();
+
+        let pattern = Pattern::new("v*").unwrap();
+        assert_eq!(
+            newest_matching_tag(&repo, &pattern).unwrap(),
+            Some(("v1.1".to_string(), feature_tip))
+        );
+
+        let mut config = Config::new();
+        config.merge_tag = Some("v*".to_string());
+        let targets = resolve_merge_targets(&repo, &config).unwrap();
+        assert_eq!(targets, vec![initial, feature_tip]);
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &targets
+        ));
+        assert!(!is_commit_merged(&repo, branch_tip(&repo, "wip"), &targets));
+
+        config.merge_tag = Some("release-*".to_string());
+        assert_eq!(
+            resolve_merge_targets(&repo, &config).unwrap(),
+            vec![initial]
+        );
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_list_branches_redundant() {
+        let (path, repo) = init_test_repo("redundant");
+        let config = Config::new();
+
+        create_branch(&repo, "at-main");
+        create_branch(&repo, "spike-b");
+        let spike_tip = commit_on_branch(&repo, "spike-b", "spike work");
+        repo.reference("refs/heads/spike-a", spike_tip, false, "test")
+            .unwrap();
+
+        let targets = resolve_merge_targets(&repo, &config).unwrap();
+        let branches = list_branches(&repo, &targets).unwrap();
+        let redundant = |name: &str| {
+            branches
+                .iter()
+                .find(|b| b.name == name)
+                .unwrap()
+                .redundant
+                .clone()
+        };
+
+        assert_eq!(redundant("at-main"), Some(Redundancy::MergeTarget));
+        assert_eq!(
+            redundant("spike-b"),
+            Some(Redundancy::Branch("spike-a".to_string()))
+        );
+        assert_eq!(redundant("spike-a"), None);
+
+        let is_empty = |name: &str| branches.iter().find(|b| b.name == name).unwrap().is_empty;
+        assert!(is_empty("at-main"));
+        assert!(!is_empty("spike-a"));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_resolve_merge_targets_multiple() {
+        let (path, repo) = init_test_repo("multiple-targets");
+
+        for name in ["develop", "release/1.0", "feature", "hotfix", "wip"] {
+            create_branch(&repo, name);
+        }
+        let feature_tip = commit_on_branch(&repo, "feature", "feature work");
+        let hotfix_tip = commit_on_branch(&repo, "hotfix", "hotfix work");
+        commit_on_branch(&repo, "wip", "unmerged work");
+        repo.reference("refs/heads/develop", feature_tip, true, "test")
+            .unwrap();
+        repo.reference("refs/heads/release/1.0", hotfix_tip, true, "test")
+            .unwrap();
+
+        let mut config = Config::new();
+        config.merge_targets = Some(vec!["develop".to_string(), "release/*".to_string()]);
+        let targets = resolve_merge_targets(&repo, &config).unwrap();
+
+        assert_eq!(targets.len(), 2);
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "feature"),
+            &targets
+        ));
+        assert!(is_commit_merged(
+            &repo,
+            branch_tip(&repo, "hotfix"),
+            &targets
+        ));
+        assert!(!is_commit_merged(&repo, branch_tip(&repo, "wip"), &targets));
+        assert!(config.is_merge_target("release/1.0").unwrap());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let names: Vec<String> = ["Feature", "feature", "fix", "FIX-2"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        assert_eq!(
+            case_collisions(&names),
+            vec![
+                ("Feature".to_string(), "feature".to_string()),
+                ("feature".to_string(), "Feature".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_branch() {
+        let (path, repo) = init_test_repo("rename");
+        create_branch(&repo, "my_feature");
+        create_branch(&repo, "taken");
+        let mut git_config = repo.config().unwrap();
+        git_config
+            .set_str("branch.my_feature.remote", "origin")
+            .unwrap();
+        git_config
+            .set_str("branch.my_feature.merge", "refs/heads/my_feature")
+            .unwrap();
+
+        rename_branch(&repo, "my_feature", "t/my-feature").unwrap();
+        assert!(repo.find_branch("my_feature", BranchType::Local).is_err());
+        let git_config = repo.config().unwrap().snapshot().unwrap();
+        assert_eq!(
+            git_config.get_str("branch.t/my-feature.remote").unwrap(),
+            "origin"
+        );
+        assert_eq!(
+            git_config.get_str("branch.t/my-feature.merge").unwrap(),
+            "refs/heads/my_feature"
+        );
+        assert!(git_config.get_str("branch.my_feature.remote").is_err());
+
+        assert!(matches!(
+            rename_branch(&repo, "t/my-feature", "taken"),
+            Err(GitTidyError::BranchExists(_))
+        ));
+
+        // taken/x would need taken to be a folder, and t/my-feature/x can
+        // only be made by moving t/my-feature itself.
+        assert!(matches!(
+            rename_branch(&repo, "t/my-feature", "taken/x"),
+            Err(GitTidyError::RefConflict { existing, .. }) if existing == "refs/heads/taken"
+        ));
+        assert!(matches!(
+            rename_branch(&repo, "taken", "t"),
+            Err(GitTidyError::RefConflict { existing, .. }) if existing == "refs/heads/t/my-feature"
+        ));
+        rename_branch(&repo, "t/my-feature", "t/my-feature/x").unwrap();
+        assert!(refs_conflict("feature", "feature/x"));
+        assert!(!refs_conflict("feature", "feature-x"));
+
+        let _ = std::fs::remove_dir_all(path);
     }
 }
@@ -0,0 +1,273 @@
+use chrono::{DateTime, Utc};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{GitTidyError, Result};
+use crate::git_operations::common_dir;
+use crate::tidy::{Report, TidyPlan};
+
+const HISTORY_FILE_NAME: &str = "git-tidy-history.jsonl";
+
+/// What one `--clean` run removed, as appended to the repository's history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupRecord {
+    pub at: DateTime<Utc>,
+    pub branches: Vec<CleanedBranch>,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanedBranch {
+    pub name: String,
+    pub owner: String,
+    /// Moved to the archive namespace instead of deleted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// The remotes the branch was deleted from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<String>,
+}
+
+/// Removed branches counted by month, owner, and name prefix, each most
+/// common first apart from the months, which are in order.
+#[derive(Debug, Default, PartialEq)]
+pub struct HistorySummary {
+    pub runs: usize,
+    pub branches: usize,
+    pub failed: usize,
+    /// As `2024-06`.
+    pub by_month: Vec<(String, usize)>,
+    pub by_owner: Vec<(String, usize)>,
+    /// Up to and including the first `/`, or "(no prefix)".
+    pub by_prefix: Vec<(String, usize)>,
+}
+
+impl CleanupRecord {
+    /// The planned branches `report` says were removed, locally or on a
+    /// remote, with their owners.
+    pub fn new(plan: &TidyPlan, report: &Report, at: DateTime<Utc>) -> Self {
+        let branches = plan
+            .to_delete()
+            .iter()
+            .filter_map(|deletion| {
+                let name = &deletion.branch.name;
+                let remotes: Vec<String> = deletion
+                    .remote_branches
+                    .iter()
+                    .filter(|remote| report.remote_deleted.contains(&remote.to_string()))
+                    .map(|remote| remote.remote.clone())
+                    .collect();
+                let archived = report.archived.contains(name);
+                if !archived && !report.deleted.contains(name) && remotes.is_empty() {
+                    return None;
+                }
+                Some(CleanedBranch {
+                    name: name.clone(),
+                    owner: deletion.branch.owner.clone(),
+                    archived,
+                    remotes,
+                })
+            })
+            .collect();
+
+        CleanupRecord {
+            at,
+            branches,
+            failed: report.failed.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty() && self.failed == 0
+    }
+}
+
+/// Where a repository's cleanup history is kept, next to the lock file and
+/// shared by its worktrees.
+pub fn history_path(repo: &Repository) -> Result<PathBuf> {
+    Ok(common_dir(repo)?.join(HISTORY_FILE_NAME))
+}
+
+/// Appends a run to the repository's history, unless it removed nothing.
+pub fn record_cleanup(repo: &Repository, record: &CleanupRecord) -> Result<()> {
+    if record.is_empty() {
+        return Ok(());
+    }
+
+    append_record(&history_path(repo)?, record)
+}
+
+fn append_record(path: &Path, record: &CleanupRecord) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+
+    Ok(())
+}
+
+/// The recorded runs at or after `since`, oldest first. A repository
+/// without history has none.
+pub fn read_history(path: &Path, since: Option<DateTime<Utc>>) -> Result<Vec<CleanupRecord>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    for (index, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: CleanupRecord =
+            serde_json::from_str(line).map_err(|source| GitTidyError::HistoryParse {
+                path: path.to_path_buf(),
+                line: index + 1,
+                source,
+            })?;
+        if since.is_none_or(|since| record.at >= since) {
+            records.push(record);
+        }
+    }
+    records.sort_by_key(|record| record.at);
+
+    Ok(records)
+}
+
+pub fn summarize(records: &[CleanupRecord]) -> HistorySummary {
+    let mut by_month: HashMap<String, usize> = HashMap::new();
+    let mut by_owner: HashMap<String, usize> = HashMap::new();
+    let mut by_prefix: HashMap<String, usize> = HashMap::new();
+
+    for record in records {
+        let month = record.at.format("%Y-%m").to_string();
+        for branch in &record.branches {
+            *by_month.entry(month.clone()).or_default() += 1;
+            *by_owner.entry(branch.owner.clone()).or_default() += 1;
+            let prefix = branch
+                .name
+                .split_once('/')
+                .map_or("(no prefix)".to_string(), |(prefix, _)| {
+                    format!("{}/", prefix)
+                });
+            *by_prefix.entry(prefix).or_default() += 1;
+        }
+    }
+
+    let mut by_month: Vec<(String, usize)> = by_month.into_iter().collect();
+    by_month.sort();
+
+    HistorySummary {
+        runs: records.len(),
+        branches: records.iter().map(|r| r.branches.len()).sum(),
+        failed: records.iter().map(|r| r.failed).sum(),
+        by_month,
+        by_owner: most_common(by_owner),
+        by_prefix: most_common(by_prefix),
+    }
+}
+
+fn most_common(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn record(at: DateTime<Utc>, branches: &[(&str, &str)]) -> CleanupRecord {
+        CleanupRecord {
+            at,
+            branches: branches
+                .iter()
+                .map(|(name, owner)| CleanedBranch {
+                    name: name.to_string(),
+                    owner: owner.to_string(),
+                    archived: false,
+                    remotes: Vec::new(),
+                })
+                .collect(),
+            failed: 0,
+        }
+    }
+
+    #[test]
+    fn test_append_and_read_history() {
+        let path = std::env::temp_dir().join(format!(
+            "git-tidy-test-history-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        assert!(read_history(&path, None).unwrap().is_empty());
+
+        let june = Utc.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap();
+        let july = Utc.with_ymd_and_hms(2024, 7, 1, 9, 0, 0).unwrap();
+        append_record(&path, &record(july, &[("feature/b", "bob@example.com")])).unwrap();
+        append_record(&path, &record(june, &[("feature/a", "ann@example.com")])).unwrap();
+
+        let records = read_history(&path, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].at, june);
+        assert_eq!(records[0].branches[0].name, "feature/a");
+
+        let records = read_history(&path, Some(july)).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].branches[0].owner, "bob@example.com");
+
+        std::fs::write(&path, "{\"at\":\n").unwrap();
+        assert!(matches!(
+            read_history(&path, None),
+            Err(GitTidyError::HistoryParse { line: 1, .. })
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let june = Utc.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap();
+        let july = Utc.with_ymd_and_hms(2024, 7, 1, 9, 0, 0).unwrap();
+        let records = [
+            record(
+                june,
+                &[
+                    ("feature/a", "ann@example.com"),
+                    ("fix/b", "bob@example.com"),
+                ],
+            ),
+            record(
+                july,
+                &[("feature/c", "bob@example.com"), ("wip", "bob@example.com")],
+            ),
+        ];
+
+        let summary = summarize(&records);
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.branches, 4);
+        assert_eq!(
+            summary.by_month,
+            vec![("2024-06".to_string(), 2), ("2024-07".to_string(), 2)]
+        );
+        assert_eq!(
+            summary.by_owner,
+            vec![
+                ("bob@example.com".to_string(), 3),
+                ("ann@example.com".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            summary.by_prefix,
+            vec![
+                ("feature/".to_string(), 2),
+                ("(no prefix)".to_string(), 1),
+                ("fix/".to_string(), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use chrono::Utc;
+use git2::{ObjectType, Oid};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::error::GitTidyError;
+
+const MAX_ATTEMPTS: u32 = 4;
+
+// Longer waits, such as for an exhausted hourly rate limit, fail the request
+// instead of stalling the run.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Answers requests only from the cache of earlier responses (--offline).
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Why a GET failed after its retries.
+#[derive(Debug)]
+pub enum RequestError {
+    Status(u16),
+    Transport(String),
+    /// Offline, with no cached response to answer from.
+    Offline,
+}
+
+impl RequestError {
+    pub fn into_error(self, url: &str) -> GitTidyError {
+        let message = match self {
+            RequestError::Status(code) => format!("status code {}", code),
+            RequestError::Transport(message) => message,
+            RequestError::Offline => return GitTidyError::Offline(url.to_string()),
+        };
+        GitTidyError::Provider {
+            url: url.to_string(),
+            message,
+        }
+    }
+}
+
+// The last response to a request, revalidated with its ETag so an unchanged
+// page costs neither a download nor, on GitHub, any of the rate limit.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cached {
+    etag: Option<String>,
+    body: String,
+}
+
+/// GETs `url` with `headers` (e.g. authorization) and returns the body.
+/// Transport errors, server errors, and rate limits, including GitHub's
+/// secondary rate limit, are retried with exponential backoff or after the
+/// wait the server asks for.
+pub fn get(url: &str, headers: &[(&str, &str)]) -> Result<String, RequestError> {
+    let cache = cache_path(url, headers);
+    let cached = cache.as_deref().and_then(read_cached);
+
+    if is_offline() {
+        debug!("Offline, answering GET {} from the cache", url);
+        return cached.map(|c| c.body).ok_or(RequestError::Offline);
+    }
+
+    let mut attempt = 1;
+    loop {
+        debug!("GET {}", url);
+        let mut request = ureq::get(url)
+            .timeout(Duration::from_secs(10))
+            .set(
+                "User-Agent",
+                concat!("git-tidy/", env!("CARGO_PKG_VERSION")),
+            )
+            .set("Accept", "application/json");
+        for (header, value) in headers {
+            request = request.set(header, value);
+        }
+        if let Some(etag) = cached.as_ref().and_then(|c| c.etag.as_deref()) {
+            request = request.set("If-None-Match", etag);
+        }
+
+        let wait = match request.call() {
+            Ok(response) if response.status() == 304 => {
+                debug!("{} is unchanged", url);
+                if let Some(cached) = cached {
+                    return Ok(cached.body);
+                }
+                return Err(RequestError::Status(304));
+            }
+            Ok(response) => {
+                let etag = response.header("ETag").map(str::to_string);
+                let body = response
+                    .into_string()
+                    .map_err(|e| RequestError::Transport(e.to_string()))?;
+                if let Some(path) = &cache {
+                    let cached = Cached { etag, body };
+                    if let Err(e) = write_cached(path, &cached) {
+                        debug!("Could not cache the response from {}: {}", url, e);
+                    }
+                    return Ok(cached.body);
+                }
+                return Ok(body);
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                let wait = retry_delay(
+                    code,
+                    |name| response.header(name).map(str::to_string),
+                    attempt,
+                    Utc::now().timestamp(),
+                );
+                match wait {
+                    Some(wait) if attempt < MAX_ATTEMPTS => wait,
+                    _ => return Err(RequestError::Status(code)),
+                }
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                let message = match transport.message() {
+                    Some(message) => format!("{}: {}", transport.kind(), message),
+                    None => transport.kind().to_string(),
+                };
+                if attempt == MAX_ATTEMPTS {
+                    return Err(RequestError::Transport(message));
+                }
+                debug!("GET {} failed: {}", url, message);
+                backoff(attempt)
+            }
+        };
+
+        debug!("Retrying {} in {}s", url, wait.as_secs());
+        std::thread::sleep(wait);
+        attempt += 1;
+    }
+}
+
+// 1s, 2s, 4s, ...
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << (attempt - 1).min(5))
+}
+
+// How long to wait before retrying a response with this status, or None if
+// it should not be retried. `header` looks up the response's headers and
+// `now` is the current Unix time.
+fn retry_delay(
+    code: u16,
+    header: impl Fn(&str) -> Option<String>,
+    attempt: u32,
+    now: i64,
+) -> Option<Duration> {
+    let retry_after = header("Retry-After").and_then(|value| value.trim().parse::<u64>().ok());
+    let exhausted = header("X-RateLimit-Remaining").is_some_and(|value| value.trim() == "0");
+    // GitHub answers rate limits with 403, its secondary limit with a
+    // Retry-After header.
+    let rate_limited = code == 429 || (code == 403 && (retry_after.is_some() || exhausted));
+    if !rate_limited && !matches!(code, 500 | 502 | 503 | 504) {
+        return None;
+    }
+
+    let wait = if let Some(seconds) = retry_after {
+        Duration::from_secs(seconds)
+    } else if exhausted {
+        let reset = header("X-RateLimit-Reset")?.trim().parse::<i64>().ok()?;
+        Duration::from_secs((reset - now).max(1) as u64)
+    } else {
+        backoff(attempt)
+    };
+
+    (wait <= MAX_WAIT).then_some(wait)
+}
+
+// Keyed by the URL and headers, so responses for different tokens are kept
+// apart, and the token itself is not written anywhere.
+fn cache_path(url: &str, headers: &[(&str, &str)]) -> Option<PathBuf> {
+    let mut key = url.to_string();
+    for (header, value) in headers {
+        key.push_str(&format!("\n{}: {}", header, value));
+    }
+    let id = Oid::hash_object(ObjectType::Blob, key.as_bytes()).ok()?;
+
+    let mut path = dirs::cache_dir()?;
+    path.push("git-tidy");
+    path.push("http");
+    path.push(format!("{}.json", id));
+    Some(path)
+}
+
+fn read_cached(path: &Path) -> Option<Cached> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_cached(path: &Path, cached: &Cached) -> crate::error::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(cached)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers<'a>(pairs: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            pairs
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let now = 1_700_000_000;
+
+        assert_eq!(retry_delay(404, headers(&[]), 1, now), None);
+        assert_eq!(retry_delay(403, headers(&[]), 1, now), None);
+        assert_eq!(
+            retry_delay(502, headers(&[]), 1, now),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            retry_delay(503, headers(&[]), 3, now),
+            Some(Duration::from_secs(4))
+        );
+        // GitHub's secondary rate limit.
+        assert_eq!(
+            retry_delay(403, headers(&[("retry-after", "30")]), 1, now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_delay(
+                403,
+                headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700000020")
+                ]),
+                1,
+                now
+            ),
+            Some(Duration::from_secs(20))
+        );
+        // An hour's wait for the primary limit is not worth it.
+        assert_eq!(
+            retry_delay(
+                403,
+                headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700003600")
+                ]),
+                1,
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            retry_delay(429, headers(&[]), 2, now),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let plain = cache_path("https://api.github.com/x", &[]);
+        let authorized = cache_path(
+            "https://api.github.com/x",
+            &[("Authorization", "Bearer secret")],
+        );
+
+        assert_ne!(plain, authorized);
+        assert!(!authorized.unwrap().to_string_lossy().contains("secret"));
+    }
+}
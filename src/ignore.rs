@@ -0,0 +1,156 @@
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+use crate::error::{GitTidyError, Result};
+
+// Like gitignore, `*` and `?` stop at `/` and `**` crosses it.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Branches that are never deletion candidates, from `.git-tidy-ignore`
+/// files written like `.gitignore`, with branch names as paths.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    // A trailing `/` only matches the prefix of longer names.
+    prefix_only: bool,
+    // A `/` before the end ties the pattern to the start of the name;
+    // otherwise it matches any segment, e.g. `wip` matches `alice/wip`.
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<std::result::Result<Self, glob::PatternError>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (prefix_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Pattern::new(line).map(|pattern| IgnoreRule {
+            pattern,
+            negated,
+            prefix_only,
+            anchored,
+        }))
+    }
+
+    fn matches(&self, branch_name: &str) -> bool {
+        let segments: Vec<&str> = branch_name.split('/').collect();
+        // The pattern may match the whole name, or the part of it up to the
+        // end of a segment, which then covers everything below.
+        let mut ends =
+            (1..=segments.len()).filter(|&end| !self.prefix_only || end < segments.len());
+
+        if self.anchored {
+            ends.any(|end| {
+                self.pattern
+                    .matches_with(&segments[..end].join("/"), MATCH_OPTIONS)
+            })
+        } else {
+            ends.any(|end| self.pattern.matches_with(segments[end - 1], MATCH_OPTIONS))
+        }
+    }
+}
+
+impl IgnoreList {
+    /// Parses one file's patterns; `path` only names it in errors.
+    pub fn parse(contents: &str, path: &Path) -> Result<Self> {
+        let mut list = IgnoreList::default();
+        list.extend(contents, path)?;
+        Ok(list)
+    }
+
+    /// Adds the patterns of another file, which take precedence over the
+    /// ones already there.
+    pub fn extend(&mut self, contents: &str, path: &Path) -> Result<()> {
+        for (index, line) in contents.lines().enumerate() {
+            match IgnoreRule::parse(line) {
+                Some(Ok(rule)) => self.rules.push(rule),
+                Some(Err(e)) => {
+                    return Err(GitTidyError::InvalidIgnorePattern {
+                        path: path.to_path_buf(),
+                        line: index + 1,
+                        pattern: line.trim_end().to_string(),
+                        message: e.msg.to_string(),
+                    });
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the last pattern matching `branch_name` ignores it (rather
+    /// than re-including it with `!`).
+    pub fn is_ignored(&self, branch_name: &str) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(branch_name))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let list = IgnoreList::parse(
+            "# personal branches\n\
+             \n\
+             wip\n\
+             spike-*\n\
+             experiments/\n\
+             /release/*\n\
+             !release/keep-me\n\
+             docs/**/draft\n",
+            Path::new(".git-tidy-ignore"),
+        )
+        .unwrap();
+
+        assert!(list.is_ignored("wip"));
+        assert!(list.is_ignored("alice/wip"));
+        assert!(list.is_ignored("wip/login"));
+        assert!(!list.is_ignored("wip-login"));
+        assert!(list.is_ignored("bob/spike-cache"));
+        assert!(list.is_ignored("experiments/a/b"));
+        assert!(!list.is_ignored("experiments"));
+        assert!(list.is_ignored("release/1.0"));
+        assert!(!list.is_ignored("old/release/1.0"));
+        assert!(!list.is_ignored("release/keep-me"));
+        assert!(list.is_ignored("docs/draft"));
+        assert!(list.is_ignored("docs/api/v2/draft"));
+        assert!(!list.is_ignored("feature/login"));
+
+        let err = IgnoreList::parse("ok\n[oops\n", Path::new("ignore")).unwrap_err();
+        assert!(matches!(
+            err,
+            GitTidyError::InvalidIgnorePattern { line: 2, .. }
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use colored::Colorize;
+use dialoguer::console::Term;
+
+static DELETING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Marks the deletion loop as running until dropped.
+pub struct Deleting(());
+
+impl Drop for Deleting {
+    fn drop(&mut self) {
+        DELETING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Installs the Ctrl-C handler. Outside [`deleting`] an interrupt exits at
+/// once, as it would without a handler. During it, the first interrupt lets
+/// the current branch finish and the loop stop, and a second one exits.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if DELETING.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "\n{}",
+                "Stopping after the current branch. Press Ctrl-C again to quit now.".yellow()
+            );
+            return;
+        }
+
+        // A progress bar or prompt may have hidden the cursor.
+        let _ = Term::stderr().show_cursor();
+        std::process::exit(130);
+    })
+}
+
+pub fn deleting() -> Deleting {
+    DELETING.store(true, Ordering::SeqCst);
+    Deleting(())
+}
+
+/// Whether Ctrl-C was pressed during the deletion loop.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
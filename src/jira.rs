@@ -0,0 +1,104 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+
+use crate::config::JiraConfig;
+use crate::error::{GitTidyError, Result};
+use crate::http::{RequestError, get};
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    fields: IssueFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueFields {
+    status: Status,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    #[serde(rename = "statusCategory")]
+    category: StatusCategory,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusCategory {
+    key: String,
+}
+
+impl Issue {
+    // Jira maps every workflow status to "new", "indeterminate", or "done".
+    fn is_open(&self) -> bool {
+        self.fields.status.category.key != "done"
+    }
+}
+
+/// The keys among `keys` whose issues are not done. Issues that do not exist,
+/// or that the token cannot see, are not open.
+pub fn open_issues(config: &JiraConfig, keys: &[String]) -> Result<Vec<String>> {
+    let Some(base) = &config.url else {
+        return Ok(Vec::new());
+    };
+    let base = base.trim_end_matches('/');
+
+    let token_env = config.token_env();
+    let token = std::env::var(token_env)
+        .map_err(|_| GitTidyError::MissingJiraToken(token_env.to_string()))?;
+    // Jira Cloud takes its API tokens as a password; Data Center personal
+    // access tokens are bearer tokens.
+    let authorization = match &config.email {
+        Some(email) => format!("Basic {}", STANDARD.encode(format!("{}:{}", email, token))),
+        None => format!("Bearer {}", token),
+    };
+
+    let mut open = Vec::new();
+    for key in keys {
+        if get_issue(base, key, &authorization)?.is_some_and(|issue| issue.is_open()) {
+            open.push(key.clone());
+        }
+    }
+
+    Ok(open)
+}
+
+// One request per issue: a JQL search for several keys fails outright if any
+// of them does not exist.
+fn get_issue(base: &str, key: &str, authorization: &str) -> Result<Option<Issue>> {
+    let url = format!("{}/rest/api/2/issue/{}?fields=status", base, key);
+
+    let body = match get(&url, &[("Authorization", authorization)]) {
+        Ok(body) => body,
+        Err(RequestError::Status(404)) => return Ok(None),
+        Err(e) => return Err(e.into_error(&url)),
+    };
+
+    serde_json::from_str(&body)
+        .map(Some)
+        .map_err(|e| GitTidyError::Provider {
+            url: url.clone(),
+            message: e.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_status() {
+        let issue: Issue = serde_json::from_str(
+            r#"{"key": "ABC-1", "fields": {"status": {"name": "In Review",
+                "statusCategory": {"id": 4, "key": "indeterminate"}}}}"#,
+        )
+        .unwrap();
+        assert!(issue.is_open());
+
+        let issue: Issue = serde_json::from_str(
+            r#"{"key": "ABC-2", "fields": {"status": {"name": "Closed",
+                "statusCategory": {"id": 3, "key": "done"}}}}"#,
+        )
+        .unwrap();
+        assert!(!issue.is_open());
+    }
+}
@@ -1,3 +1,37 @@
 pub mod config;
+pub mod credentials;
+pub mod doctor;
+pub mod document;
+pub mod error;
 pub mod filters;
 pub mod git_operations;
+pub mod history;
+#[cfg(feature = "providers")]
+pub mod http;
+pub mod ignore;
+#[cfg(feature = "cli")]
+pub mod interrupt;
+#[cfg(feature = "providers")]
+pub mod jira;
+pub mod lint;
+pub mod lock;
+pub mod metrics;
+#[cfg(feature = "providers")]
+pub mod notify;
+pub mod output;
+#[cfg(feature = "cli")]
+pub mod pager;
+#[cfg(feature = "cli")]
+pub mod progress;
+#[cfg(feature = "cli")]
+pub mod prompt;
+#[cfg(feature = "providers")]
+pub mod provider;
+pub mod pull_requests;
+pub mod stats;
+pub mod style;
+#[cfg(test)]
+mod test_support;
+pub mod tidy;
+#[cfg(feature = "providers")]
+pub mod tokens;
@@ -0,0 +1,473 @@
+use regex::Regex;
+
+use crate::config::{Config, NamingConfig};
+use crate::error::{GitTidyError, Result};
+use crate::git_operations::{BranchInfo, CheckedOutBranch, refs_conflict};
+
+const RENAME_FIELDS: [&str; 3] = ["name", "initials", "owner"];
+
+/// The config's `[naming]` conventions, ready to check branch names against.
+#[derive(Debug, Default)]
+pub struct NamingRules {
+    prefixes: Vec<String>,
+    max_length: Option<usize>,
+    forbidden_chars: Vec<char>,
+    pattern: Option<Regex>,
+}
+
+/// A branch whose name breaks one or more conventions, with what is wrong.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub branch: String,
+    pub problems: Vec<String>,
+}
+
+/// What `lint` found.
+#[derive(Debug, Default)]
+pub struct Lint {
+    /// How many names were checked, leaving out protected branches.
+    pub checked: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl NamingRules {
+    pub fn new(config: &NamingConfig) -> Result<Self> {
+        let pattern = config
+            .pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| GitTidyError::InvalidRegex {
+                    pattern: pattern.to_string(),
+                    message: e.to_string(),
+                })
+            })
+            .transpose()?;
+
+        Ok(NamingRules {
+            prefixes: config.prefixes.clone().unwrap_or_default(),
+            max_length: config.max_length,
+            forbidden_chars: config
+                .forbidden_chars
+                .as_deref()
+                .unwrap_or_default()
+                .chars()
+                .collect(),
+            pattern,
+        })
+    }
+
+    /// Whether no convention is configured, so every name passes.
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+            && self.max_length.is_none()
+            && self.forbidden_chars.is_empty()
+            && self.pattern.is_none()
+    }
+
+    /// What is wrong with `name`, e.g. "longer than 40 characters (52)".
+    pub fn check(&self, name: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !self.prefixes.is_empty() && !self.prefixes.iter().any(|p| name.starts_with(p)) {
+            problems.push(format!("does not start with {}", self.prefixes.join(", ")));
+        }
+
+        let length = name.chars().count();
+        if let Some(max_length) = self.max_length
+            && length > max_length
+        {
+            problems.push(format!(
+                "longer than {} characters ({})",
+                max_length, length
+            ));
+        }
+
+        let mut forbidden: Vec<char> = Vec::new();
+        for c in name.chars().filter(|c| self.forbidden_chars.contains(c)) {
+            if !forbidden.contains(&c) {
+                forbidden.push(c);
+            }
+        }
+        if !forbidden.is_empty() {
+            let forbidden: Vec<String> = forbidden.iter().map(|c| format!("{:?}", c)).collect();
+            problems.push(format!("contains {}", forbidden.join(", ")));
+        }
+
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(name)
+        {
+            problems.push(format!("does not match {}", pattern));
+        }
+
+        problems
+    }
+}
+
+/// A `[naming]` `rename` template such as `{initials}/{name}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameTemplate {
+    template: String,
+}
+
+/// A new name for a branch breaking the conventions, or why it gets none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+    /// Set when the branch is left alone, e.g. "feature/x already exists".
+    pub skipped: Option<String>,
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid = |message: String| GitTidyError::InvalidRenameTemplate {
+            template: template.to_string(),
+            message,
+        };
+
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let after = &rest[start + 1..];
+            let end = after
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'".to_string()))?;
+            let field = &after[..end];
+            if !RENAME_FIELDS.contains(&field) {
+                return Err(invalid(format!(
+                    "unknown field '{{{}}}'. Available: {{{}}}",
+                    field,
+                    RENAME_FIELDS.join("}, {")
+                )));
+            }
+            rest = &after[end + 1..];
+        }
+        if !template.contains("{name}") {
+            return Err(invalid("it must contain {name}".to_string()));
+        }
+
+        Ok(RenameTemplate {
+            template: template.to_string(),
+        })
+    }
+
+    /// The new name for `branch`. Characters the rules forbid become `-`.
+    pub fn render(&self, branch: &BranchInfo, rules: &NamingRules) -> String {
+        let initials: String = branch
+            .last_commit_author
+            .split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let owner = branch.owner.split('@').next().unwrap_or_default();
+        let name: String = branch
+            .name
+            .chars()
+            .map(|c| {
+                if rules.forbidden_chars.contains(&c) {
+                    '-'
+                } else {
+                    c
+                }
+            })
+            .collect();
+
+        // `{name}` goes last, so braces in a branch name stay as they are.
+        self.template
+            .replace("{initials}", &initials)
+            .replace("{owner}", owner)
+            .replace("{name}", &name)
+    }
+}
+
+/// New names for the branches breaking the rules, from `template`. A
+/// branch is skipped when its new name would still break them, is not a
+/// valid branch name, or is taken by another branch or an earlier rename,
+/// and when it is checked out in another worktree.
+pub fn plan_renames(
+    branches: &[BranchInfo],
+    rules: &NamingRules,
+    template: &RenameTemplate,
+    config: &Config,
+    checked_out: &[CheckedOutBranch],
+) -> Result<Vec<Rename>> {
+    let names: Vec<String> = branches.iter().map(|b| b.name.clone()).collect();
+    let lint = lint(&names, rules, config)?;
+
+    let mut taken: Vec<String> = names.clone();
+    // Branches that may be renamed out of the way of another's new name.
+    let moving: Vec<&str> = lint.violations.iter().map(|v| v.branch.as_str()).collect();
+    let mut renames = Vec::new();
+    for violation in &lint.violations {
+        let Some(branch) = branches.iter().find(|b| b.name == violation.branch) else {
+            continue;
+        };
+        let to = template.render(branch, rules);
+        let problems = rules.check(&to);
+        // Renaming only moves this worktree's HEAD along.
+        let checkout = checked_out
+            .iter()
+            .find(|c| c.branch == branch.name && !c.is_current);
+
+        let skipped = if let Some(checkout) = checkout {
+            Some(format!("checked out in {}", checkout.checkout))
+        } else if to == branch.name {
+            Some("the template leaves it unchanged".to_string())
+        } else if !git2::Branch::name_is_valid(&to).unwrap_or(false) {
+            Some("not a valid branch name".to_string())
+        } else if !problems.is_empty() {
+            Some(format!("would still break: {}", problems.join("; ")))
+        } else if taken.contains(&to) {
+            Some(format!("{} already exists", to))
+        } else if let Some(other) = taken
+            .iter()
+            .find(|name| refs_conflict(name, &to) && !moving.contains(&name.as_str()))
+        {
+            Some(format!("{} is in the way", other))
+        } else {
+            taken.push(to.clone());
+            None
+        };
+        renames.push(Rename {
+            from: branch.name.clone(),
+            to,
+            skipped,
+        });
+    }
+
+    order_renames(&mut renames);
+    Ok(renames)
+}
+
+// A branch can only be renamed onto a name, or into a folder, that another
+// branch occupies once that branch has moved, e.g. `fix/a` to `fix-a`
+// before `fix` to `fix/b`. Renames are put in that order; one whose way a
+// staying branch, or a cycle, blocks is skipped. Skipped renames keep their
+// place.
+fn order_renames(renames: &mut [Rename]) {
+    loop {
+        let blocked = renames
+            .iter()
+            .enumerate()
+            .filter(|(_, rename)| rename.skipped.is_none())
+            .find_map(|(i, rename)| {
+                renames
+                    .iter()
+                    .find(|other| {
+                        other.skipped.is_some()
+                            && other.from != rename.from
+                            && refs_conflict(&other.from, &rename.to)
+                    })
+                    .map(|staying| (i, staying.from.clone()))
+            });
+        match blocked {
+            Some((i, other)) => renames[i].skipped = Some(format!("{} is in the way", other)),
+            None => break,
+        }
+    }
+
+    let slots: Vec<usize> = (0..renames.len())
+        .filter(|&i| renames[i].skipped.is_none())
+        .collect();
+    let mut pending = slots.clone();
+    let mut order = Vec::new();
+    while let Some(ready) = pending.iter().position(|&i| {
+        !pending
+            .iter()
+            .any(|&j| j != i && refs_conflict(&renames[j].from, &renames[i].to))
+    }) {
+        order.push(pending.remove(ready));
+    }
+    // What is left waits on each other.
+    for &i in &pending {
+        if let Some(&j) = pending
+            .iter()
+            .find(|&&j| j != i && refs_conflict(&renames[j].from, &renames[i].to))
+        {
+            renames[i].skipped = Some(format!("{} is in the way", renames[j].from));
+        }
+    }
+
+    let ordered: Vec<Rename> = order.iter().map(|&i| renames[i].clone()).collect();
+    let free = slots.into_iter().filter(|i| !pending.contains(i));
+    for (slot, rename) in free.zip(ordered) {
+        renames[slot] = rename;
+    }
+}
+
+/// Checks each name against the rules, skipping branches the config
+/// protects (such as `main`), which predate any convention.
+pub fn lint(names: &[String], rules: &NamingRules, config: &Config) -> Result<Lint> {
+    let protected_patterns = config.get_protected_patterns()?;
+    let is_protected = |name: &str| {
+        config.get_protected_branches().iter().any(|p| p == name)
+            || config.is_protected(name)
+            || (protected_patterns.iter().any(|p| p.is_match(name)) && !config.is_unprotected(name))
+    };
+
+    let mut lint = Lint::default();
+    for name in names.iter().filter(|name| !is_protected(name)) {
+        lint.checked += 1;
+        let problems = rules.check(name);
+        if !problems.is_empty() {
+            lint.violations.push(Violation {
+                branch: name.clone(),
+                problems,
+            });
+        }
+    }
+
+    Ok(lint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let mut config = Config::new();
+        config.naming = NamingConfig {
+            prefixes: Some(vec!["feature/".to_string(), "fix/".to_string()]),
+            max_length: Some(20),
+            forbidden_chars: Some("_ ".to_string()),
+            pattern: Some("^[a-z0-9/_ -]+$".to_string()),
+            rename: None,
+        };
+        let rules = NamingRules::new(&config.naming).unwrap();
+        assert!(!rules.is_empty());
+
+        let names: Vec<String> = [
+            "main",
+            "feature/login",
+            "Feature/Login",
+            "fix/a_b_c",
+            "feature/a-very-long-branch-name",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        let lint = lint(&names, &rules, &config).unwrap();
+
+        assert_eq!(lint.checked, 4);
+        assert_eq!(
+            lint.violations,
+            vec![
+                Violation {
+                    branch: "Feature/Login".to_string(),
+                    problems: vec![
+                        "does not start with feature/, fix/".to_string(),
+                        "does not match ^[a-z0-9/_ -]+$".to_string(),
+                    ],
+                },
+                Violation {
+                    branch: "fix/a_b_c".to_string(),
+                    problems: vec!["contains '_'".to_string()],
+                },
+                Violation {
+                    branch: "feature/a-very-long-branch-name".to_string(),
+                    problems: vec!["longer than 20 characters (31)".to_string()],
+                },
+            ]
+        );
+
+        assert!(
+            NamingRules::new(&NamingConfig::default())
+                .unwrap()
+                .is_empty()
+        );
+        let invalid = NamingConfig {
+            pattern: Some("[oops".to_string()),
+            ..NamingConfig::default()
+        };
+        assert!(matches!(
+            NamingRules::new(&invalid),
+            Err(GitTidyError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_plan_renames() {
+        let branch = |name: &str, author: &str| BranchInfo {
+            name: name.to_string(),
+            tip: git2::Oid::zero(),
+            is_merged: false,
+            merged_at: None,
+            last_commit_date: chrono::Utc::now(),
+            last_commit_subject: String::new(),
+            last_commit_author: author.to_string(),
+            short_sha: String::new(),
+            remote: None,
+            redundant: None,
+            is_empty: false,
+            is_orphan: false,
+            owner: "jane.doe@example.com".to_string(),
+        };
+        let mut config = Config::new();
+        config.naming = NamingConfig {
+            forbidden_chars: Some("_".to_string()),
+            pattern: Some("^[a-z]+/".to_string()),
+            ..NamingConfig::default()
+        };
+        let rules = NamingRules::new(&config.naming).unwrap();
+        let template = RenameTemplate::parse("{initials}/{name}").unwrap();
+
+        let branches = vec![
+            branch("main", "Jane Doe"),
+            branch("my_login", "Jane Doe"),
+            branch("jd/my-login", "Jane Doe"),
+            branch("Cache", "Bob Smith"),
+            branch("fix_it", "Ann Lee"),
+        ];
+        let renames = plan_renames(&branches, &rules, &template, &config, &[]).unwrap();
+
+        assert_eq!(
+            renames,
+            vec![
+                Rename {
+                    from: "my_login".to_string(),
+                    to: "jd/my-login".to_string(),
+                    skipped: Some("jd/my-login already exists".to_string()),
+                },
+                Rename {
+                    from: "Cache".to_string(),
+                    to: "bs/Cache".to_string(),
+                    skipped: None,
+                },
+                Rename {
+                    from: "fix_it".to_string(),
+                    to: "al/fix-it".to_string(),
+                    skipped: None,
+                },
+            ]
+        );
+
+        // al has to move into al/al before al_x can move to al/al-x; bs
+        // is protected, so it stays in the way of bs/x-y.
+        config.protected_branches.additional = Some(vec!["bs".to_string()]);
+        let branches = vec![
+            branch("al_x", "Ann Lee"),
+            branch("al", "Ann Lee"),
+            branch("bs", "Bob Smith"),
+            branch("x_y", "Bob Smith"),
+        ];
+        let renames = plan_renames(&branches, &rules, &template, &config, &[]).unwrap();
+        let planned: Vec<(&str, &str, Option<&str>)> = renames
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str(), r.skipped.as_deref()))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                ("al", "al/al", None),
+                ("al_x", "al/al-x", None),
+                ("x_y", "bs/x-y", Some("bs is in the way")),
+            ]
+        );
+
+        let owner = RenameTemplate::parse("{owner}/{name}").unwrap();
+        assert_eq!(owner.render(&branch("x", ""), &rules), "jane.doe/x");
+        assert!(RenameTemplate::parse("{initials}").is_err());
+        assert!(RenameTemplate::parse("{user}/{name}").is_err());
+        assert!(RenameTemplate::parse("{name").is_err());
+    }
+}
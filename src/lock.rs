@@ -0,0 +1,268 @@
+use chrono::{DateTime, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{GitTidyError, Result};
+
+const LOCK_FILE_NAME: &str = "git-tidy.lock";
+
+pub struct RepoLock {
+    path: PathBuf,
+}
+
+struct LockOwner {
+    pid: u32,
+    created_at: DateTime<Utc>,
+}
+
+impl RepoLock {
+    pub fn acquire(git_dir: &Path) -> Result<RepoLock> {
+        let path = git_dir.join(LOCK_FILE_NAME);
+
+        if try_create(&path)? {
+            return Ok(RepoLock { path });
+        }
+
+        let owner = read_owner(&path);
+        let is_stale = match &owner {
+            Some(owner) => owner.is_stale(),
+            None => is_abandoned(&path),
+        };
+
+        if is_stale {
+            let _ = fs::remove_file(&path);
+
+            if try_create(&path)? {
+                return Ok(RepoLock { path });
+            }
+        }
+
+        Err(GitTidyError::Locked {
+            owner: owner.map(|owner| (owner.pid, owner.created_at)),
+            path,
+        })
+    }
+}
+
+/// Whether a live git-tidy run holds the lock, as `acquire` would decide.
+pub fn is_locked(git_dir: &Path) -> bool {
+    let path = git_dir.join(LOCK_FILE_NAME);
+    if !path.exists() {
+        return false;
+    }
+
+    match read_owner(&path) {
+        Some(owner) => !owner.is_stale(),
+        None => !is_abandoned(&path),
+    }
+}
+
+// A lock taken over as stale may since belong to another run, whose file
+// is left alone.
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        if read_owner(&self.path).is_some_and(|owner| owner.pid == std::process::id()) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl LockOwner {
+    // However long a run has held the lock, e.g. waiting at a
+    // --confirm-each prompt, it is only stale once its process is gone.
+    fn is_stale(&self) -> bool {
+        !process_is_running(self.pid)
+    }
+}
+
+fn try_create(path: &Path) -> Result<bool> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => {
+            writeln!(file, "{}", std::process::id())?;
+            writeln!(file, "{}", Utc::now().timestamp())?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(source) => Err(GitTidyError::LockCreate {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+// A lock without readable owner details is only treated as abandoned once it
+// is old enough that it cannot be another process still writing it.
+fn is_abandoned(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|elapsed| elapsed > std::time::Duration::from_secs(10))
+}
+
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+
+    let pid = lines.next()?.trim().parse().ok()?;
+    let timestamp = lines.next()?.trim().parse().ok()?;
+    let created_at = DateTime::from_timestamp(timestamp, 0)?;
+
+    Some(LockOwner { pid, created_at })
+}
+
+#[cfg(unix)]
+fn process_is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // Signal 0 only checks that the process exists; EPERM means it does but
+    // belongs to another user.
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn process_is_running(pid: u32) -> bool {
+    let filter = format!("PID eq {}", pid);
+    match std::process::Command::new("tasklist")
+        .args(["/FI", &filter, "/NH", "/FO", "CSV"])
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)),
+        // Without a way to tell, the lock is assumed to be held.
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "git-tidy-test-lock-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = lock_dir("release");
+
+        let lock = RepoLock::acquire(&dir).unwrap();
+        assert!(dir.join(LOCK_FILE_NAME).exists());
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE_NAME).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_acquire_while_held() {
+        let dir = lock_dir("held");
+
+        let _lock = RepoLock::acquire(&dir).unwrap();
+        let result = RepoLock::acquire(&dir);
+
+        assert!(matches!(
+            &result,
+            Err(GitTidyError::Locked { owner: Some((pid, _)), .. }) if *pid == std::process::id()
+        ));
+        assert!(
+            result
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("Another git-tidy process")
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_acquire_keeps_old_lock_of_running_process() {
+        let dir = lock_dir("old");
+        let path = dir.join(LOCK_FILE_NAME);
+        let old = Utc::now() - chrono::Duration::hours(2);
+
+        fs::write(
+            &path,
+            format!("{}\n{}\n", std::process::id(), old.timestamp()),
+        )
+        .unwrap();
+        assert!(RepoLock::acquire(&dir).is_err());
+        assert!(is_locked(&dir));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_acquire_replaces_stale_lock() {
+        let dir = lock_dir("stale");
+        let path = dir.join(LOCK_FILE_NAME);
+
+        // A process that has exited, whose pid is not yet reused.
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+
+        fs::write(&path, format!("{}\n{}\n", pid, Utc::now().timestamp())).unwrap();
+        assert!(!is_locked(&dir));
+
+        let lock = RepoLock::acquire(&dir).unwrap();
+        drop(lock);
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_drop_leaves_another_runs_lock() {
+        let dir = lock_dir("taken-over");
+        let path = dir.join(LOCK_FILE_NAME);
+
+        let lock = RepoLock::acquire(&dir).unwrap();
+        fs::write(&path, format!("{}\n{}\n", u32::MAX, Utc::now().timestamp())).unwrap();
+        drop(lock);
+        assert!(path.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_acquire_replaces_unreadable_lock() {
+        let dir = lock_dir("garbage");
+
+        let path = dir.join(LOCK_FILE_NAME);
+        fs::write(&path, "not a lock").unwrap();
+        assert!(RepoLock::acquire(&dir).is_err());
+
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+        assert!(RepoLock::acquire(&dir).is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,266 +1,2516 @@
 mod config;
+mod credentials;
+mod doctor;
+mod document;
+mod error;
 mod filters;
 mod git_operations;
+mod history;
+mod http;
+mod ignore;
+mod interrupt;
+mod jira;
+mod lint;
+mod lock;
+mod metrics;
+mod notify;
+mod output;
+mod pager;
+mod progress;
+mod prompt;
+mod provider;
+mod pull_requests;
+mod stats;
+mod style;
+#[cfg(test)]
+mod test_support;
+mod tidy;
+mod tokens;
 
 use anyhow::Result;
-use chrono::{Duration, Utc};
-use clap::Parser;
-use colored::Colorize;
-use regex::Regex;
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use colored::{ColoredString, Colorize};
+use glob::Pattern;
+use log::{LevelFilter, debug};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use config::{load_config, parse_duration};
-use filters::{filter_by_age, filter_out_protected};
-use git_operations::{BranchInfo, get_current_branch, list_branches, safe_delete_branch};
+use config::{
+    Config, DateBound, Period, ProtectPattern, ServerProvider, config_schema, load_config,
+    load_config_in, load_merged_config, parse_date, parse_date_bound, parse_duration,
+    parse_protect_pattern,
+};
+use doctor::{Diagnostic, Severity, check_config_files, check_repository, check_settings};
+use document::{BranchLinks, Document, ReportFormat};
+use error::GitTidyError;
+use filters::{FilterExpr, group_by_issue, group_by_prefix, parse_branch_list, parse_filter_expr};
+use git_operations::{
+    BranchInfo, BranchPreview, BranchSize, LeftoverRef, WorktreeInfo, branch_size, common_dir,
+    compact_repository, count_loose_refs, delete_leftover_ref, ensure_no_operation_in_progress,
+    expire_deleted_reflogs, expire_reflog, fetch_and_prune, fetch_unshallow,
+    get_checked_out_branches, list_archived, list_branch_names, list_leftover_refs, list_worktrees,
+    plan_reflog_expiry, preview_branch, remove_worktree, rename_branch, resolve_merge_targets,
+    unarchive_branch, unreachable_objects, write_bundle,
+};
+use history::{CleanupRecord, history_path, read_history, record_cleanup, summarize};
+use jira::open_issues;
+use lint::{NamingRules, RenameTemplate, lint, plan_renames};
+use lock::RepoLock;
+use metrics::record_run;
+use notify::{RunSummary, notify_run};
+use output::{
+    OutputFormat, PlanDiff, Template, diff_plans, parse_template, read_plan, render, render_jsonl,
+    render_template,
+};
+use progress::progress_bar;
+use prompt::{choose, confirm, secret};
+use provider::{deployments_from_url, provider_for};
+use pull_requests::{PullRequestRules, needs_closed_pull_requests};
+use stats::{AGE_BUCKETS, collect_stats};
+use style::AgeColors;
+use tidy::{Confirmation, Deletion, ExecuteOptions, Outcome, Report, list_branches_by_age};
+use tokens::{default_host, delete_token, store_token};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Actually delete branches (default: dry-run)
-    #[arg(long)]
+    #[arg(long, global = true)]
     clean: bool,
 
-    /// Only show merged branches
-    #[arg(long)]
-    merged: bool,
+    /// Only show merged branches
+    #[arg(long, global = true)]
+    merged: bool,
+
+    /// Also count branches as merged when the newest tag matching this glob
+    /// (e.g. "v*") reaches them, for repositories that release from tags
+    #[arg(long, global = true, value_name = "GLOB", value_parser = parse_glob)]
+    merge_tag: Option<Pattern>,
+
+    /// Also delete branches that are not merged, discarding their unique
+    /// commits (like `git branch -D`)
+    #[arg(long, alias = "force-unmerged", conflicts_with = "merged")]
+    include_unmerged: bool,
+
+    /// Also delete orphan branches, which share no history with the merge
+    /// target (kept and listed separately otherwise)
+    #[arg(long, conflicts_with = "merged")]
+    include_orphans: bool,
+
+    /// Skip the merge check, which is slow in very large repositories, and
+    /// count every branch as unmerged; combine with --include-unmerged to
+    /// delete by age or pattern alone
+    #[arg(
+        long,
+        alias = "assume-unmerged",
+        conflicts_with_all = ["merged", "older_than_merge", "merge_tag", "include_orphans", "show_size"]
+    )]
+    no_merge_check: bool,
+
+    /// Filter branches older than a duration, date, or phrase (e.g., 30d,
+    /// 6mo, 2024-01-01, "last quarter")
+    #[arg(long, global = true, value_name = "WHEN", value_parser = parse_date_bound)]
+    older_than: Option<DateBound>,
+
+    /// Only branches merged longer ago than this (e.g. 30d), counted from
+    /// the merge into the target instead of the last commit
+    #[arg(long, global = true, value_name = "WHEN", value_parser = parse_date_bound)]
+    older_than_merge: Option<DateBound>,
+
+    /// Only branches last committed to on or after this date, or within this
+    /// duration (e.g. 2024-01-01, 365d)
+    #[arg(long, global = true, value_name = "DATE", value_parser = parse_date_bound)]
+    since: Option<DateBound>,
+
+    /// Only branches last committed to on or before this date, or at least
+    /// this long ago (e.g. 2024-06-30, 180d)
+    #[arg(long, global = true, value_name = "DATE", value_parser = parse_date_bound)]
+    until: Option<DateBound>,
+
+    /// Preview changes without deleting (default: true)
+    #[arg(long, global = true, default_value = "true")]
+    dry_run: bool,
+
+    /// Skip confirmation prompts
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Confirm each branch individually, showing its recent commits
+    #[arg(long, requires = "clean", conflicts_with = "force")]
+    confirm_each: bool,
+
+    /// Evaluate ages as of this date (YYYY-MM-DD or RFC 3339) instead of now
+    #[arg(long, global = true, value_name = "DATE", value_parser = parse_date)]
+    as_of: Option<DateTime<Utc>>,
+
+    /// Only consider branches matching a filter expression, e.g.
+    /// 'merged && age > 30d && name =~ "^feature/"'
+    #[arg(long = "where", value_name = "EXPR", value_parser = parse_filter_expr)]
+    where_expr: Option<FilterExpr>,
+
+    /// Apply a named preset of filters, e.g. `bots` for dependabot/renovate
+    /// branches
+    #[arg(long, value_name = "NAME")]
+    preset: Option<String>,
+
+    /// Only consider branches matching this glob for deletion (repeatable)
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    only: Vec<Pattern>,
+
+    /// Only consider branches you own: those the config's `owners` assigns
+    /// to your user.email, or whose last commit you made
+    #[arg(long)]
+    mine: bool,
+
+    /// Also delete each deleted branch's remote branch (its upstream, or the
+    /// same name on origin)
+    #[arg(long)]
+    delete_remote: bool,
+
+    /// Plan and delete branches on origin instead of local branches, which
+    /// are left alone; includes origin branches with no local branch
+    #[arg(
+        long,
+        conflicts_with_all = ["delete_remote", "archive", "backup_bundle", "reflog_age"]
+    )]
+    remote_only: bool,
+
+    /// Also clean up each initialized submodule, with the config found
+    /// inside it, and summarize the results per submodule
+    #[arg(long, conflicts_with_all = ["output", "format", "report"])]
+    recurse_submodules: bool,
+
+    /// Remote that --delete-remote, --remote-only, and --fetch work on
+    /// (repeatable; default: each branch's upstream or origin, origin for
+    /// --remote-only, every remote for --fetch)
+    #[arg(long, value_name = "NAME")]
+    remote_name: Vec<String>,
+
+    /// Move branches to the archive namespace (archive_namespace, default
+    /// refs/archived/) instead of deleting them; `unarchive` restores them
+    #[arg(long, conflicts_with = "delete_remote")]
+    archive: bool,
+
+    /// Before deleting, write the branches to delete and their history to
+    /// a git bundle at this path, for restoring them offline
+    #[arg(long, value_name = "PATH")]
+    backup_bundle: Option<PathBuf>,
+
+    /// After deleting branches, drop their reflogs and the HEAD reflog
+    /// entries that still keep their commits from being garbage collected
+    #[arg(long, conflicts_with = "archive")]
+    expire_reflog: bool,
+
+    /// Group branches to delete in the report
+    #[arg(long, value_enum, value_name = "KEY", conflicts_with = "delete_remote")]
+    group_by: Option<GroupBy>,
+
+    /// With --group-by, collapse groups with more than N branches into a
+    /// summary line
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "group_by")]
+    collapse_over: usize,
+
+    /// Show each branch to delete's unique commits and lines added/removed
+    /// since its merge base
+    #[arg(long, conflicts_with_all = ["output", "format"])]
+    show_size: bool,
+
+    /// Fetch and prune all remotes before planning
+    #[arg(long)]
+    fetch: bool,
+
+    /// Like --fetch, also fetching the history a shallow clone left out so
+    /// merged branches are recognized
+    #[arg(long, alias = "unshallow")]
+    fetch_unshallow: bool,
+
+    /// Measure age from the last time each branch moved (per the reflog),
+    /// e.g. a rebase or reset, not only from its last commit
+    #[arg(long, global = true)]
+    reflog_age: bool,
+
+    /// Protect the branches the remote's forge (GitHub, GitLab, Gitea, or
+    /// Bitbucket) protects or has open pull requests for (needs an API token)
+    #[arg(long)]
+    sync_protection: bool,
+
+    /// Delete branches whose pull request was merged on the forge, even by
+    /// squashing or rebasing, if they have not moved since (implies
+    /// --sync-protection)
+    #[arg(long)]
+    pr_merged: bool,
+
+    /// Make no network requests: answer forge and Jira lookups from the
+    /// responses cached by earlier runs, and skip fetching
+    #[arg(long, global = true, conflicts_with_all = ["fetch", "fetch_unshallow"])]
+    offline: bool,
+
+    /// Allow deleting branches that have stash entries
+    #[arg(long)]
+    ignore_stashes: bool,
+
+    /// Protect matching branches: a name or glob, or a regex prefixed with
+    /// `re:` (repeatable)
+    #[arg(long, value_name = "PATTERN", value_parser = parse_protect_pattern)]
+    protect: Vec<ProtectPattern>,
+
+    /// Deprecated: use --protect re:<PATTERN>
+    #[arg(long, hide = true, value_parser = parse_keep_pattern)]
+    keep_pattern: Vec<ProtectPattern>,
+
+    /// Print the plan as json, yaml, csv, or jsonl instead of the report
+    /// (never deletes); jsonl prints each branch as soon as it is analyzed
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "clean")]
+    output: Option<OutputFormat>,
+
+    /// Print one line per branch from a template, e.g.
+    /// "{{name}}\t{{age_days}}\t{{reason}}" (fields: name, action, reason,
+    /// merged, last_commit, age_days)
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_template,
+        conflicts_with_all = ["clean", "output"]
+    )]
+    format: Option<Template>,
+
+    /// Also write a markdown or html report of the plan, or of the run with
+    /// --clean, with tables per owner and links to the branches on the forge
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "report_out")]
+    report: Option<ReportFormat>,
+
+    /// File the --report is written to
+    #[arg(long, value_name = "FILE", requires = "report")]
+    report_out: Option<PathBuf>,
+
+    /// Show debug output (-v) or trace output (-vv) on stderr
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only print errors and the final summary
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Config file layered on top of the global and project config
+    #[arg(long, global = true, env = "GIT_TIDY_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Do not protect master, develop, and main (or the configured
+    /// `defaults`); set merge_targets to the real mainline
+    #[arg(long, global = true)]
+    no_default_protection: bool,
+
+    /// Config profile to use, e.g. `work` for [profile.work] (default: the
+    /// first profile whose `remotes` match this repository)
+    #[arg(long, global = true, env = "GIT_TIDY_PROFILE")]
+    profile: Option<String>,
+
+    /// Print x and + instead of ✗ and ✓, for terminals without Unicode
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Print reports straight to the terminal instead of through the pager
+    #[arg(long, global = true)]
+    no_pager: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Inspect git-tidy configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Store or remove the forge API tokens --sync-protection uses, kept in
+    /// the system keyring instead of config files or environment variables
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+    /// Check the config and repository for problems and explain how to fix
+    /// them
+    Doctor,
+    /// Report branch health statistics without deleting anything
+    Stats,
+    /// Print the cleanup plan as JSON to save for a later --diff, or with
+    /// --diff, which branches became or stopped being candidates since
+    Plan {
+        /// A plan saved from `git-tidy plan` or --output json
+        #[arg(long, value_name = "PATH")]
+        diff: Option<PathBuf>,
+    },
+    /// Show the branches earlier --clean runs removed, by month, owner, and
+    /// prefix, optionally only those since --since (e.g. 3mo)
+    History,
+    /// Check branch names against the [naming] conventions (prefixes,
+    /// length, forbidden characters, regex) and list the ones breaking them
+    Lint,
+    /// Rename the branches breaking the [naming] conventions after a
+    /// template such as "{initials}/{name}", keeping their upstreams; shows
+    /// the new names unless --apply is given
+    Rename {
+        /// Template for the new names (default: [naming] rename), from
+        /// {name}, {initials}, and {owner}
+        #[arg(long, value_parser = parse_rename_template)]
+        template: Option<RenameTemplate>,
+        /// Rename the branches instead of only showing the new names
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Remove linked worktrees that are missing or whose branches are
+    /// merged (or older than --older-than)
+    Worktrees,
+    /// Remove refs left behind by history rewrites (refs/original/ and
+    /// refs/replace/), or only those older than --older-than
+    Refs,
+    /// Remove reflog entries older than --older-than (default 90d) from
+    /// every reflog, so the work they pin can be garbage collected
+    Reflog,
+    /// Delete the branches listed in a file (one per line, `-` for stdin),
+    /// with the same protections and checks as automatic candidates
+    Delete {
+        /// File listing branch names
+        #[arg(long, value_name = "PATH")]
+        from_file: PathBuf,
+    },
+    /// Restore branches moved aside by --archive, or list them when no
+    /// names are given
+    Unarchive {
+        /// Branch names to restore
+        branches: Vec<String>,
+    },
+    /// Stay running and repeat the cleanup on a schedule: a dry-run report
+    /// each time, or deletion with --force
+    Watch {
+        /// Time between runs (e.g. 30m, 12h, 1d)
+        #[arg(long, value_parser = parse_interval, default_value = "24h")]
+        interval: Period,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum GroupBy {
+    /// The part of the name before the first `/`, e.g. `feature/`
+    Prefix,
+    /// The issue key in the name, e.g. `ABC-123` in `feature/ABC-123-login`
+    Ticket,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum AuthCommand {
+    /// Prompt for a token and store it in the system keyring
+    Login {
+        provider: ServerProvider,
+        /// Host of a self-hosted server (default: the provider's public
+        /// site, e.g. github.com)
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Remove a token stored with `auth login`
+    Logout {
+        provider: ServerProvider,
+        /// Host of a self-hosted server
+        #[arg(long)]
+        host: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommand {
+    /// Print a JSON Schema for .git-tidy.toml
+    Schema,
+}
+
+fn parse_glob(pattern: &str) -> Result<Pattern, String> {
+    Pattern::new(pattern).map_err(|e| format!("Invalid glob '{}': {}", pattern, e))
+}
+
+fn parse_rename_template(template: &str) -> Result<RenameTemplate, String> {
+    RenameTemplate::parse(template).map_err(|e| e.to_string())
+}
+
+fn parse_interval(interval: &str) -> Result<Period, String> {
+    let period = parse_duration(interval)?;
+    if period.is_zero() {
+        return Err(format!("Interval must be positive: '{}'", interval));
+    }
+
+    Ok(period)
+}
+
+fn parse_keep_pattern(pattern: &str) -> Result<ProtectPattern, String> {
+    parse_protect_pattern(&format!("re:{}", pattern))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logging(cli.verbose, cli.quiet);
+    style::set_ascii(cli.ascii);
+    http::set_offline(cli.offline);
+    let pager = if pages_output(&cli) {
+        pager::start()
+    } else {
+        None
+    };
+    if let Err(e) = interrupt::install() {
+        debug!("Could not install the Ctrl-C handler: {}", e);
+    }
+
+    let result = match &cli.command {
+        Some(Command::Config { command }) => run_config(command),
+        Some(Command::Auth { command }) => run_auth(command),
+        Some(Command::Doctor) => run_doctor(&cli),
+        Some(Command::Stats) => run_stats(&cli),
+        Some(Command::History) => run_history(&cli),
+        Some(Command::Plan { diff }) => run_plan(&cli, diff.as_deref()),
+        Some(Command::Lint) => run_lint(&cli),
+        Some(Command::Rename { template, apply }) => run_rename(&cli, template.as_ref(), *apply),
+        Some(Command::Worktrees) => run_worktrees(&cli),
+        Some(Command::Refs) => run_refs(&cli),
+        Some(Command::Reflog) => run_reflog(&cli),
+        Some(Command::Watch { interval }) => run_watch(&cli, *interval),
+        Some(Command::Delete { from_file }) => run_delete(&cli, from_file),
+        Some(Command::Unarchive { branches }) => run_unarchive(&cli, branches),
+        None => run_cleanup(&cli, None),
+    };
+
+    ExitCode::from(finish(result, pager))
+}
+
+/// Ends a command whose findings, already printed, make it fail, such as
+/// doctor errors or lint violations, with this exit status.
+#[derive(Debug)]
+struct ExitStatus(u8);
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+// The pager is closed, and waited for, before the process exits, so however
+// the run ended its output is complete and the terminal is restored.
+fn finish(result: Result<()>, pager: Option<pager::Pager>) -> u8 {
+    let (status, error) = match result {
+        Ok(()) => (0, None),
+        Err(e) if is_cancelled(&e) => {
+            println!("{}", "Cancelled.".yellow());
+            (130, None)
+        }
+        Err(e) => match e.downcast_ref::<ExitStatus>() {
+            Some(ExitStatus(status)) => (*status, None),
+            None => (1, Some(e)),
+        },
+    };
+
+    drop(pager);
+    if let Some(e) = error {
+        eprintln!("Error: {:?}", e);
+    }
+    status
+}
+
+fn is_cancelled(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref(), Some(GitTidyError::Cancelled))
+}
+
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_module("git_tidy", level)
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+}
+
+// Reports are paged; runs that may prompt, and watch, which never ends, are
+// not.
+fn pages_output(cli: &Cli) -> bool {
+    if cli.no_pager {
+        return false;
+    }
+
+    match cli.command {
+        Some(Command::Watch { .. }) => false,
+        Some(Command::Rename { apply, .. }) => !apply,
+        None
+        | Some(Command::Delete { .. })
+        | Some(Command::Worktrees)
+        | Some(Command::Refs)
+        | Some(Command::Reflog) => !cli.clean,
+        _ => true,
+    }
+}
+
+fn run_auth(command: &AuthCommand) -> Result<()> {
+    let (AuthCommand::Login { provider, host } | AuthCommand::Logout { provider, host }) = command;
+    let host = match host {
+        Some(host) => host.as_str(),
+        None => default_host(*provider).ok_or(GitTidyError::MissingHost)?,
+    };
+
+    match command {
+        AuthCommand::Login { .. } => {
+            let token = secret(&format!("API token for {}", host))?;
+            if token.is_empty() {
+                anyhow::bail!("No token given");
+            }
+            store_token(host, &token)?;
+            println!(
+                "{}",
+                format!("Stored the token for {} in the system keyring.", host)
+                    .green()
+                    .bold()
+            );
+        }
+        AuthCommand::Logout { .. } => {
+            if delete_token(host)? {
+                println!(
+                    "{}",
+                    format!("Removed the token for {}.", host).green().bold()
+                );
+            } else {
+                println!("No token stored for {}.", host);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_config(command: &ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Schema => println!("{}", config_schema()?),
+    }
+
+    Ok(())
+}
+
+fn run_doctor(cli: &Cli) -> Result<()> {
+    let mut sections = vec![("Config files", check_config_files(cli.config.as_deref()))];
+
+    match load_merged_config(cli.config.as_deref(), cli.profile.as_deref()) {
+        Ok(config) => {
+            sections.push(("Settings", check_settings(&config)));
+            let repository = match git2::Repository::discover(".") {
+                Ok(repo) => check_repository(&repo, &config)?,
+                Err(e) => vec![Diagnostic {
+                    severity: Severity::Error,
+                    message: e.message().to_string(),
+                    hint: Some("Run git-tidy doctor inside a repository".to_string()),
+                }],
+            };
+            sections.push(("Repository", repository));
+        }
+        // The file checks above already say why.
+        Err(e) => {
+            debug!("Config did not load: {}", e);
+            sections.push((
+                "Settings",
+                vec![Diagnostic {
+                    severity: Severity::Warning,
+                    message: "Settings and repository checks skipped".to_string(),
+                    hint: Some("They run once the config loads".to_string()),
+                }],
+            ));
+        }
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for (title, diagnostics) in &sections {
+        println!("{}:", title.bold());
+        for diagnostic in diagnostics {
+            let mark = match diagnostic.severity {
+                Severity::Ok => style::keep_mark(),
+                Severity::Warning => {
+                    warnings += 1;
+                    "!".yellow()
+                }
+                Severity::Error => {
+                    errors += 1;
+                    style::delete_mark()
+                }
+            };
+            // Regex errors span several lines.
+            println!(
+                "   {} {}",
+                mark,
+                diagnostic.message.replace('\n', "\n     ")
+            );
+            if let Some(hint) = &diagnostic.hint {
+                println!("     {}", hint.dimmed());
+            }
+        }
+        println!();
+    }
+
+    let summary = format!("{} errors, {} warnings.", errors, warnings);
+    if errors > 0 {
+        println!("{}", summary.red().bold());
+        return Err(ExitStatus(1).into());
+    }
+    println!("{}", summary.green().bold());
+
+    Ok(())
+}
+
+fn run_lint(cli: &Cli) -> Result<()> {
+    let config = naming_config(cli)?;
+    let rules = NamingRules::new(&config.naming)?;
+    if rules.is_empty() {
+        println!(
+            "{}",
+            "No naming conventions to check. Set prefixes, max_length, forbidden_chars, or pattern under [naming]."
+                .yellow()
+        );
+        return Ok(());
+    }
+
+    let repo = git2::Repository::discover(".")?;
+    let mut names = Vec::new();
+    if cli.remote_only {
+        let remotes: Vec<&str> = if cli.remote_name.is_empty() {
+            vec!["origin"]
+        } else {
+            cli.remote_name.iter().map(String::as_str).collect()
+        };
+        for remote in remotes {
+            names.extend(list_branch_names(&repo, Some(remote))?);
+        }
+    } else {
+        names = list_branch_names(&repo, None)?;
+    }
+    let lint = lint(&names, &rules, &config)?;
+
+    if !cli.quiet {
+        println!(
+            "{} ({}):",
+            "Branches breaking the naming conventions".bold(),
+            lint.violations.len()
+        );
+        let width = lint
+            .violations
+            .iter()
+            .map(|v| v.branch.chars().count())
+            .max()
+            .unwrap_or(0);
+        for violation in &lint.violations {
+            println!(
+                "   {} {:<width$}  {}",
+                style::delete_mark(),
+                violation.branch,
+                violation.problems.join("; ").dimmed(),
+                width = width
+            );
+        }
+        println!();
+    }
+
+    if lint.violations.is_empty() {
+        let summary = format!(
+            "All {} branches follow the naming conventions.",
+            lint.checked
+        );
+        println!("{}", summary.green().bold());
+        return Ok(());
+    }
+    let summary = format!(
+        "{} of {} branches break the naming conventions.",
+        lint.violations.len(),
+        lint.checked
+    );
+    println!("{}", summary.red().bold());
+    Err(ExitStatus(1).into())
+}
+
+// Protected branches are exempt from the conventions, so --protect and
+// --no-default-protection apply.
+fn naming_config(cli: &Cli) -> Result<Config> {
+    let mut config = load_config(cli.config.as_deref(), cli.profile.as_deref())?;
+    if cli.no_default_protection {
+        config.protected_branches.default_protection = Some(false);
+    }
+    config.add_protections(cli.protect.iter().chain(&cli.keep_pattern));
+    Ok(config)
+}
+
+fn run_rename(cli: &Cli, template: Option<&RenameTemplate>, apply: bool) -> Result<()> {
+    let config = naming_config(cli)?;
+    let rules = NamingRules::new(&config.naming)?;
+    let template = match (template, &config.naming.rename) {
+        (Some(template), _) => template.clone(),
+        (None, Some(template)) => RenameTemplate::parse(template)?,
+        (None, None) => anyhow::bail!(
+            "No rename template. Set rename under [naming], or pass --template (e.g. \"{{initials}}/{{name}}\")."
+        ),
+    };
+
+    let repo = git2::Repository::discover(".")?;
+    let options = tidy::Options::new(config);
+    let branches = list_branches_by_age(&repo, &options, &[])?;
+    let checked_out = get_checked_out_branches(&repo)?;
+    let renames = plan_renames(&branches, &rules, &template, &options.config, &checked_out)?;
+    let (to_rename, skipped): (Vec<_>, Vec<_>) =
+        renames.iter().partition(|rename| rename.skipped.is_none());
+
+    let width = renames
+        .iter()
+        .map(|rename| rename.from.chars().count())
+        .max()
+        .unwrap_or(0);
+    println!("{} ({}):", "Branches to rename".bold(), to_rename.len());
+    for rename in &to_rename {
+        println!(
+            "   {:<width$} {} {}",
+            rename.from,
+            style::arrow(),
+            rename.to.green(),
+            width = width
+        );
+    }
+    if !skipped.is_empty() {
+        println!("\n{} ({}):", "Skipped".yellow().bold(), skipped.len());
+        for rename in &skipped {
+            println!(
+                "   {} {:<width$} {} {} {}",
+                "?".yellow(),
+                rename.from,
+                style::arrow(),
+                rename.to,
+                format!("({})", rename.skipped.as_deref().unwrap_or_default()).dimmed(),
+                width = width
+            );
+        }
+    }
+
+    if to_rename.is_empty() {
+        println!("\n{}", "No branches to rename.".green().bold());
+        return Ok(());
+    }
+    if !apply {
+        println!(
+            "\n{}",
+            "Run with --apply to rename these branches.".blue().bold()
+        );
+        return Ok(());
+    }
+
+    ensure_no_operation_in_progress(&repo)?;
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+    if !cli.force {
+        println!();
+        if !confirm(&format!("Rename {} branches?", to_rename.len()))? {
+            println!("{}", "Cancelled.".yellow());
+            return Ok(());
+        }
+    }
+
+    let mut failed = 0;
+    for rename in &to_rename {
+        match rename_branch(&repo, &rename.from, &rename.to) {
+            Ok(()) => println!(
+                "{} {} {} {}",
+                "Renamed".green(),
+                rename.from,
+                style::arrow(),
+                rename.to
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("{} {}: {}", "Failed to rename".red(), rename.from, e);
+            }
+        }
+    }
+
+    let mut line = format!("Renamed {} branches.", to_rename.len() - failed);
+    if failed > 0 {
+        line.push_str(&format!(" {} failed.", failed));
+    }
+    println!("\n{}", line.green().bold());
+    if failed > 0 {
+        anyhow::bail!("Could not rename {} branches", failed);
+    }
+
+    Ok(())
+}
+
+fn run_stats(cli: &Cli) -> Result<()> {
+    let options = cleanup_options(
+        cli,
+        load_config(cli.config.as_deref(), cli.profile.as_deref())?,
+    );
+    let repo = git2::Repository::discover(".")?;
+    let merge_targets = resolve_merge_targets(&repo, &options.config)?;
+    let branches = list_branches_by_age(&repo, &options, &merge_targets)?;
+    let stats = collect_stats(&repo, &branches, Utc::now())?;
+
+    println!("{} ({}):", "Branches".bold(), stats.total);
+    println!("   {} merged", stats.merged.to_string().green());
+    println!("   {} unmerged", stats.unmerged.to_string().yellow());
+
+    println!("\n{}:", "By age".bold());
+    let max_count = stats.by_age.iter().copied().max().unwrap_or(0);
+    for (bucket, count) in AGE_BUCKETS.iter().zip(stats.by_age) {
+        println!(
+            "   {:<12} {:>5} {}",
+            bucket,
+            count,
+            histogram_bar(count, max_count).blue()
+        );
+    }
+
+    println!("\n{}:", "By author".bold());
+    let max_count = stats.by_author.first().map(|(_, c)| *c).unwrap_or(0);
+    for (author, count) in &stats.by_author {
+        println!(
+            "   {:<24} {:>5} {}",
+            author,
+            count,
+            histogram_bar(*count, max_count).blue()
+        );
+    }
+
+    println!("\n{}:", "Upstream".bold());
+    println!("   {} tracking", stats.upstream_tracking);
+    println!("   {} gone", stats.upstream_gone.to_string().red());
+    println!("   {} no upstream", stats.upstream_none);
+
+    Ok(())
+}
+
+fn run_history(cli: &Cli) -> Result<()> {
+    let repo = git2::Repository::discover(".")?;
+    let since = cli.since.map(|bound| bound.resolve(Utc::now()));
+    let records = read_history(&history_path(&repo)?, since)?;
+    if records.is_empty() {
+        println!("{}", "No cleanups recorded yet.".green().bold());
+        return Ok(());
+    }
+    let history = summarize(&records);
+
+    let first = records[0].at.with_timezone(&Local).format("%Y-%m-%d");
+    println!(
+        "{} ({} run{} since {}):",
+        "Cleanups".bold(),
+        history.runs,
+        if history.runs == 1 { "" } else { "s" },
+        first
+    );
+    println!(
+        "   {} branches removed",
+        history.branches.to_string().green()
+    );
+    if history.failed > 0 {
+        println!("   {} failed", history.failed.to_string().red());
+    }
+
+    for (title, counts) in [
+        ("By month", &history.by_month),
+        ("By owner", &history.by_owner),
+        ("By prefix", &history.by_prefix),
+    ] {
+        println!("\n{}:", title.bold());
+        let max_count = counts.iter().map(|(_, c)| *c).max().unwrap_or(0);
+        for (key, count) in counts {
+            println!(
+                "   {:<24} {:>5} {}",
+                key,
+                count,
+                histogram_bar(*count, max_count).blue()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn histogram_bar(count: usize, max_count: usize) -> String {
+    const WIDTH: usize = 40;
+
+    if max_count == 0 {
+        return String::new();
+    }
+
+    style::bar_cell().repeat((count * WIDTH).div_ceil(max_count))
+}
+
+fn run_unarchive(cli: &Cli, names: &[String]) -> Result<()> {
+    let config = load_config(cli.config.as_deref(), cli.profile.as_deref())?;
+    let repo = git2::Repository::discover(".")?;
+    let archived = list_archived(&repo, &config)?;
+
+    if names.is_empty() {
+        if archived.is_empty() {
+            println!("{}", "No archived branches.".green().bold());
+            return Ok(());
+        }
+
+        println!("{} ({}):", "Archived branches".bold(), archived.len());
+        for branch in &archived {
+            println!(
+                "   {} {}",
+                branch.name,
+                format!("({})", branch.reference).dimmed()
+            );
+        }
+        println!(
+            "\n{}",
+            "Run `git-tidy unarchive <branch>...` to restore them."
+                .blue()
+                .bold()
+        );
+        return Ok(());
+    }
+
+    // Of several archives of one name, the newest is restored.
+    let selected = names
+        .iter()
+        .map(|name| {
+            archived
+                .iter()
+                .rev()
+                .find(|branch| &branch.name == name)
+                .ok_or_else(|| GitTidyError::NotArchived(name.clone()))
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+    for branch in selected {
+        unarchive_branch(&repo, branch)?;
+        println!(
+            "{} {} from {}",
+            "Restored".green(),
+            branch.name,
+            branch.reference
+        );
+    }
+
+    Ok(())
+}
+
+fn run_worktrees(cli: &Cli) -> Result<()> {
+    let mut options = cleanup_options(
+        cli,
+        load_config(cli.config.as_deref(), cli.profile.as_deref())?,
+    );
+    if cli.no_default_protection {
+        options.config.protected_branches.default_protection = Some(false);
+    }
+    let config = &options.config;
+    let repo = git2::Repository::discover(".")?;
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+
+    let now = cli.as_of.unwrap_or_else(Utc::now);
+    let merge_targets = resolve_merge_targets(&repo, config)?;
+    let branches = list_branches_by_age(&repo, &options, &merge_targets)?;
+
+    let mut to_remove: Vec<(WorktreeInfo, String)> = Vec::new();
+    let mut kept: Vec<(WorktreeInfo, String)> = Vec::new();
+
+    for worktree in list_worktrees(&repo)? {
+        let branch = worktree
+            .branch
+            .as_ref()
+            .and_then(|name| branches.iter().find(|b| &b.name == name));
+        let is_protected = match &worktree.branch {
+            Some(name) => {
+                config.get_protected_branches().contains(name)
+                    || config.is_protected(name)
+                    || config.is_ignored(name)
+                    || config.is_merge_target(name)?
+            }
+            None => false,
+        };
+        let is_old = |b: &BranchInfo| {
+            cli.older_than
+                .is_some_and(|older_than| b.last_commit_date <= older_than.resolve(now))
+        };
+
+        let removal = if worktree.is_locked {
+            Err("locked".to_string())
+        } else if worktree.is_missing {
+            Ok("directory missing".to_string())
+        } else if worktree.is_dirty {
+            Err("uncommitted changes".to_string())
+        } else if is_protected {
+            Err("protected branch".to_string())
+        } else {
+            match branch {
+                Some(b) if b.is_merged => Ok(format!("{} merged", b.name)),
+                Some(b) if is_old(b) && !cli.merged => Ok(format!(
+                    "{} last commit {}",
+                    b.name,
+                    format_age(b.last_commit_date, now, cli.as_of.is_some())
+                )),
+                Some(_) => Err("in use".to_string()),
+                None => Err("detached HEAD".to_string()),
+            }
+        };
+
+        match removal {
+            Ok(reason) => to_remove.push((worktree, reason)),
+            Err(reason) => kept.push((worktree, reason)),
+        }
+    }
+
+    for (worktree, reason) in &to_remove {
+        debug!("worktree {}: will be removed ({})", worktree.name, reason);
+    }
+    for (worktree, reason) in &kept {
+        debug!("worktree {}: kept ({})", worktree.name, reason);
+    }
+
+    if !cli.quiet {
+        print_worktree_plan(&to_remove, &kept);
+    }
+
+    if to_remove.is_empty() {
+        println!("\n{}", "No worktrees to remove.".green().bold());
+        return Ok(());
+    }
+
+    if !cli.clean && cli.dry_run {
+        println!(
+            "\n{}",
+            "Run with --clean to remove these worktrees.".blue().bold()
+        );
+        return Ok(());
+    }
+
+    println!();
+    if !cli.force && !confirm(&format!("Remove {} worktrees?", to_remove.len()))? {
+        println!("{}", "Cancelled.".yellow());
+        return Ok(());
+    }
+
+    let mut removed_count = 0;
+    for (worktree, _) in &to_remove {
+        match remove_worktree(&repo, &worktree.name) {
+            Ok(()) => {
+                if !cli.quiet {
+                    println!("{} {}", "Removed".green(), worktree.name);
+                }
+                removed_count += 1;
+            }
+            Err(e) => println!("{} {}: {}", "Failed to remove".red(), worktree.name, e),
+        }
+    }
+
+    println!(
+        "\n{}",
+        format!("Removed {} worktrees.", removed_count)
+            .green()
+            .bold()
+    );
+
+    Ok(())
+}
+
+fn print_worktree_plan(to_remove: &[(WorktreeInfo, String)], kept: &[(WorktreeInfo, String)]) {
+    println!("{} ({}):", "Worktrees to remove".bold(), to_remove.len());
+    for (worktree, reason) in to_remove {
+        println!(
+            "   {} {} {} - {}",
+            style::delete_mark(),
+            worktree.name,
+            worktree.path.display().to_string().dimmed(),
+            reason
+        );
+    }
+
+    if !kept.is_empty() {
+        println!("\n{} ({}):", "Worktrees kept".bold(), kept.len());
+        for (worktree, reason) in kept {
+            println!(
+                "   {} {} {} - {}",
+                style::keep_mark(),
+                worktree.name,
+                worktree.path.display().to_string().dimmed(),
+                format!("({})", reason).dimmed()
+            );
+        }
+    }
+}
+
+fn run_refs(cli: &Cli) -> Result<()> {
+    let repo = git2::Repository::discover(".")?;
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+
+    let now = cli.as_of.unwrap_or_else(Utc::now);
+    let (to_remove, kept): (Vec<LeftoverRef>, Vec<LeftoverRef>) =
+        list_leftover_refs(&repo)?.into_iter().partition(|r| {
+            cli.older_than
+                .is_none_or(|older_than| r.date <= older_than.resolve(now))
+        });
+
+    for leftover in &to_remove {
+        debug!("{}: will be removed", leftover.name);
+    }
+    for leftover in &kept {
+        debug!("{}: kept (too new)", leftover.name);
+    }
+
+    if !cli.quiet {
+        println!("{} ({}):", "Refs to remove".bold(), to_remove.len());
+        for leftover in &to_remove {
+            println!(
+                "   {} {} - {}",
+                style::delete_mark(),
+                leftover.name,
+                format_age(leftover.date, now, cli.as_of.is_some())
+            );
+        }
+
+        if !kept.is_empty() {
+            println!("\n{} ({}):", "Refs kept".bold(), kept.len());
+            for leftover in &kept {
+                println!(
+                    "   {} {} - {} {}",
+                    style::keep_mark(),
+                    leftover.name,
+                    format_age(leftover.date, now, cli.as_of.is_some()),
+                    "(too new)".dimmed()
+                );
+            }
+        }
+    }
+
+    if to_remove.is_empty() {
+        println!("\n{}", "No refs to remove.".green().bold());
+        return Ok(());
+    }
+
+    if !cli.clean && cli.dry_run {
+        println!(
+            "\n{}",
+            "Run with --clean to remove these refs.".blue().bold()
+        );
+        return Ok(());
+    }
+
+    println!();
+    if !cli.force && !confirm(&format!("Remove {} refs?", to_remove.len()))? {
+        println!("{}", "Cancelled.".yellow());
+        return Ok(());
+    }
+
+    let mut removed_count = 0;
+    for leftover in &to_remove {
+        match delete_leftover_ref(&repo, &leftover.name) {
+            Ok(()) => {
+                if !cli.quiet {
+                    println!("{} {}", "Removed".green(), leftover.name);
+                }
+                removed_count += 1;
+            }
+            Err(e) => println!("{} {}: {}", "Failed to remove".red(), leftover.name, e),
+        }
+    }
+
+    println!(
+        "\n{}",
+        format!("Removed {} refs.", removed_count).green().bold()
+    );
+
+    Ok(())
+}
+
+fn run_reflog(cli: &Cli) -> Result<()> {
+    let repo = git2::Repository::discover(".")?;
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+
+    let now = cli.as_of.unwrap_or_else(Utc::now);
+    let cutoff = cli.older_than.unwrap_or(DEFAULT_REFLOG_EXPIRY).resolve(now);
+    let plan = plan_reflog_expiry(&repo, cutoff)?;
+    let expired: usize = plan.iter().map(|r| r.expired).sum();
+
+    if !cli.quiet {
+        println!(
+            "{} ({}):",
+            format!(
+                "Reflog entries to remove, from before {}",
+                cutoff.format("%Y-%m-%d")
+            )
+            .bold(),
+            expired
+        );
+        for reflog in &plan {
+            println!(
+                "   {} {} - {} of {} entries",
+                style::delete_mark(),
+                reflog.reference,
+                reflog.expired,
+                reflog.total
+            );
+        }
+    }
+
+    if plan.is_empty() {
+        println!("\n{}", "No reflog entries to remove.".green().bold());
+        return Ok(());
+    }
+
+    if !cli.clean && cli.dry_run {
+        println!(
+            "\n{}",
+            "Run with --clean to remove these reflog entries."
+                .blue()
+                .bold()
+        );
+        return Ok(());
+    }
+
+    println!();
+    if !cli.force && !confirm(&format!("Remove {} reflog entries?", expired))? {
+        println!("{}", "Cancelled.".yellow());
+        return Ok(());
+    }
+
+    let mut removed_count = 0;
+    for reflog in &plan {
+        match expire_reflog(&repo, &reflog.reference, cutoff) {
+            Ok(removed) => {
+                if !cli.quiet {
+                    println!(
+                        "{} {} entries from {}",
+                        "Removed".green(),
+                        removed,
+                        reflog.reference
+                    );
+                }
+                removed_count += removed;
+            }
+            Err(e) => println!("{} {}: {}", "Failed to expire".red(), reflog.reference, e),
+        }
+    }
+
+    println!(
+        "\n{}",
+        format!("Removed {} reflog entries.", removed_count)
+            .green()
+            .bold()
+    );
+
+    Ok(())
+}
+
+fn run_delete(cli: &Cli, from_file: &Path) -> Result<()> {
+    let read = if from_file == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(from_file)
+    };
+    let list = read.map_err(|source| GitTidyError::BranchListRead {
+        path: from_file.to_path_buf(),
+        source,
+    })?;
+
+    run_cleanup(cli, Some(&parse_branch_list(&list)))
+}
+
+// Never deletes. The diff is printed by clean_repository once the plan is
+// made, in place of the report.
+fn run_plan(cli: &Cli, diff: Option<&Path>) -> Result<()> {
+    if cli.recurse_submodules {
+        anyhow::bail!("git-tidy plan cannot be combined with --recurse-submodules");
+    }
+    let output = match diff {
+        Some(_) => None,
+        None => Some(cli.output.unwrap_or(OutputFormat::Json)),
+    };
+    let cli = Cli {
+        clean: false,
+        output,
+        ..cli.clone()
+    };
+    run_cleanup(&cli, None)
+}
+
+// `selection` limits the run to the named branches, which are still subject
+// to every protection and filter.
+fn run_cleanup(cli: &Cli, selection: Option<&[String]>) -> Result<()> {
+    let report = cleanup_repository(cli, selection, Path::new("."))?;
+    let mut failed = report.failed.len();
+
+    let mut submodule_errors = 0;
+    if cli.recurse_submodules && !interrupt::interrupted() {
+        let mut results = Vec::new();
+        cleanup_submodules(cli, selection, Path::new("."), Path::new(""), &mut results)?;
+        print_submodule_results(cli, &results);
+        for (_, result) in &results {
+            match result {
+                Ok(report) => failed += report.failed.len(),
+                Err(_) => submodule_errors += 1,
+            }
+        }
+    }
+
+    if interrupt::interrupted() {
+        return Err(GitTidyError::Cancelled.into());
+    }
+
+    if submodule_errors > 0 {
+        anyhow::bail!(
+            "Cleanup failed in {} submodules (see above)",
+            submodule_errors
+        );
+    }
+
+    if failed > 0 {
+        return Err(GitTidyError::PartialFailure(failed).into());
+    }
+
+    Ok(())
+}
+
+// Written once the plan is made, and again with the results after deleting.
+fn write_report(
+    cli: &Cli,
+    repo: &git2::Repository,
+    options: &tidy::Options,
+    plan: &tidy::TidyPlan,
+    report: Option<&Report>,
+) -> Result<()> {
+    let (Some(format), Some(path)) = (cli.report, &cli.report_out) else {
+        return Ok(());
+    };
+
+    let server = &options.config.server_protection;
+    let remote = if cli.remote_only {
+        options.remote_only_remotes()[0]
+    } else {
+        server.remote()
+    };
+    let links = repo
+        .find_remote(remote)
+        .ok()
+        .and_then(|r| BranchLinks::from_remote_url(r.url()?, server.provider));
+    let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+    let repository = workdir
+        .canonicalize()
+        .unwrap_or_else(|_| workdir.to_path_buf());
+    let repository = repository.file_name().map_or_else(
+        || repository.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+
+    let output = plan.to_output();
+    let document = Document {
+        repository: &repository,
+        plan: &output,
+        report,
+        links: links.as_ref(),
+    };
+    std::fs::write(path, document.render(format))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// Runs one cleanup in the repository `dir` is in, with the config found from
+// there.
+fn cleanup_repository(cli: &Cli, selection: Option<&[String]>, dir: &Path) -> Result<Report> {
+    let mut options = cleanup_options(
+        cli,
+        load_config_in(dir, cli.config.as_deref(), cli.profile.as_deref())?,
+    );
+    options.selection = selection.map(<[String]>::to_vec);
+    // A branch on the remote is merged once the remote's main has it.
+    if cli.remote_only && options.config.merge_remote.is_none() {
+        options.config.merge_remote = Some(options.remote_only_remotes()[0].to_string());
+    }
+    let config = &mut options.config;
+    if cli.no_default_protection {
+        config.protected_branches.default_protection = Some(false);
+    }
+    config.add_protections(cli.protect.iter().chain(&cli.keep_pattern));
+    if cli.pr_merged {
+        config.server_protection.delete_merged_prs = Some(true);
+    }
+
+    let mut repo = git2::Repository::discover(dir)?;
+    let _lock = RepoLock::acquire(&common_dir(&repo)?)?;
+
+    if cli.sync_protection || cli.pr_merged || config.server_protection.enabled() {
+        match sync_server_protection(cli, &repo, &mut options) {
+            Ok(()) => {}
+            // Deleting would ignore the forge's protections and open pull
+            // requests; a report can go on without them.
+            Err(e) if is_offline_error(&e) && !cli.clean => {
+                eprintln!(
+                    "{} {}; not syncing protections from the forge",
+                    "Warning:".yellow(),
+                    e
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if options.config.jira.url.is_some() {
+        let jira = options.config.jira.clone();
+        let show = !cli.quiet && cli.output.is_none() && cli.format.is_none();
+        options.open_tickets = Some(Box::new(move |keys| {
+            let open_tickets = match open_issues(&jira, keys) {
+                // Unknown tickets count as open, keeping their branches.
+                Err(e @ GitTidyError::Offline(_)) => {
+                    eprintln!(
+                        "{} {}; keeping branches for all tickets",
+                        "Warning:".yellow(),
+                        e
+                    );
+                    return Ok(keys.to_vec());
+                }
+                result => result?,
+            };
+            if show {
+                println!(
+                    "{}",
+                    format!(
+                        "Checked {} tickets in Jira, {} still open",
+                        keys.len(),
+                        open_tickets.len()
+                    )
+                    .blue()
+                );
+            }
+            Ok(open_tickets)
+        }));
+    }
+
+    let report = clean_repository(cli, &mut repo, &options)?;
+    let summary = RunSummary::from(&report);
+
+    if let Err(e) = record_run(&options.config.metrics, &repo, summary.stats()) {
+        eprintln!("{} {}", "Warning: failed to record metrics:".yellow(), e);
+    }
+
+    if let Err(e) = notify_run(&options.config.notify, &repo, &summary) {
+        eprintln!("{} {}", "Warning:".yellow(), e);
+    }
+
+    Ok(report)
+}
+
+// Each initialized submodule of the repository `dir` is in is cleaned up as
+// if git-tidy ran inside it, so it uses its own config and lock. Nested
+// submodules follow their parent. A submodule that fails is reported and the
+// rest still run.
+fn cleanup_submodules(
+    cli: &Cli,
+    selection: Option<&[String]>,
+    dir: &Path,
+    prefix: &Path,
+    results: &mut Vec<(PathBuf, Result<Report>)>,
+) -> Result<()> {
+    let repo = git2::Repository::discover(dir)?;
+    let Some(workdir) = repo.workdir().map(Path::to_path_buf) else {
+        return Ok(());
+    };
+
+    for submodule in repo.submodules()? {
+        if interrupt::interrupted() {
+            break;
+        }
+        let path = prefix.join(submodule.path());
+        if submodule.open().is_err() {
+            debug!(
+                "Skipping submodule {}, which is not initialized",
+                path.display()
+            );
+            continue;
+        }
+
+        if !cli.quiet {
+            println!("\n{}\n", format!("Submodule {}", path.display()).bold());
+        }
+        let submodule_dir = workdir.join(submodule.path());
+        let result = cleanup_repository(cli, selection, &submodule_dir);
+        if let Err(e) = &result {
+            if is_cancelled(e) {
+                return result.map(|_| ());
+            }
+            eprintln!("{} {:#}", "Error:".red(), e);
+        }
+        results.push((path.clone(), result));
+
+        cleanup_submodules(cli, selection, &submodule_dir, &path, results)?;
+    }
+
+    Ok(())
+}
+
+fn print_submodule_results(cli: &Cli, results: &[(PathBuf, Result<Report>)]) {
+    if cli.quiet || results.is_empty() {
+        return;
+    }
+
+    println!("\n{}", "Submodules:".bold());
+    for (path, result) in results {
+        let outcome = match result {
+            Err(_) => "error (see above)".red().to_string(),
+            Ok(_) if !cli.clean => "checked".to_string(),
+            Ok(report) => {
+                let done = if cli.archive {
+                    format!("archived {}", report.archived.len())
+                } else {
+                    format!("deleted {}", report.deleted.len())
+                };
+                if report.failed.is_empty() {
+                    done
+                } else {
+                    format!(
+                        "{}, {}",
+                        done,
+                        format!("failed {}", report.failed.len()).red()
+                    )
+                }
+            }
+        };
+        println!("  {}: {}", path.display(), outcome);
+    }
+}
+
+// The flags that decide what a cleanup looks at and may remove. The forge
+// and Jira lookups are added by run_cleanup.
+fn cleanup_options(cli: &Cli, mut config: Config) -> tidy::Options {
+    if let Some(merge_tag) = &cli.merge_tag {
+        config.merge_tag = Some(merge_tag.to_string());
+    }
 
-    /// Filter branches older than duration (e.g., 30d, 2w, 1m)
-    #[arg(long, value_parser = parse_duration)]
-    older_than: Option<Duration>,
+    tidy::Options {
+        as_of: cli.as_of,
+        merged: cli.merged,
+        include_unmerged: cli.include_unmerged,
+        include_orphans: cli.include_orphans,
+        no_merge_check: cli.no_merge_check,
+        older_than: cli.older_than,
+        older_than_merge: cli.older_than_merge,
+        since: cli.since,
+        until: cli.until,
+        where_expr: cli.where_expr.clone(),
+        preset: cli.preset.clone(),
+        only: cli.only.clone(),
+        mine: cli.mine,
+        remote_only: cli.remote_only,
+        remote_names: cli.remote_name.clone(),
+        delete_remote: cli.delete_remote,
+        archive: cli.archive,
+        reflog_age: cli.reflog_age,
+        ignore_stashes: cli.ignore_stashes,
+        ..tidy::Options::new(config)
+    }
+}
 
-    /// Preview changes without deleting (default: true)
-    #[arg(long, default_value = "true")]
-    dry_run: bool,
+// Each run reloads the config and takes the repository lock afresh, so edits
+// and manual runs between scheduled runs are picked up. A failed run is
+// reported and retried at the next interval rather than ending the watch.
+fn run_watch(cli: &Cli, interval: Period) -> Result<()> {
+    // Nobody is around to answer prompts, so only --force deletes.
+    let cli = Cli {
+        clean: cli.force,
+        ..cli.clone()
+    };
+    loop {
+        if !cli.quiet {
+            println!(
+                "{}",
+                format!("Run at {}", Local::now().format("%Y-%m-%d %H:%M:%S")).bold()
+            );
+        }
 
-    /// Skip confirmation prompts
-    #[arg(long)]
-    force: bool,
+        match run_cleanup(&cli, None) {
+            Err(e) if is_cancelled(&e) => return Err(e),
+            Err(e) => eprintln!("{} {:#}", "Error:".red(), e),
+            Ok(()) => {}
+        }
 
-    /// Regex pattern to protect matching branches
-    #[arg(long, value_parser = parse_regex)]
-    keep_pattern: Option<Regex>,
-}
+        let now = Utc::now();
+        let next = interval.after(now);
+        if !cli.quiet {
+            println!(
+                "\n{}\n",
+                format!(
+                    "Next run at {}",
+                    next.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+                )
+                .dimmed()
+            );
+        }
 
-fn parse_regex(pattern: &str) -> Result<Regex, String> {
-    Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))
+        std::thread::sleep((next - now).to_std()?);
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let config = load_config()?;
+// Adds what the forge says about branches to `options`: its protected and
+// default branches, and the rules for its pull requests and deployments.
+fn sync_server_protection(
+    cli: &Cli,
+    repo: &git2::Repository,
+    options: &mut tidy::Options,
+) -> Result<()> {
+    let config = &mut options.config;
+    let provider = provider_for(repo, &config.server_protection)?;
+    let server_protected = provider.protected_branches()?;
+    let default_branch = provider.default_branch()?;
+    let mut pull_requests = provider.open_pull_requests()?;
+    if needs_closed_pull_requests(&config.server_protection) {
+        pull_requests.extend(provider.closed_pull_requests()?);
+    }
+    let mut pr_rules = PullRequestRules::from_pull_requests(
+        &pull_requests,
+        &config.server_protection,
+        cli.as_of.unwrap_or_else(Utc::now),
+    )?;
+
+    let server = &config.server_protection;
+    let deployments = if !server.deployments() {
+        Vec::new()
+    } else if let Some(url) = &server.deployments_url {
+        deployments_from_url(url, server.deployments_token_env.as_deref())?
+    } else {
+        provider.deployments()?
+    };
+    pr_rules.keep_deployed(&deployments);
 
-    let repo = git2::Repository::open(".")?;
+    if !cli.quiet && cli.output.is_none() && cli.format.is_none() {
+        println!(
+            "{}",
+            format!(
+                "Synced {} protected branches and {} pull requests from {} ({})",
+                server_protected.len(),
+                pull_requests.len(),
+                config.server_protection.remote(),
+                provider.name()
+            )
+            .blue()
+        );
+        if server.deployments() {
+            println!(
+                "{}",
+                format!("Found {} deployed branches", deployments.len()).blue()
+            );
+        }
+    }
 
-    let current_branch = get_current_branch(&repo)?;
+    let protections: Vec<ProtectPattern> = server_protected
+        .into_iter()
+        .map(ProtectPattern::Glob)
+        .collect();
+    config.add_protections(&protections);
 
-    let branches = list_branches(&repo)?;
+    let remote = config.server_protection.remote().to_string();
+    options
+        .default_branches
+        .extend(default_branch.map(|branch| (remote, branch)));
+    options.pull_requests = pr_rules;
 
-    let protected_patterns = config.get_protected_patterns()?;
+    Ok(())
+}
 
-    let mut branches_to_delete: Vec<BranchInfo> = Vec::new();
-    let mut protected_branches: Vec<BranchInfo> = Vec::new();
+fn is_offline_error(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref(), Some(GitTidyError::Offline(_)))
+}
 
-    for branch in branches {
-        let is_protected_exact = config.get_protected_branches().contains(&branch.name);
-        let is_protected_glob = config.is_protected(&branch.name);
-        let is_protected_regex = protected_patterns.iter().any(|p| p.is_match(&branch.name));
-        let is_current_branch = current_branch.as_ref() == Some(&branch.name);
-        let is_protected_cli = cli
-            .keep_pattern
-            .as_ref()
-            .is_some_and(|p| p.is_match(&branch.name));
+fn clean_repository(
+    cli: &Cli,
+    repo: &mut git2::Repository,
+    options: &tidy::Options,
+) -> Result<Report> {
+    let config = &options.config;
+    if cli.fetch_unshallow && repo.is_shallow() {
+        for remote in fetch_unshallow(repo, &cli.remote_name, &config.credentials)? {
+            if !cli.quiet && cli.output.is_none() && cli.format.is_none() {
+                println!(
+                    "{}",
+                    format!("Fetched the full history of {}", remote).blue()
+                );
+            }
+        }
+    } else if cli.offline {
+        debug!("Offline, not fetching");
+    } else if cli.fetch || cli.fetch_unshallow || config.fetch() {
+        for remote in fetch_and_prune(repo, &cli.remote_name, &config.credentials)? {
+            if !cli.quiet && cli.output.is_none() && cli.format.is_none() {
+                println!("{}", format!("Fetched and pruned {}", remote).blue());
+            }
+        }
+    }
 
-        let is_protected = is_protected_exact
-            || is_protected_glob
-            || is_protected_regex
-            || is_current_branch
-            || is_protected_cli;
+    let age_colors = config.age_colors.age_colors()?;
+    // JSON Lines go out as each branch is classified, so wrappers can start
+    // on a huge repository before the scan is done.
+    let plan = if cli.output == Some(OutputFormat::Jsonl) {
+        tidy::stream_plan(repo, options, &mut |entry| {
+            print!("{}", render_jsonl(&entry)?);
+            Ok(())
+        })?
+    } else {
+        tidy::plan(repo, options)?
+    };
+    let now = plan.now;
+    let as_of = cli.as_of.is_some();
 
-        if is_protected {
-            protected_branches.push(branch);
+    for name in &plan.missing {
+        let kind = if cli.remote_only {
+            format!("a branch on {}", options.remote_only_remotes().join(" or "))
         } else {
-            branches_to_delete.push(branch);
-        }
+            "a local branch".to_string()
+        };
+        eprintln!("{} {} is not {}", "Warning:".yellow(), name, kind);
     }
+    if plan.shallow {
+        eprintln!(
+            "{} this is a shallow clone, so branches merged before its oldest commit are reported as not merged; run with --fetch-unshallow, or use --no-merge-check to rely on age and patterns alone",
+            "Warning:".yellow()
+        );
+    }
+    write_report(cli, repo, options, &plan, None)?;
 
-    let mut filtered_branches: Vec<BranchInfo> = Vec::new();
+    let (verb, done) = if cli.archive {
+        ("Archive", "Archived")
+    } else {
+        ("Delete", "Deleted")
+    };
 
-    let mut candidates: Vec<&BranchInfo> = branches_to_delete.iter().collect();
+    if cli.output.is_some() || cli.format.is_some() {
+        let output = plan.to_output();
+        match (&cli.format, cli.output) {
+            (Some(template), _) => print!("{}", render_template(&output, template)),
+            (None, Some(OutputFormat::Jsonl)) => {}
+            (None, Some(format)) => print!("{}", render(&output, format)?),
+            (None, None) => {}
+        }
+        return Ok(Report::default());
+    }
 
-    let not_merged: Vec<&BranchInfo> = candidates
-        .iter()
-        .filter(|b| !b.is_merged && cli.merged)
-        .copied()
-        .collect();
+    if let Some(Command::Plan {
+        diff: Some(previous),
+    }) = &cli.command
+    {
+        print_plan_diff(&diff_plans(&read_plan(previous)?, &plan.to_output()));
+        return Ok(Report::default());
+    }
 
-    if cli.merged {
-        candidates = candidates.into_iter().filter(|b| b.is_merged).collect();
+    if let Some(sha) = &plan.detached_head
+        && !cli.quiet
+    {
+        println!("{}\n", format!("HEAD detached at {}", sha).blue());
     }
 
-    let too_new: Vec<&BranchInfo> = if let Some(older_than) = cli.older_than {
-        candidates
+    if !cli.quiet {
+        let title = if cli.remote_only {
+            format!(
+                "Branches to {} on {}",
+                verb.to_lowercase(),
+                options.remote_only_remotes().join(", ")
+            )
+        } else {
+            format!("Branches to {}", verb.to_lowercase())
+        };
+        println!("{} ({}):", title.bold(), plan.to_delete().len());
+    }
+    let sizes: HashMap<&str, BranchSize> = if cli.show_size && !cli.quiet {
+        plan.to_delete()
             .iter()
-            .filter(|b| b.last_commit_date > Utc::now() - older_than)
-            .copied()
-            .collect()
+            .map(|d| {
+                let size = branch_size(repo, d.branch.tip, &plan.merge_targets)?;
+                Ok((d.branch.name.as_str(), size))
+            })
+            .collect::<error::Result<_>>()?
     } else {
-        Vec::new()
+        HashMap::new()
     };
-
-    let candidates = if let Some(older_than) = cli.older_than {
-        filter_by_age(&candidates, older_than)
-    } else {
-        candidates
+    let reasons: HashMap<&str, Option<&str>> = plan
+        .to_delete()
+        .iter()
+        .map(|d| (d.branch.name.as_str(), d.reason.as_deref()))
+        .collect();
+    let note = |branch: &BranchInfo| {
+        let name = branch.name.as_str();
+        delete_note(
+            branch,
+            reasons.get(name).copied().flatten(),
+            sizes.get(name),
+        )
     };
+    let branches_to_delete: Vec<&BranchInfo> = plan.to_delete().iter().map(|d| &d.branch).collect();
+    match cli.group_by {
+        _ if cli.quiet => {}
+        _ if cli.delete_remote || cli.remote_only => {
+            let local_actions: Vec<ColoredString> = branches_to_delete
+                .iter()
+                .map(|b| {
+                    if !cli.remote_only {
+                        "delete".red()
+                    } else if repo.find_branch(&b.name, git2::BranchType::Local).is_ok() {
+                        "keep  ".dimmed()
+                    } else {
+                        "-     ".dimmed()
+                    }
+                })
+                .collect();
+            print_remote_plan(plan.to_delete(), &local_actions, &sizes)
+        }
+        Some(group_by) => {
+            let (groups, ungrouped) = match group_by {
+                GroupBy::Prefix => (group_by_prefix(&branches_to_delete), "(no prefix)"),
+                GroupBy::Ticket => (
+                    group_by_issue(&branches_to_delete, config.jira.projects()),
+                    "(no ticket)",
+                ),
+            };
+            for (key, group) in groups {
+                let label = if key.is_empty() {
+                    ungrouped
+                } else {
+                    key.as_str()
+                };
+                println!("   {} ({})", label.bold(), group.len());
 
-    filtered_branches.extend(not_merged.into_iter().chain(too_new).map(|b| b.clone()));
+                if group.len() > cli.collapse_over {
+                    let newest = group.iter().map(|b| b.last_commit_date).max();
+                    let oldest = group.iter().map(|b| b.last_commit_date).min();
+                    if let (Some(newest), Some(oldest)) = (newest, oldest) {
+                        println!(
+                            "      {} {} branches, last commits {} to {}",
+                            style::delete_mark(),
+                            group.len(),
+                            colored_age(newest, now, as_of, &age_colors),
+                            colored_age(oldest, now, as_of, &age_colors)
+                        );
+                    }
+                    continue;
+                }
 
-    let filtered = filter_out_protected(
-        &candidates,
-        &config.get_protected_branches(),
-        current_branch.as_deref(),
-    );
+                let width = name_width(group.iter().copied());
+                for branch in group {
+                    println!(
+                        "      {} {}{}",
+                        style::delete_mark(),
+                        branch_columns(branch, width, now, as_of, &age_colors),
+                        note(branch)
+                    );
+                }
+            }
+        }
+        None => {
+            let width = name_width(branches_to_delete.iter().copied());
+            for branch in &branches_to_delete {
+                println!(
+                    "   {} {}{}",
+                    style::delete_mark(),
+                    branch_columns(branch, width, now, as_of, &age_colors),
+                    note(branch)
+                );
+            }
+        }
+    }
 
-    let branches_to_delete: Vec<&BranchInfo> = filtered;
+    for deletion in plan.to_delete() {
+        if let Some(other) = &deletion.shares_unmerged_with {
+            println!(
+                "{} {} shares unmerged commits with {}, which would be the only branch left holding them.",
+                "Warning:".yellow(),
+                deletion.branch.name,
+                other
+            );
+        }
+    }
 
-    println!(
-        "{} ({}):",
-        "Branches to delete".bold(),
-        branches_to_delete.len()
-    );
-    for branch in &branches_to_delete {
+    if !plan.kept.is_empty() && !cli.quiet {
         println!(
-            "   {} {} - {}",
-            "✗".red(),
-            branch.name,
-            format_age(branch.last_commit_date)
+            "\n{} ({}):",
+            "Branches kept (filtered out)".yellow().bold(),
+            plan.kept.len()
         );
     }
+    let width = name_width(plan.kept.iter().map(|k| &k.branch));
+    for kept in &plan.kept {
+        debug!("{}: kept ({})", kept.branch.name, kept.reason);
+
+        if !cli.quiet {
+            println!(
+                "   {} {} ({})",
+                "?".yellow(),
+                branch_columns(&kept.branch, width, now, as_of, &age_colors),
+                kept.reason.dimmed()
+            );
+        }
+    }
 
-    if !filtered_branches.is_empty() {
+    if !plan.orphans.is_empty() && !cli.quiet {
         println!(
             "\n{} ({}):",
-            "Branches kept (filtered out)".yellow().bold(),
-            filtered_branches.len()
-        );
-        for branch in &filtered_branches {
-            let reason = if !branch.is_merged && cli.merged {
-                "not merged"
-            } else if let Some(older_than) = cli.older_than {
-                if branch.last_commit_date > Utc::now() - older_than {
-                    "too new"
-                } else {
-                    "filtered"
-                }
-            } else {
-                "filtered"
-            };
+            "Orphan branches (no shared history)".yellow().bold(),
+            plan.orphans.len()
+        );
+        let width = name_width(plan.orphans.iter());
+        for branch in &plan.orphans {
+            debug!("{}: kept ({})", branch.name, tidy::ORPHAN);
             println!(
-                "   {} {} - {} ({})",
+                "   {} {}",
                 "?".yellow(),
-                branch.name,
-                format_age(branch.last_commit_date),
-                reason.dimmed()
+                branch_columns(branch, width, now, as_of, &age_colors)
             );
         }
+        println!("   {}", "Pass --include-orphans to delete them.".dimmed());
     }
 
-    println!(
-        "\n{} ({}):",
-        "Protected branches".bold(),
-        protected_branches.len()
-    );
-    for branch in &protected_branches {
-        let reason = if current_branch.as_ref() == Some(&branch.name) {
-            "current"
-        } else if cli
-            .keep_pattern
-            .as_ref()
-            .is_some_and(|p| p.is_match(&branch.name))
-        {
-            "cli pattern"
-        } else if protected_patterns.iter().any(|p| p.is_match(&branch.name)) {
-            "regex pattern"
-        } else if config.is_protected(&branch.name) {
-            "glob pattern"
-        } else if config.get_protected_branches().contains(&branch.name) {
-            "protected"
-        } else {
-            "pattern"
-        };
+    if !plan.case_collisions.is_empty() && !cli.quiet {
         println!(
-            "   {} {} - {}",
-            "✓".green(),
-            branch.name,
-            format!("({})", reason).dimmed()
+            "\n{} ({}):",
+            "Case collisions (sharing a ref file)".yellow().bold(),
+            plan.case_collisions.len()
+        );
+        let width = name_width(plan.case_collisions.iter().map(|k| &k.branch));
+        for collision in &plan.case_collisions {
+            debug!("{}: kept ({})", collision.branch.name, collision.reason);
+            println!(
+                "   {} {} ({})",
+                "!".yellow(),
+                branch_columns(&collision.branch, width, now, as_of, &age_colors),
+                collision.reason.dimmed()
+            );
+        }
+        println!(
+            "   {}",
+            "Rename one of each pair with git branch -m to clean them up.".dimmed()
         );
     }
 
-    if branches_to_delete.is_empty() {
-        println!("\n{}", "No branches to delete.".green().bold());
-        return Ok(());
+    if !cli.quiet {
+        println!(
+            "\n{} ({}):",
+            "Protected branches".bold(),
+            plan.protected.len()
+        );
+    }
+    for protected in &plan.protected {
+        debug!(
+            "{}: protected ({})",
+            protected.branch.name, protected.reason
+        );
+
+        if !cli.quiet {
+            println!(
+                "   {} {} - {}",
+                style::keep_mark(),
+                protected.branch.name,
+                format!("({})", protected.reason).dimmed()
+            );
+        }
+    }
+
+    if !cli.quiet {
+        println!("\n{} {}", "Summary:".bold(), plan.summary().describe());
+    }
+
+    if plan.to_delete().is_empty() {
+        let line = format!("No branches to {}.", verb.to_lowercase());
+        println!("\n{}", line.green().bold());
+        return Ok(Report::default());
     }
 
     if !cli.clean && cli.dry_run {
+        let flag = match cli.command {
+            Some(Command::Watch { .. }) => "--force",
+            _ => "--clean",
+        };
+        let verb = verb.to_lowercase();
+        let summary = if cli.quiet {
+            format!(
+                "{} branches to {}. Run with {} to {} them.",
+                plan.to_delete().len(),
+                verb,
+                flag,
+                verb
+            )
+        } else {
+            format!("Run with {} to {} these branches.", flag, verb)
+        };
+        println!("\n{}", summary.blue().bold());
+        return Ok(Report::default());
+    }
+
+    ensure_no_operation_in_progress(repo)?;
+
+    // Archiving keeps the commits, so only deletion needs the warning.
+    let unmerged_count = branches_to_delete
+        .iter()
+        .filter(|b| !b.has_no_unique_work())
+        .count();
+    if unmerged_count > 0 && !cli.archive {
         println!(
             "\n{}",
-            "Run with --clean to delete these branches.".blue().bold()
+            format!(
+                "Warning: {} branches are not merged. Deleting them discards their unique commits.",
+                unmerged_count
+            )
+            .red()
+            .bold()
         );
-        return Ok(());
+        if !cli.force && !confirm(&format!("Delete {} unmerged branches?", unmerged_count))? {
+            println!("{}", "Cancelled.".yellow());
+            return Ok(Report::default());
+        }
     }
 
-    if !cli.force && !confirm_deletion(&branches_to_delete)? {
+    if !cli.force && !cli.confirm_each {
+        println!();
+    }
+    if !cli.force
+        && !cli.confirm_each
+        && !confirm(&format!("{} {} branches?", verb, plan.to_delete().len()))?
+    {
         println!("{}", "Cancelled.".yellow());
-        return Ok(());
+        return Ok(Report::default());
     }
 
-    let mut repo = git2::Repository::open(".")?;
-    let mut deleted_count = 0;
-
-    for branch in branches_to_delete {
-        if cli.clean {
-            match safe_delete_branch(
-                &mut repo,
-                &branch.name,
-                &config,
-                current_branch.as_deref(),
-                cli.force,
-            ) {
-                Ok(_) => {
-                    println!("{} {}", "Deleted".green(), branch.name);
-                    deleted_count += 1;
-                }
-                Err(e) => {
-                    println!("{} {}: {}", "Failed to delete".red(), branch.name, e);
+    // Nothing is deleted unless the bundle was written.
+    if cli.clean
+        && let Some(path) = &cli.backup_bundle
+    {
+        let names: Vec<&str> = branches_to_delete.iter().map(|b| b.name.as_str()).collect();
+        write_bundle(repo, path, &names)?;
+        if !cli.quiet {
+            println!(
+                "{}",
+                format!(
+                    "Wrote {} branches to {}. Restore one with `git fetch {} refs/heads/<branch>:refs/heads/<branch>`.",
+                    names.len(),
+                    path.display(),
+                    path.display()
+                )
+                .blue()
+            );
+        }
+    }
+
+    // Without --force there may be prompts, which a redrawing bar would
+    // garble.
+    let progress = if cli.force {
+        progress_bar(plan.to_delete().len(), "Deleting branches")
+    } else {
+        indicatif::ProgressBar::hidden()
+    };
+    let confirm_remaining = Cell::new(cli.confirm_each);
+    // execute holds the repository mutably, so previews read through a
+    // second handle.
+    let preview_repo = git2::Repository::open(repo.path())?;
+
+    let confirm_branch = |deletion: &Deletion| {
+        progress.inc(1);
+        let branch = &deletion.branch;
+
+        if !confirm_remaining.get() {
+            return Ok(Confirmation::Yes);
+        }
+
+        let preview = preview_branch(
+            &preview_repo,
+            branch.tip,
+            PREVIEW_COMMITS,
+            &plan.merge_targets,
+        )?;
+        Ok(
+            match prompt_branch(branch, &preview, verb, now, as_of, &age_colors)? {
+                BranchDecision::Yes => Confirmation::Yes,
+                BranchDecision::No => Confirmation::No,
+                BranchDecision::All => {
+                    confirm_remaining.set(false);
+                    Confirmation::Yes
                 }
+                BranchDecision::Quit => Confirmation::Quit,
+            },
+        )
+    };
+    let on_outcome = |outcome: &Outcome| match outcome {
+        Outcome::Skipped(branch) => println!("{} {}", "Skipped".yellow(), branch.name),
+        Outcome::Removed(branch) => {
+            if !cli.quiet {
+                progress.suspend(|| println!("{} {}", done.green(), branch.name));
+            }
+        }
+        Outcome::Failed(_, e) if matches!(e, GitTidyError::TipMoved { .. }) => {
+            progress.suspend(|| println!("{} {}", "Skipped:".yellow(), e));
+        }
+        Outcome::Failed(branch, e) => progress.suspend(|| {
+            let failed = format!("Failed to {}", verb.to_lowercase());
+            println!("{} {}: {}", failed.red(), branch.name, e)
+        }),
+        Outcome::RemoteDeleted(remote_branch) => {
+            if !cli.quiet {
+                progress.suspend(|| println!("{} {}", "Deleted".green(), remote_branch));
             }
         }
+        Outcome::RemoteFailed(remote_branch, e) => {
+            progress.suspend(|| println!("{} {}: {}", "Failed to delete".red(), remote_branch, e))
+        }
+    };
+    let confirm_retry = |count: usize| {
+        confirm_remaining.set(false);
+        let retry = cli.force || {
+            println!();
+            confirm(&format!("Retry {} failed branches?", count))?
+        };
+        if retry {
+            progress.inc_length(count as u64);
+        }
+        Ok(retry)
+    };
+
+    let deleting = interrupt::deleting();
+    let report = tidy::execute(
+        repo,
+        &plan,
+        &ExecuteOptions {
+            confirm: Some(&confirm_branch),
+            on_outcome: Some(&on_outcome),
+            confirm_retry: Some(&confirm_retry),
+            stop: Some(&interrupt::interrupted),
+        },
+    )?;
+    progress.finish_and_clear();
+    drop(deleting);
+    write_report(cli, repo, options, &plan, Some(&report))?;
+    if let Err(e) = record_cleanup(repo, &CleanupRecord::new(&plan, &report, Utc::now())) {
+        eprintln!(
+            "{} failed to record the cleanup history: {}",
+            "Warning:".yellow(),
+            e
+        );
+    }
+
+    if report.cancelled {
+        println!("{}", "Cancelled.".yellow());
+    }
+
+    if !report.not_attempted.is_empty() {
+        let shown = report.not_attempted.len().min(NOT_ATTEMPTED_SHOWN);
+        let mut names = report.not_attempted[..shown].join(", ");
+        if report.not_attempted.len() > shown {
+            names.push_str(&format!(" and {} more", report.not_attempted.len() - shown));
+        }
+        println!(
+            "\n{} Not {}: {}",
+            "Stopped by Ctrl-C.".yellow().bold(),
+            done.to_lowercase(),
+            names
+        );
     }
 
     if cli.clean {
+        let mut line = if cli.remote_only {
+            format!("Deleted {} remote branches.", report.remote_deleted.len())
+        } else if cli.delete_remote {
+            format!(
+                "Deleted {} branches and {} remote branches.",
+                report.deleted.len(),
+                report.remote_deleted.len()
+            )
+        } else if cli.archive {
+            format!(
+                "Archived {} branches. Restore them with `git-tidy unarchive <branch>`.",
+                report.archived.len()
+            )
+        } else {
+            format!("Deleted {} branches.", report.deleted.len())
+        };
+        if !report.failed.is_empty() {
+            line.push_str(&format!(" {} failed.", report.failed.len()));
+        }
+        if !report.deleted_tips.is_empty() {
+            match unreachable_objects(repo, &report.deleted_tips) {
+                Ok(reclaimable) if reclaimable.objects > 0 => {
+                    line.push_str(&format!(" About {} can be garbage collected.", reclaimable))
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Could not estimate reclaimable space: {}", e),
+            }
+        }
+        println!("\n{}", line.green().bold());
+        if report.remote_results.len() > 1 {
+            for (remote, (deleted, failed)) in &report.remote_results {
+                let mut line = format!("  {}: {} deleted", remote, deleted);
+                if *failed > 0 {
+                    line.push_str(&format!(", {} failed", failed));
+                }
+                println!("{}", line);
+            }
+        }
+    }
+
+    if (cli.expire_reflog || config.expire_reflog())
+        && !report.deleted.is_empty()
+        && !interrupt::interrupted()
+    {
+        match expire_deleted_reflogs(repo, &report.deleted, &report.deleted_tips) {
+            Ok(expired) if expired > 0 && !cli.quiet => println!(
+                "{}",
+                format!(
+                    "Expired {} reflog entries keeping deleted work alive.",
+                    expired
+                )
+                .blue()
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("{} {}", "Warning:".yellow(), e),
+        }
+    }
+
+    if config.gc_after_clean() && !report.deleted.is_empty() && !interrupt::interrupted() {
+        let before = count_loose_refs(repo)?;
+        match compact_repository(repo) {
+            Ok(()) if !cli.quiet => println!(
+                "{}",
+                format!(
+                    "Packed refs: {} loose refs before, {} after.",
+                    before,
+                    count_loose_refs(repo)?
+                )
+                .blue()
+            ),
+            Ok(()) => {}
+            Err(e) => eprintln!("{} {}", "Warning:".yellow(), e),
+        }
+    }
+
+    Ok(report)
+}
+
+fn print_plan_diff(diff: &PlanDiff) {
+    let width = diff
+        .appeared
+        .iter()
+        .chain(&diff.disappeared)
+        .map(|change| change.entry.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    // What the branch was, or has become, in the other plan.
+    let other = |change: &output::CandidateChange| match change.other {
+        Some(other) => match &other.reason {
+            Some(reason) => format!("({}: {})", other.action.as_str(), reason),
+            None => format!("({})", other.action.as_str()),
+        },
+        None => "(no such branch)".to_string(),
+    };
+
+    println!("{} ({}):", "New candidates".bold(), diff.appeared.len());
+    for change in &diff.appeared {
         println!(
-            "\n{}",
-            format!("Deleted {} branches.", deleted_count)
-                .green()
-                .bold()
+            "   {} {:<width$}  {}",
+            "+".green(),
+            change.entry.name,
+            format!("was {}", other(change)).dimmed(),
+            width = width
+        );
+    }
+    println!(
+        "\n{} ({}):",
+        "No longer candidates".bold(),
+        diff.disappeared.len()
+    );
+    for change in &diff.disappeared {
+        println!(
+            "   {} {:<width$}  {}",
+            "-".red(),
+            change.entry.name,
+            format!("now {}", other(change)).dimmed(),
+            width = width
         );
     }
+    println!(
+        "\n{}",
+        format!(
+            "{} new, {} gone, {} unchanged.",
+            diff.appeared.len(),
+            diff.disappeared.len(),
+            diff.unchanged
+        )
+        .bold()
+    );
+}
 
-    Ok(())
+fn print_remote_plan(
+    to_delete: &[Deletion],
+    local_actions: &[ColoredString],
+    sizes: &HashMap<&str, BranchSize>,
+) {
+    let width = to_delete
+        .iter()
+        .map(|d| d.branch.name.chars().count())
+        .chain(["Branch".len()])
+        .max()
+        .unwrap_or(0);
+
+    println!(
+        "     {}",
+        format!("{:<width$}  {:<6}  {}", "Branch", "Local", "Remote").dimmed()
+    );
+    for (deletion, local_action) in to_delete.iter().zip(local_actions) {
+        let remote_action = if deletion.remote_branches.is_empty() {
+            "- (no remote branch)".dimmed()
+        } else {
+            let names: Vec<String> = deletion
+                .remote_branches
+                .iter()
+                .map(|r| r.to_string())
+                .collect();
+            format!("delete {}", names.join(", ")).red()
+        };
+        let size = match sizes.get(deletion.branch.name.as_str()) {
+            Some(size) => format!(" {}", format!("({})", size).dimmed()),
+            None => String::new(),
+        };
+        println!(
+            "   {} {:<width$}  {}  {}{}",
+            style::delete_mark(),
+            deletion.branch.name,
+            local_action,
+            remote_action,
+            size
+        );
+    }
+}
+
+const PREVIEW_COMMITS: usize = 5;
+
+// How old reflog entries `git-tidy reflog` removes without --older-than,
+// the same as git's gc.reflogExpire.
+const DEFAULT_REFLOG_EXPIRY: DateBound = DateBound::Ago(Period::days(90));
+
+// How many branches left alone by Ctrl-C are named before "and N more".
+const NOT_ATTEMPTED_SHOWN: usize = 20;
+
+enum BranchDecision {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+fn prompt_branch(
+    branch: &BranchInfo,
+    preview: &BranchPreview,
+    verb: &str,
+    now: DateTime<Utc>,
+    as_of: bool,
+    age_colors: &AgeColors,
+) -> error::Result<BranchDecision> {
+    println!(
+        "\n{} - {}, {} commit{} ahead",
+        branch.name.bold(),
+        colored_age(branch.last_commit_date, now, as_of, age_colors),
+        preview.ahead,
+        if preview.ahead == 1 { "" } else { "s" }
+    );
+    for subject in &preview.recent_subjects {
+        println!("   {}", subject.dimmed());
+    }
+
+    let decision = choose(
+        &format!("{} {}?", verb, branch.name),
+        &[('y', "Yes"), ('n', "No"), ('a', "All"), ('q', "Quit")],
+    )?;
+
+    Ok(match decision {
+        0 => BranchDecision::Yes,
+        1 => BranchDecision::No,
+        2 => BranchDecision::All,
+        _ => BranchDecision::Quit,
+    })
+}
+
+fn delete_note(branch: &BranchInfo, reason: Option<&str>, size: Option<&BranchSize>) -> String {
+    let notes: Vec<String> = reason
+        .map(str::to_string)
+        .into_iter()
+        .chain(size.map(|size| size.to_string()))
+        .chain(Some(branch.owner.clone()).filter(|owner| !owner.is_empty()))
+        .collect();
+
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", format!("({})", notes.join(", ")).dimmed())
+    }
+}
+
+// Wide enough for "11 months ago", so most ages line up.
+const AGE_WIDTH: usize = 13;
+
+// Longer subjects are cut so each branch stays on one line.
+const SUBJECT_WIDTH: usize = 50;
+
+fn name_width<'a>(branches: impl Iterator<Item = &'a BranchInfo>) -> usize {
+    branches.map(|b| b.name.chars().count()).max().unwrap_or(0)
 }
 
-fn confirm_deletion(branches: &[&BranchInfo]) -> Result<bool> {
-    println!("\nDelete {} branches? [y/N]: ", branches.len());
+// E.g. `feature/login  3 months ago   a1b2c3d  "fix oauth flow"  alice`.
+fn branch_columns(
+    branch: &BranchInfo,
+    width: usize,
+    now: DateTime<Utc>,
+    as_of: bool,
+    age_colors: &AgeColors,
+) -> String {
+    let age = format!(
+        "{:<AGE_WIDTH$}",
+        format_age(branch.last_commit_date, now, as_of)
+    );
+    let mut subject = branch.last_commit_subject.clone();
+    if subject.chars().count() > SUBJECT_WIDTH {
+        subject = subject.chars().take(SUBJECT_WIDTH - 3).collect();
+        subject.push_str("...");
+    }
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
+    format!(
+        "{:<width$}  {}  {}  \"{}\"  {}",
+        branch.name,
+        age_colors.paint(branch.last_commit_date, now, &age),
+        branch.short_sha.dimmed(),
+        subject,
+        branch.last_commit_author.dimmed()
+    )
+}
 
-    Ok(input.trim().to_lowercase() == "y")
+fn colored_age(
+    date: DateTime<Utc>,
+    now: DateTime<Utc>,
+    as_of: bool,
+    age_colors: &AgeColors,
+) -> ColoredString {
+    age_colors.paint(date, now, &format_age(date, now, as_of))
 }
 
-fn format_age(date: chrono::DateTime<Utc>) -> String {
-    let now = Utc::now();
+// `as_of` says whether --as-of set `now`, which commits can then postdate.
+fn format_age(date: DateTime<Utc>, now: DateTime<Utc>, as_of: bool) -> String {
     let duration = now.signed_duration_since(date);
 
+    if duration < Duration::zero() {
+        return if as_of {
+            "after --as-of date"
+        } else {
+            "in the future"
+        }
+        .to_string();
+    }
+
     let days = duration.num_days();
 
     if days == 0 {
@@ -282,3 +2532,49 @@ fn format_age(date: chrono::DateTime<Utc>) -> String {
         format!("{} year{} ago", years, if years > 1 { "s" } else { "" })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_format_age() {
+        let now = Utc::now();
+
+        assert_eq!(
+            format_age(now - Duration::days(3), now, false),
+            "3 days ago"
+        );
+        assert_eq!(
+            format_age(now + Duration::hours(2), now, false),
+            "in the future"
+        );
+        assert_eq!(
+            format_age(now + Duration::hours(2), now, true),
+            "after --as-of date"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_finish_closes_the_pager() {
+        let path = std::env::temp_dir().join(format!("git-tidy-test-pager-{}", std::process::id()));
+        let pager = pager::spawn(&[
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("cat > '{}'", path.display()),
+        ])
+        .unwrap();
+        std::io::stdout()
+            .write_all(b"2 errors, 0 warnings.\n")
+            .unwrap();
+
+        assert_eq!(finish(Err(ExitStatus(1).into()), Some(pager)), 1);
+        // The pager was waited for, so it has written everything.
+        let paged = std::fs::read_to_string(&path).unwrap();
+        assert!(paged.contains("2 errors, 0 warnings.\n"));
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,236 @@
+use chrono::{DateTime, Utc};
+use git2::{ObjectType, Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::MetricsConfig;
+use crate::error::{GitTidyError, Result};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunStats {
+    pub deleted: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RepoMetrics {
+    pub runs: u64,
+    pub deletions: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+#[cfg(feature = "providers")]
+#[derive(Debug, Serialize)]
+struct RunEvent<'a> {
+    repo: &'a str,
+    version: &'a str,
+    runs: u64,
+    deletions: u64,
+    failures: u64,
+}
+
+pub fn record_run(config: &MetricsConfig, repo: &Repository, stats: RunStats) -> Result<()> {
+    if !config.enabled.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let path = metrics_path()?;
+    let salt = install_salt(&path.with_file_name("salt"))?;
+    let repo_id = anonymous_repo_id(repo, &salt)?;
+    record_run_at(&path, &repo_id, stats)?;
+
+    if let Some(endpoint) = &config.endpoint {
+        send_run(endpoint, &repo_id, stats)?;
+    }
+
+    Ok(())
+}
+
+pub fn metrics_path() -> Result<PathBuf> {
+    let mut path = dirs::data_local_dir().ok_or(GitTidyError::NoDataDirectory)?;
+
+    path.push("git-tidy");
+    path.push("metrics.json");
+
+    Ok(path)
+}
+
+// Repositories are identified by a salted hash of their location so no paths
+// or remote URLs ever leave the machine, and a guessed path such as
+// ~/src/<project> cannot be hashed to find its id.
+fn anonymous_repo_id(repo: &Repository, salt: &str) -> Result<String> {
+    let path = repo.path().canonicalize()?;
+    let salted = format!("{}:{}", salt, path.to_string_lossy());
+    let id = Oid::hash_object(ObjectType::Blob, salted.as_bytes())?;
+
+    Ok(id.to_string())
+}
+
+// A random value made the first time metrics are recorded and kept next to
+// metrics.json, the same for every repository on this machine.
+fn install_salt(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(salt) if !salt.trim().is_empty() => return Ok(salt.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| std::io::Error::other(e.to_string()))?;
+    let salt: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, &salt)?;
+
+    Ok(salt)
+}
+
+fn record_run_at(path: &Path, repo_id: &str, stats: RunStats) -> Result<()> {
+    let mut metrics: BTreeMap<String, RepoMetrics> = if path.exists() {
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|source| GitTidyError::MetricsParse {
+            path: path.to_path_buf(),
+            source,
+        })?
+    } else {
+        BTreeMap::new()
+    };
+
+    let entry = metrics.entry(repo_id.to_string()).or_default();
+    entry.runs += 1;
+    entry.deletions += stats.deleted as u64;
+    entry.failures += stats.failed as u64;
+    entry.last_run = Some(Utc::now());
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&metrics)?)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "providers")]
+fn send_run(endpoint: &str, repo_id: &str, stats: RunStats) -> Result<()> {
+    if crate::http::is_offline() {
+        log::debug!("Offline, not sending the run to {}", endpoint);
+        return Ok(());
+    }
+
+    let event = RunEvent {
+        repo: repo_id,
+        version: env!("CARGO_PKG_VERSION"),
+        runs: 1,
+        deletions: stats.deleted as u64,
+        failures: stats.failed as u64,
+    };
+
+    ureq::post(endpoint)
+        .timeout(std::time::Duration::from_secs(5))
+        .send_json(&event)
+        .map_err(|e| GitTidyError::MetricsSend {
+            endpoint: endpoint.to_string(),
+            message: e.to_string(),
+        })?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "providers"))]
+fn send_run(endpoint: &str, _repo_id: &str, _stats: RunStats) -> Result<()> {
+    Err(GitTidyError::MetricsSend {
+        endpoint: endpoint.to_string(),
+        message: "git-tidy was built without the `providers` feature".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anonymous_repo_id() {
+        let path =
+            std::env::temp_dir().join(format!("git-tidy-test-repo-metrics-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let repo = Repository::init(&path).unwrap();
+
+        let id = anonymous_repo_id(&repo, "salt-a").unwrap();
+        assert_eq!(id.len(), 40);
+        assert!(!id.contains("git-tidy-test"));
+        assert_eq!(id, anonymous_repo_id(&repo, "salt-a").unwrap());
+
+        // Without the salt, hashing the path alone does not give the id.
+        let unsalted = Oid::hash_object(
+            ObjectType::Blob,
+            repo.path()
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_ne!(id, unsalted.to_string());
+        assert_ne!(id, anonymous_repo_id(&repo, "salt-b").unwrap());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_install_salt_is_kept() {
+        let dir =
+            std::env::temp_dir().join(format!("git-tidy-test-metrics-salt-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("salt");
+
+        let salt = install_salt(&path).unwrap();
+        assert_eq!(salt.len(), 64);
+        assert_eq!(install_salt(&path).unwrap(), salt);
+        assert_ne!(install_salt(&dir.join("other")).unwrap(), salt);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_record_run_at_accumulates() {
+        let path =
+            std::env::temp_dir().join(format!("git-tidy-test-metrics-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        record_run_at(
+            &path,
+            "repo-a",
+            RunStats {
+                deleted: 3,
+                failed: 1,
+            },
+        )
+        .unwrap();
+        record_run_at(
+            &path,
+            "repo-a",
+            RunStats {
+                deleted: 2,
+                failed: 0,
+            },
+        )
+        .unwrap();
+        record_run_at(&path, "repo-b", RunStats::default()).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let metrics: BTreeMap<String, RepoMetrics> = serde_json::from_str(&contents).unwrap();
+
+        assert_eq!(metrics["repo-a"].runs, 2);
+        assert_eq!(metrics["repo-a"].deletions, 5);
+        assert_eq!(metrics["repo-a"].failures, 1);
+        assert_eq!(metrics["repo-b"].runs, 1);
+        assert_eq!(metrics["repo-b"].deletions, 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,180 @@
+use git2::Repository;
+use serde::Serialize;
+
+use crate::config::{NotifyConfig, NotifyFormat};
+use crate::error::{GitTidyError, Result};
+use crate::metrics::RunStats;
+use crate::tidy::{Failure, Report};
+
+/// What a `--clean` run did, branch by branch.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunSummary {
+    pub deleted: Vec<String>,
+    /// Moved to the archive namespace by --archive
+    pub archived: Vec<String>,
+    pub remote_deleted: Vec<String>,
+    pub failed: Vec<Failure>,
+}
+
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    repo: &'a str,
+    version: &'a str,
+    #[serde(flatten)]
+    summary: &'a RunSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct SlackMessage {
+    text: String,
+}
+
+impl From<&Report> for RunSummary {
+    fn from(report: &Report) -> Self {
+        RunSummary {
+            deleted: report.deleted.clone(),
+            archived: report.archived.clone(),
+            remote_deleted: report.remote_deleted.clone(),
+            failed: report.failed.clone(),
+        }
+    }
+}
+
+impl RunSummary {
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty()
+            && self.archived.is_empty()
+            && self.remote_deleted.is_empty()
+            && self.failed.is_empty()
+    }
+
+    pub fn stats(&self) -> RunStats {
+        RunStats {
+            deleted: self.deleted.len(),
+            failed: self.failed.len(),
+        }
+    }
+}
+
+/// Posts the run summary to the configured webhook. Runs that touched no
+/// branches are not announced.
+pub fn notify_run(config: &NotifyConfig, repo: &Repository, summary: &RunSummary) -> Result<()> {
+    let Some(url) = &config.webhook_url else {
+        return Ok(());
+    };
+    if summary.is_empty() || crate::http::is_offline() {
+        return Ok(());
+    }
+
+    let repo_name = repo_name(repo);
+    let format = config.format.unwrap_or_else(|| detect_format(url));
+    let body = match format {
+        NotifyFormat::Slack => serde_json::to_value(SlackMessage {
+            text: slack_text(&repo_name, summary),
+        })?,
+        NotifyFormat::Json => serde_json::to_value(Notification {
+            repo: &repo_name,
+            version: env!("CARGO_PKG_VERSION"),
+            summary,
+        })?,
+    };
+
+    ureq::post(url)
+        .timeout(std::time::Duration::from_secs(5))
+        .send_json(body)
+        .map_err(|e| match e {
+            ureq::Error::Status(code, _) => {
+                GitTidyError::NotifySend(format!("status code {}", code))
+            }
+            ureq::Error::Transport(transport) => {
+                GitTidyError::NotifySend(transport.kind().to_string())
+            }
+        })?;
+
+    Ok(())
+}
+
+// The working directory's name, which is what people call the repository.
+fn repo_name(repo: &Repository) -> String {
+    let path = repo.workdir().unwrap_or_else(|| repo.path());
+
+    path.canonicalize()
+        .ok()
+        .as_deref()
+        .unwrap_or(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().trim_end_matches(".git").to_string())
+        .unwrap_or_else(|| "repository".to_string())
+}
+
+fn detect_format(url: &str) -> NotifyFormat {
+    if url.starts_with("https://hooks.slack.com/") {
+        NotifyFormat::Slack
+    } else {
+        NotifyFormat::Json
+    }
+}
+
+fn slack_text(repo_name: &str, summary: &RunSummary) -> String {
+    let mut lines = vec![format!("git-tidy cleaned up *{}*", repo_name)];
+
+    let mut list = |label: &str, names: &[String]| {
+        if !names.is_empty() {
+            let names: Vec<String> = names.iter().map(|name| format!("`{}`", name)).collect();
+            lines.push(format!("{} ({}): {}", label, names.len(), names.join(", ")));
+        }
+    };
+    list("Deleted", &summary.deleted);
+    list("Archived", &summary.archived);
+    list("Deleted on remote", &summary.remote_deleted);
+
+    if !summary.failed.is_empty() {
+        lines.push(format!("Failed ({}):", summary.failed.len()));
+        for failure in &summary.failed {
+            lines.push(format!("• `{}`: {}", failure.branch, failure.error));
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_text() {
+        let summary = RunSummary {
+            deleted: vec!["feature/a".to_string(), "fix/b".to_string()],
+            archived: vec!["spike/old".to_string()],
+            remote_deleted: Vec::new(),
+            failed: vec![Failure {
+                branch: "feature/c".to_string(),
+                error: "Branch 'feature/c' is protected".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            slack_text("web", &summary),
+            "git-tidy cleaned up *web*\n\
+             Deleted (2): `feature/a`, `fix/b`\n\
+             Archived (1): `spike/old`\n\
+             Failed (1):\n\
+             • `feature/c`: Branch 'feature/c' is protected"
+        );
+        assert_eq!(summary.stats().deleted, 2);
+        assert_eq!(summary.stats().failed, 1);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            detect_format("https://hooks.slack.com/services/T0/B0/x"),
+            NotifyFormat::Slack
+        );
+        assert_eq!(
+            detect_format("https://ci.example.com/hooks/tidy"),
+            NotifyFormat::Json
+        );
+    }
+}
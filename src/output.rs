@@ -0,0 +1,624 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{GitTidyError, Result};
+use crate::git_operations::BranchInfo;
+
+/// Machine-readable renderings of the cleanup plan. Without one, git-tidy
+/// prints its colored report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    Json,
+    Yaml,
+    Csv,
+    /// One JSON object per branch and line, without the summary.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Delete,
+    Archive,
+    Keep,
+    Protected,
+    /// Left alone because its name differs only in case from another
+    /// branch's, and on a case-insensitive filesystem both share a ref file.
+    #[serde(rename = "case-collision")]
+    CaseCollision,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub name: String,
+    pub action: PlanAction,
+    pub reason: Option<String>,
+    pub merged: bool,
+    pub last_commit: DateTime<Utc>,
+    pub age_days: i64,
+    pub owner: String,
+    /// Something to know before removing the branch, e.g. that another
+    /// branch depends on its unmerged commits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub as_of: DateTime<Utc>,
+    pub summary: PlanSummary,
+    pub branches: Vec<PlanEntry>,
+}
+
+/// How many branches each action covers, with kept and protected branches
+/// counted by reason.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlanSummary {
+    pub delete: usize,
+    pub archive: usize,
+    pub keep: BTreeMap<String, usize>,
+    pub protected: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub case_collisions: usize,
+}
+
+// Reasons naming a remote, worktree, date, ticket, label, pull request, or
+// environment are counted together.
+const REASON_KINDS: [(&str, &str); 7] = [
+    ("default branch of ", "default branch"),
+    ("checked out in ", "checked out"),
+    ("exception until ", "exception"),
+    ("open ticket ", "open ticket"),
+    ("PR label ", "PR label"),
+    ("PR #", "PR merged"),
+    ("deployed to ", "deployed"),
+];
+
+/// A branch that became, or stopped being, a candidate between two plans.
+#[derive(Debug)]
+pub struct CandidateChange<'a> {
+    /// The branch's entry in the plan where it is a candidate.
+    pub entry: &'a PlanEntry,
+    /// Its entry in the other plan, unless the branch is not in it.
+    pub other: Option<&'a PlanEntry>,
+}
+
+/// How the candidates of a plan differ from those of a saved one.
+#[derive(Debug, Default)]
+pub struct PlanDiff<'a> {
+    /// Candidates that were not candidates in the saved plan.
+    pub appeared: Vec<CandidateChange<'a>>,
+    /// Candidates of the saved plan that no longer are.
+    pub disappeared: Vec<CandidateChange<'a>>,
+    /// How many branches are candidates in both.
+    pub unchanged: usize,
+}
+
+impl PlanAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanAction::Delete => "delete",
+            PlanAction::Archive => "archive",
+            PlanAction::Keep => "keep",
+            PlanAction::Protected => "protected",
+            PlanAction::CaseCollision => "case-collision",
+        }
+    }
+
+    /// Whether the branch is to be deleted or archived.
+    pub fn is_removal(&self) -> bool {
+        matches!(self, PlanAction::Delete | PlanAction::Archive)
+    }
+}
+
+impl PlanEntry {
+    pub fn new(
+        branch: &BranchInfo,
+        action: PlanAction,
+        reason: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        PlanEntry {
+            name: branch.name.clone(),
+            action,
+            reason,
+            merged: branch.is_merged,
+            last_commit: branch.last_commit_date,
+            age_days: (now - branch.last_commit_date).num_days(),
+            owner: branch.owner.clone(),
+            warning: None,
+        }
+    }
+}
+
+impl Plan {
+    pub fn new(as_of: DateTime<Utc>, branches: Vec<PlanEntry>) -> Self {
+        let mut summary = PlanSummary::default();
+        for entry in &branches {
+            summary.add(entry.action, entry.reason.as_deref());
+        }
+
+        Plan {
+            as_of,
+            summary,
+            branches,
+        }
+    }
+
+    fn entry(&self, name: &str) -> Option<&PlanEntry> {
+        self.branches.iter().find(|entry| entry.name == name)
+    }
+
+    fn candidates(&self) -> impl Iterator<Item = &PlanEntry> {
+        self.branches
+            .iter()
+            .filter(|entry| entry.action.is_removal())
+    }
+}
+
+impl PlanSummary {
+    pub fn add(&mut self, action: PlanAction, reason: Option<&str>) {
+        let counts = match action {
+            PlanAction::Delete => return self.delete += 1,
+            PlanAction::Archive => return self.archive += 1,
+            PlanAction::Keep => &mut self.keep,
+            PlanAction::Protected => &mut self.protected,
+            PlanAction::CaseCollision => return self.case_collisions += 1,
+        };
+        *counts
+            .entry(reason_kind(reason.unwrap_or_default()))
+            .or_default() += 1;
+    }
+
+    /// E.g. "3 to delete, 13 kept (not merged 9, too new 4), 2 protected
+    /// (current 1, open PR 1)", most common reasons first.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![if self.archive > 0 {
+            format!("{} to archive", self.archive)
+        } else {
+            format!("{} to delete", self.delete)
+        }];
+
+        for (label, counts) in [("kept", &self.keep), ("protected", &self.protected)] {
+            if counts.is_empty() {
+                continue;
+            }
+            let mut reasons: Vec<(&String, &usize)> = counts.iter().collect();
+            reasons.sort_by(|a, b| b.1.cmp(a.1));
+            let reasons: Vec<String> = reasons
+                .into_iter()
+                .map(|(reason, count)| format!("{} {}", reason, count))
+                .collect();
+            parts.push(format!(
+                "{} {} ({})",
+                counts.values().sum::<usize>(),
+                label,
+                reasons.join(", ")
+            ));
+        }
+
+        match self.case_collisions {
+            0 => {}
+            1 => parts.push("1 case collision".to_string()),
+            n => parts.push(format!("{} case collisions", n)),
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Reads a plan saved with `--output json`.
+pub fn read_plan(path: &Path) -> Result<Plan> {
+    let json = std::fs::read_to_string(path).map_err(|source| GitTidyError::PlanRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&json).map_err(|source| GitTidyError::PlanParse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Compares the candidates of `current` with those of the `previous` plan,
+/// so a review only needs to look at what changed. Deleting and archiving
+/// count as the same.
+pub fn diff_plans<'a>(previous: &'a Plan, current: &'a Plan) -> PlanDiff<'a> {
+    let mut diff = PlanDiff::default();
+    for entry in current.candidates() {
+        match previous.entry(&entry.name) {
+            Some(other) if other.action.is_removal() => diff.unchanged += 1,
+            other => diff.appeared.push(CandidateChange { entry, other }),
+        }
+    }
+    for entry in previous.candidates() {
+        let other = current.entry(&entry.name);
+        if !other.is_some_and(|other| other.action.is_removal()) {
+            diff.disappeared.push(CandidateChange { entry, other });
+        }
+    }
+    diff
+}
+
+fn reason_kind(reason: &str) -> String {
+    REASON_KINDS
+        .iter()
+        .find(|(prefix, _)| reason.starts_with(prefix))
+        .map_or(reason, |(_, kind)| kind)
+        .to_string()
+}
+
+pub fn render(plan: &Plan, format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(plan)?),
+        OutputFormat::Yaml => Ok(serde_yaml::to_string(plan)?),
+        OutputFormat::Csv => Ok(render_csv(&plan.branches)),
+        OutputFormat::Jsonl => plan.branches.iter().map(render_jsonl).collect(),
+    }
+}
+
+/// A branch's line of `--output jsonl`, which can be printed as soon as the
+/// branch is classified (see `tidy::stream_plan`).
+pub fn render_jsonl(entry: &PlanEntry) -> Result<String> {
+    Ok(format!("{}\n", serde_json::to_string(entry)?))
+}
+
+/// A `--format` template such as `{{name}}\t{{age_days}}\t{{reason}}`,
+/// rendered once per branch. `\t`, `\n`, and `\\` are unescaped so the
+/// template can be passed as a plain shell string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Action,
+    Reason,
+    Merged,
+    LastCommit,
+    AgeDays,
+    Owner,
+}
+
+const FIELDS: [(&str, Field); 7] = [
+    ("name", Field::Name),
+    ("action", Field::Action),
+    ("reason", Field::Reason),
+    ("merged", Field::Merged),
+    ("last_commit", Field::LastCommit),
+    ("age_days", Field::AgeDays),
+    ("owner", Field::Owner),
+];
+
+pub fn parse_template(input: &str) -> Result<Template, String> {
+    let mut segments = Vec::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("{{") {
+        push_literal(&mut segments, &rest[..start]);
+
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed '{{{{' in format '{}'", input))?;
+        let name = after[..end].trim();
+
+        let field = FIELDS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, f)| *f)
+            .ok_or_else(|| {
+                let names: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                format!(
+                    "Unknown field '{}' in format. Available: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        segments.push(Segment::Field(field));
+
+        rest = &after[end + 2..];
+    }
+    push_literal(&mut segments, rest);
+
+    Ok(Template { segments })
+}
+
+fn push_literal(segments: &mut Vec<Segment>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+
+    let mut literal = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            literal.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => literal.push('\t'),
+            Some('n') => literal.push('\n'),
+            Some('\\') => literal.push('\\'),
+            Some(other) => {
+                literal.push('\\');
+                literal.push(other);
+            }
+            None => literal.push('\\'),
+        }
+    }
+
+    segments.push(Segment::Literal(literal));
+}
+
+pub fn render_template(plan: &Plan, template: &Template) -> String {
+    let mut output = String::new();
+
+    for entry in &plan.branches {
+        for segment in &template.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Field(field) => output.push_str(&match field {
+                    Field::Name => entry.name.clone(),
+                    Field::Action => entry.action.as_str().to_string(),
+                    Field::Reason => entry.reason.clone().unwrap_or_default(),
+                    Field::Merged => entry.merged.to_string(),
+                    Field::LastCommit => entry.last_commit.to_rfc3339(),
+                    Field::AgeDays => entry.age_days.to_string(),
+                    Field::Owner => entry.owner.clone(),
+                }),
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+fn render_csv(entries: &[PlanEntry]) -> String {
+    let mut csv = String::from("name,action,reason,merged,last_commit,age_days,owner\n");
+
+    for entry in entries {
+        let fields = [
+            csv_field(&entry.name),
+            entry.action.as_str().to_string(),
+            csv_field(entry.reason.as_deref().unwrap_or("")),
+            entry.merged.to_string(),
+            entry.last_commit.to_rfc3339(),
+            entry.age_days.to_string(),
+            csv_field(&entry.owner),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+// RFC 4180: quote fields containing separators, quotes, or line breaks, and
+// double any embedded quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn test_plan() -> Plan {
+        let now = Utc::now();
+        let branch = |name: &str, is_merged: bool, days_ago: i64| BranchInfo {
+            name: name.to_string(),
+            tip: git2::Oid::zero(),
+            is_merged,
+            merged_at: None,
+            last_commit_date: now - Duration::days(days_ago),
+            last_commit_subject: String::new(),
+            last_commit_author: String::new(),
+            short_sha: String::new(),
+            remote: None,
+            redundant: None,
+            is_empty: false,
+            is_orphan: false,
+            owner: "dev@example.com".to_string(),
+        };
+
+        Plan::new(
+            now,
+            vec![
+                PlanEntry::new(
+                    &branch("feature/done", true, 40),
+                    PlanAction::Delete,
+                    None,
+                    now,
+                ),
+                PlanEntry::new(
+                    &branch("wip", false, 2),
+                    PlanAction::Keep,
+                    Some("too new, \"recent\"".to_string()),
+                    now,
+                ),
+                PlanEntry::new(
+                    &branch("main", true, 0),
+                    PlanAction::Protected,
+                    Some("current".to_string()),
+                    now,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = render(&test_plan(), OutputFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let branches = value["branches"].as_array().unwrap();
+        assert_eq!(branches.len(), 3);
+        assert_eq!(branches[0]["name"], "feature/done");
+        assert_eq!(branches[0]["action"], "delete");
+        assert!(branches[0]["reason"].is_null());
+        assert_eq!(branches[0]["age_days"], 40);
+        assert_eq!(branches[2]["action"], "protected");
+        assert_eq!(value["summary"]["delete"], 1);
+        assert_eq!(value["summary"]["protected"]["current"], 1);
+    }
+
+    #[test]
+    fn test_plan_summary() {
+        let mut summary = PlanSummary::default();
+        for _ in 0..3 {
+            summary.add(PlanAction::Delete, None);
+        }
+        for reason in ["too new", "not merged", "not merged"] {
+            summary.add(PlanAction::Keep, Some(reason));
+        }
+        for reason in ["current", "open ticket ABC-1", "open ticket ABC-2"] {
+            summary.add(PlanAction::Protected, Some(reason));
+        }
+        summary.add(PlanAction::CaseCollision, Some("collides with Feature"));
+
+        assert_eq!(summary.protected.get("open ticket"), Some(&2));
+        assert_eq!(
+            summary.describe(),
+            "3 to delete, 3 kept (not merged 2, too new 1), 3 protected (open ticket 2, current 1), 1 case collision"
+        );
+        assert_eq!(PlanSummary::default().describe(), "0 to delete");
+    }
+
+    #[test]
+    fn test_diff_plans() {
+        let previous = test_plan();
+        let mut current = test_plan();
+        current
+            .branches
+            .retain(|entry| entry.name != "feature/done");
+        current.branches[0].action = PlanAction::Delete;
+        current.branches[0].reason = Some("not merged".to_string());
+        let mut gone = test_plan();
+        gone.branches.truncate(1);
+        gone.branches[0].name = "feature/new".to_string();
+        current.branches.append(&mut gone.branches);
+
+        let diff = diff_plans(&previous, &current);
+        let appeared: Vec<(&str, Option<PlanAction>)> = diff
+            .appeared
+            .iter()
+            .map(|c| (c.entry.name.as_str(), c.other.map(|o| o.action)))
+            .collect();
+        assert_eq!(
+            appeared,
+            vec![("wip", Some(PlanAction::Keep)), ("feature/new", None)]
+        );
+        assert_eq!(diff.disappeared.len(), 1);
+        assert_eq!(diff.disappeared[0].entry.name, "feature/done");
+        assert!(diff.disappeared[0].other.is_none());
+        assert_eq!(diff.unchanged, 0);
+
+        let diff = diff_plans(&previous, &previous);
+        assert!(diff.appeared.is_empty() && diff.disappeared.is_empty());
+        assert_eq!(diff.unchanged, 1);
+    }
+
+    #[test]
+    fn test_read_plan() {
+        let path =
+            std::env::temp_dir().join(format!("git-tidy-test-plan-{}.json", std::process::id()));
+        std::fs::write(&path, render(&test_plan(), OutputFormat::Json).unwrap()).unwrap();
+
+        let plan = read_plan(&path).unwrap();
+        assert_eq!(plan.branches.len(), 3);
+        assert_eq!(plan.branches[1].action, PlanAction::Keep);
+        assert_eq!(plan.summary, test_plan().summary);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_render_yaml() {
+        let yaml = render(&test_plan(), OutputFormat::Yaml).unwrap();
+
+        assert!(yaml.contains("- name: feature/done\n  action: delete\n"));
+        assert!(yaml.contains("reason: current"));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template = parse_template("{{name}}\\t{{ age_days }}\\t{{reason}}").unwrap();
+        let output = render_template(&test_plan(), &template);
+
+        assert_eq!(
+            output,
+            "feature/done\t40\t\nwip\t2\ttoo new, \"recent\"\nmain\t0\tcurrent\n"
+        );
+
+        let template = parse_template("{{owner}}").unwrap();
+        assert!(
+            render_template(&test_plan(), &template)
+                .lines()
+                .all(|line| line == "dev@example.com")
+        );
+    }
+
+    #[test]
+    fn test_parse_template_invalid() {
+        assert!(
+            parse_template("{{name}} {{author}}")
+                .unwrap_err()
+                .contains("Unknown field 'author'")
+        );
+        assert!(parse_template("{{name").unwrap_err().contains("Unclosed"));
+        assert_eq!(
+            parse_template("plain").unwrap(),
+            Template {
+                segments: vec![Segment::Literal("plain".to_string())]
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let csv = render(&test_plan(), OutputFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "name,action,reason,merged,last_commit,age_days,owner"
+        );
+        assert!(lines[1].starts_with("feature/done,delete,,true,"));
+        assert!(lines[1].ends_with(",40,dev@example.com"));
+        assert!(lines[2].starts_with("wip,keep,\"too new, \"\"recent\"\"\",false,"));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn test_render_jsonl() {
+        let jsonl = render(&test_plan(), OutputFormat::Jsonl).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["name"], "feature/done");
+        assert_eq!(first["action"], "delete");
+        assert_eq!(first["age_days"], 40);
+        let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(second["reason"], "too new, \"recent\"");
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+use log::debug;
+
+/// Stdout redirected into the user's pager until dropped, which closes the
+/// pipe and waits for the pager to quit.
+pub struct Pager {
+    child: Child,
+    #[cfg(unix)]
+    stdout: libc::c_int,
+}
+
+/// The pager from GIT_PAGER or PAGER (default `less`) split into words, or
+/// `None` when paging is turned off with an empty value or `cat`.
+pub fn pager_command(git_pager: Option<String>, pager: Option<String>) -> Option<Vec<String>> {
+    let command = git_pager.or(pager).unwrap_or_else(|| "less".to_string());
+    let words: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+
+    if words.is_empty() || words == ["cat"] {
+        None
+    } else {
+        Some(words)
+    }
+}
+
+/// Sends the rest of stdout through the pager when stdout is a terminal.
+/// Like git, `less` is run with `LESS=FRX` unless LESS is set, so output
+/// that fits on one screen is printed as usual.
+#[cfg(unix)]
+pub fn start() -> Option<Pager> {
+    use std::io::IsTerminal;
+
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let words = pager_command(std::env::var("GIT_PAGER").ok(), std::env::var("PAGER").ok())?;
+
+    spawn(&words)
+}
+
+/// Starts the pager command `words` and redirects stdout into it, whether
+/// or not stdout is a terminal.
+#[cfg(unix)]
+pub fn spawn(words: &[String]) -> Option<Pager> {
+    use std::os::fd::AsRawFd;
+
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Could not start pager '{}': {}", words.join(" "), e);
+            return None;
+        }
+    };
+    let pipe = child.stdin.take()?;
+
+    // Colors were chosen for the terminal and should survive the pipe.
+    colored::control::set_override(colored::control::SHOULD_COLORIZE.should_colorize());
+    let _ = std::io::stdout().flush();
+    // SAFETY: fd 1 is only swapped for the pipe here and restored in drop.
+    // Quitting the pager early then ends git-tidy quietly, as it does git,
+    // instead of failing on the next write.
+    let stdout = unsafe {
+        let stdout = libc::dup(1);
+        libc::dup2(pipe.as_raw_fd(), 1);
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        stdout
+    };
+
+    Some(Pager { child, stdout })
+}
+
+#[cfg(not(unix))]
+pub fn start() -> Option<Pager> {
+    None
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        #[cfg(unix)]
+        // SAFETY: restores the descriptor saved in start, which closes the
+        // last write end of the pipe so the pager sees end of input.
+        unsafe {
+            libc::dup2(self.stdout, 1);
+            libc::close(self.stdout);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        let some = |s: &str| Some(s.to_string());
+
+        assert_eq!(pager_command(None, None), Some(vec!["less".to_string()]));
+        assert_eq!(
+            pager_command(some("less -S"), some("more")),
+            Some(vec!["less".to_string(), "-S".to_string()])
+        );
+        assert_eq!(
+            pager_command(None, some("more")),
+            Some(vec!["more".to_string()])
+        );
+        assert_eq!(pager_command(some("cat"), some("less")), None);
+        assert_eq!(pager_command(some(""), some("less")), None);
+    }
+}
@@ -0,0 +1,21 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+// Below this many items the work finishes too quickly for a bar to help, so
+// small repositories keep their plain output.
+const MIN_ITEMS: usize = 100;
+
+pub fn progress_bar(len: usize, message: &'static str) -> ProgressBar {
+    if len < MIN_ITEMS {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::new(len as u64);
+    bar.set_style(
+        ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} (eta {eta})")
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    bar.set_message(message);
+
+    bar
+}
@@ -0,0 +1,102 @@
+use dialoguer::console::Term;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Password, Select};
+use std::io::{BufRead, IsTerminal};
+
+use crate::error::{GitTidyError, Result};
+
+/// A yes/no question defaulting to no.
+pub fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        let answer = read_answer(&format!("{} [y/N]: ", question))?;
+        return Ok(matches!(answer.as_str(), "y" | "yes"));
+    }
+
+    let answer = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .default(false)
+        .interact_opt()
+        .map_err(interrupted)?;
+
+    answer.ok_or(GitTidyError::Cancelled)
+}
+
+/// Picks one of `choices`, each a single-letter key and a label, returning
+/// its index.
+pub fn choose(question: &str, choices: &[(char, &str)]) -> Result<usize> {
+    if !std::io::stdin().is_terminal() {
+        let keys: Vec<String> = choices.iter().map(|(key, _)| key.to_string()).collect();
+        let prompt = format!("{} [{}]: ", question, keys.join("/"));
+
+        loop {
+            let answer = read_answer(&prompt)?;
+            let found = choices.iter().position(|(key, label)| {
+                answer == key.to_string() || answer == label.to_lowercase()
+            });
+
+            match found {
+                Some(index) => return Ok(index),
+                None => {
+                    let options: Vec<String> = choices
+                        .iter()
+                        .map(|(key, label)| format!("{} ({})", key, label.to_lowercase()))
+                        .collect();
+                    println!("Please answer {}.", options.join(", "));
+                }
+            }
+        }
+    }
+
+    let labels: Vec<&str> = choices.iter().map(|(_, label)| *label).collect();
+    let answer = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .items(&labels)
+        .default(0)
+        .interact_opt()
+        .map_err(interrupted)?;
+
+    answer.ok_or(GitTidyError::Cancelled)
+}
+
+/// A secret such as an API token, not echoed on a terminal. Redirected
+/// input is read as the first line, so a token can be piped in.
+pub fn secret(question: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        let mut input = String::new();
+        if std::io::stdin().lock().read_line(&mut input)? == 0 {
+            return Err(GitTidyError::Cancelled);
+        }
+        return Ok(input.trim().to_string());
+    }
+
+    Password::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .interact()
+        .map_err(interrupted)
+}
+
+// Redirected input (pipes, CI) gets line-based prompts; reaching the end of
+// the input means nobody is left to answer.
+fn read_answer(prompt: &str) -> Result<String> {
+    println!("{}", prompt);
+
+    let mut input = String::new();
+    if std::io::stdin().lock().read_line(&mut input)? == 0 {
+        return Err(GitTidyError::Cancelled);
+    }
+
+    Ok(input.trim().to_lowercase())
+}
+
+// dialoguer reports ctrl-c as an interrupted read and leaves the cursor
+// hidden.
+fn interrupted(error: dialoguer::Error) -> GitTidyError {
+    let dialoguer::Error::IO(error) = error;
+
+    if error.kind() == std::io::ErrorKind::Interrupted {
+        let _ = Term::stderr().show_cursor();
+        GitTidyError::Cancelled
+    } else {
+        error.into()
+    }
+}
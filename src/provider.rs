@@ -0,0 +1,755 @@
+use chrono::{DateTime, Utc};
+use git2::Repository;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::config::{ServerProtectionConfig, ServerProvider, parse_remote_url};
+use crate::error::{GitTidyError, Result};
+use crate::pull_requests::{Deployment, PullRequest, PullRequestState};
+use crate::tokens::{cli_token, stored_token};
+
+const PER_PAGE: usize = 50;
+
+/// A code forge that knows which branches must not be deleted: those it
+/// protects, and those its pull or merge requests still need.
+pub trait Provider {
+    fn name(&self) -> &'static str;
+
+    /// Names and globs of the protected branches.
+    fn protected_branches(&self) -> Result<Vec<String>>;
+
+    /// The repository's default branch, if it has any branches.
+    fn default_branch(&self) -> Result<Option<String>>;
+
+    /// Open pull (or merge) requests, drafts included.
+    fn open_pull_requests(&self) -> Result<Vec<PullRequest>>;
+
+    /// Merged and closed pull requests. This walks the project's whole
+    /// history, so it is only asked for when a rule needs it.
+    fn closed_pull_requests(&self) -> Result<Vec<PullRequest>>;
+
+    /// The branch each environment was last successfully deployed from.
+    fn deployments(&self) -> Result<Vec<Deployment>> {
+        Err(GitTidyError::DeploymentsUnsupported(self.name()))
+    }
+}
+
+/// The provider for the configured remote, chosen by `provider` or guessed
+/// from the remote's host name.
+pub fn provider_for(
+    repo: &Repository,
+    config: &ServerProtectionConfig,
+) -> Result<Box<dyn Provider>> {
+    let remote = repo.find_remote(config.remote())?;
+    let url = remote.url().unwrap_or_default();
+    let (host, project) =
+        parse_remote_url(url).ok_or_else(|| GitTidyError::UnsupportedRemoteUrl(url.to_string()))?;
+
+    let kind = config
+        .provider_for_host(&host)
+        .ok_or_else(|| GitTidyError::UnknownProvider(host.clone()))?;
+
+    let token_env = config
+        .token_env
+        .clone()
+        .unwrap_or_else(|| default_token_env(kind).to_string());
+    // An exported token beats one stored with `git-tidy auth login`, which
+    // beats the one `gh` or `glab` is logged in with. A keyring that cannot
+    // be reached, as on a headless machine, holds no token.
+    let token = match std::env::var(&token_env) {
+        Ok(token) => token,
+        Err(_) => stored_token(&host)
+            .unwrap_or_else(|e| {
+                log::debug!("{}", e);
+                None
+            })
+            .or_else(|| cli_token(kind, &host))
+            .ok_or(GitTidyError::MissingToken(token_env))?,
+    };
+
+    let base = config
+        .api_url
+        .clone()
+        .unwrap_or_else(|| default_api_url(kind, &host));
+    let base = base.trim_end_matches('/').to_string();
+
+    Ok(match kind {
+        ServerProvider::GitHub => Box::new(GitHub(Api {
+            base,
+            project,
+            auth: ("Authorization", format!("Bearer {}", token)),
+        })),
+        ServerProvider::GitLab => Box::new(GitLab(Api {
+            base,
+            project,
+            auth: ("PRIVATE-TOKEN", token),
+        })),
+        ServerProvider::Gitea => Box::new(Gitea(Api {
+            base,
+            project,
+            auth: ("Authorization", format!("token {}", token)),
+        })),
+        ServerProvider::Bitbucket => Box::new(Bitbucket(Api {
+            base,
+            project,
+            auth: ("Authorization", format!("Bearer {}", token)),
+        })),
+    })
+}
+
+struct Api {
+    base: String,
+    project: String,
+    auth: (&'static str, String),
+}
+
+/// Deployments listed by a `deployments_url` endpoint, for providers without
+/// a deployments API or deploy tooling that lives outside the forge.
+pub fn deployments_from_url(url: &str, token_env: Option<&str>) -> Result<Vec<Deployment>> {
+    let authorization = token_env
+        .map(|name| {
+            std::env::var(name)
+                .map(|token| format!("Bearer {}", token))
+                .map_err(|_| GitTidyError::MissingToken(name.to_string()))
+        })
+        .transpose()?;
+
+    get_json(
+        url,
+        authorization
+            .as_deref()
+            .map(|value| ("Authorization", value)),
+    )
+}
+
+fn get_json<T: DeserializeOwned>(url: &str, auth: Option<(&str, &str)>) -> Result<T> {
+    let headers: Vec<(&str, &str)> = auth.into_iter().collect();
+    let body = crate::http::get(url, &headers).map_err(|e| e.into_error(url))?;
+
+    serde_json::from_str(&body).map_err(|e| GitTidyError::Provider {
+        url: url.to_string(),
+        message: e.to_string(),
+    })
+}
+
+impl Api {
+    fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        get_json(url, Some((self.auth.0, &self.auth.1)))
+    }
+
+    // GitHub, GitLab, and Gitea page by number; a short page is the last.
+    fn get_pages<T: DeserializeOwned>(&self, url: impl Fn(usize) -> String) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+
+        for page in 1.. {
+            let batch: Vec<T> = self.get(&url(page))?;
+            let is_last_page = batch.len() < PER_PAGE;
+            items.extend(batch);
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Named {
+    name: String,
+}
+
+// GitHub's, GitLab's, and Gitea's repository (or project) object.
+#[derive(Debug, Deserialize)]
+struct Repo {
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeadRef {
+    #[serde(rename = "ref")]
+    name: String,
+    #[serde(default)]
+    sha: String,
+}
+
+// GitHub and Gitea share this shape.
+#[derive(Debug, Deserialize)]
+struct ApiPullRequest {
+    #[serde(default)]
+    number: u64,
+    head: HeadRef,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    labels: Vec<Named>,
+    // GitHub sets merged_at, Gitea a merged flag.
+    merged_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    merged: bool,
+    closed_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiPullRequest> for PullRequest {
+    fn from(pr: ApiPullRequest) -> Self {
+        let state = if pr.state == "open" {
+            PullRequestState::Open
+        } else if pr.merged || pr.merged_at.is_some() {
+            PullRequestState::Merged
+        } else {
+            PullRequestState::Closed
+        };
+
+        PullRequest {
+            number: pr.number,
+            branch: pr.head.name,
+            head: pr.head.sha,
+            state,
+            draft: pr.draft,
+            labels: pr.labels.into_iter().map(|l| l.name).collect(),
+            closed_at: pr.closed_at,
+        }
+    }
+}
+
+struct GitHub(Api);
+
+impl Provider for GitHub {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn protected_branches(&self) -> Result<Vec<String>> {
+        let api = &self.0;
+        let branches: Vec<Named> = api.get_pages(|page| {
+            format!(
+                "{}/repos/{}/branches?protected=true&per_page={}&page={}",
+                api.base, api.project, PER_PAGE, page
+            )
+        })?;
+
+        Ok(sorted(branches.into_iter().map(|b| b.name)))
+    }
+
+    fn default_branch(&self) -> Result<Option<String>> {
+        let api = &self.0;
+        let repo: Repo = api.get(&format!("{}/repos/{}", api.base, api.project))?;
+
+        Ok(repo.default_branch)
+    }
+
+    fn open_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        self.pulls("open")
+    }
+
+    fn closed_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        self.pulls("closed")
+    }
+
+    // Deployments come newest first. The newest one whose latest status is
+    // "success" is live; newer failed or pending ones did not replace it. One
+    // marked "inactive" was replaced or taken down, so nothing older is live.
+    fn deployments(&self) -> Result<Vec<Deployment>> {
+        let api = &self.0;
+        let environments: GitHubEnvironments = api.get(&format!(
+            "{}/repos/{}/environments?per_page=100",
+            api.base, api.project
+        ))?;
+
+        let mut deployments = Vec::new();
+        for environment in environments.environments {
+            let candidates: Vec<GitHubDeployment> = api.get(&format!(
+                "{}/repos/{}/deployments?environment={}&per_page={}",
+                api.base,
+                api.project,
+                encode_query(&environment.name),
+                PER_PAGE
+            ))?;
+
+            for candidate in candidates {
+                let statuses: Vec<GitHubDeploymentStatus> = api.get(&format!(
+                    "{}/repos/{}/deployments/{}/statuses?per_page=1",
+                    api.base, api.project, candidate.id
+                ))?;
+
+                match statuses.first().map(|status| status.state.as_str()) {
+                    Some("success") => {
+                        deployments.push(Deployment {
+                            environment: environment.name,
+                            branch: candidate.name,
+                        });
+                        break;
+                    }
+                    Some("inactive") => break,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(deployments)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubEnvironments {
+    environments: Vec<Named>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubDeployment {
+    id: u64,
+    // A branch, tag, or commit; only branches match anything.
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubDeploymentStatus {
+    state: String,
+}
+
+impl GitHub {
+    fn pulls(&self, state: &str) -> Result<Vec<PullRequest>> {
+        let api = &self.0;
+        let pulls: Vec<ApiPullRequest> = api.get_pages(|page| {
+            format!(
+                "{}/repos/{}/pulls?state={}&per_page={}&page={}",
+                api.base, api.project, state, PER_PAGE, page
+            )
+        })?;
+
+        Ok(pulls.into_iter().map(PullRequest::from).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    #[serde(default)]
+    iid: u64,
+    source_branch: String,
+    #[serde(default)]
+    sha: String,
+    state: String,
+    // GitLab before 15.0 only has work_in_progress.
+    #[serde(default, alias = "work_in_progress")]
+    draft: bool,
+    #[serde(default)]
+    labels: Vec<String>,
+    closed_at: Option<DateTime<Utc>>,
+}
+
+impl From<MergeRequest> for PullRequest {
+    fn from(mr: MergeRequest) -> Self {
+        let state = match mr.state.as_str() {
+            "opened" => PullRequestState::Open,
+            "merged" => PullRequestState::Merged,
+            _ => PullRequestState::Closed,
+        };
+
+        PullRequest {
+            number: mr.iid,
+            branch: mr.source_branch,
+            head: mr.sha,
+            state,
+            draft: mr.draft,
+            labels: mr.labels,
+            closed_at: mr.closed_at,
+        }
+    }
+}
+
+struct GitLab(Api);
+
+impl GitLab {
+    fn project_url(&self) -> String {
+        format!(
+            "{}/projects/{}",
+            self.0.base,
+            self.0.project.replace('/', "%2F")
+        )
+    }
+}
+
+impl Provider for GitLab {
+    fn name(&self) -> &'static str {
+        "GitLab"
+    }
+
+    fn protected_branches(&self) -> Result<Vec<String>> {
+        let project = self.project_url();
+        let branches: Vec<Named> = self.0.get_pages(|page| {
+            format!(
+                "{}/protected_branches?per_page={}&page={}",
+                project, PER_PAGE, page
+            )
+        })?;
+
+        Ok(sorted(branches.into_iter().map(|b| b.name)))
+    }
+
+    fn default_branch(&self) -> Result<Option<String>> {
+        let project: Repo = self.0.get(&self.project_url())?;
+
+        Ok(project.default_branch)
+    }
+
+    fn open_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        self.merge_requests("opened")
+    }
+
+    fn closed_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        let mut requests = self.merge_requests("merged")?;
+        requests.extend(self.merge_requests("closed")?);
+        Ok(requests)
+    }
+
+    fn deployments(&self) -> Result<Vec<Deployment>> {
+        let project = self.project_url();
+        let environments: Vec<Named> = self.0.get_pages(|page| {
+            format!(
+                "{}/environments?states=available&per_page={}&page={}",
+                project, PER_PAGE, page
+            )
+        })?;
+
+        let mut deployments = Vec::new();
+        for environment in environments {
+            let latest: Vec<GitLabDeployment> = self.0.get(&format!(
+                "{}/deployments?environment={}&status=success&order_by=id&sort=desc&per_page=1",
+                project,
+                encode_query(&environment.name)
+            ))?;
+
+            deployments.extend(latest.into_iter().map(|deployment| Deployment {
+                environment: environment.name.clone(),
+                branch: deployment.name,
+            }));
+        }
+
+        Ok(deployments)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabDeployment {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+impl GitLab {
+    fn merge_requests(&self, state: &str) -> Result<Vec<PullRequest>> {
+        let project = self.project_url();
+        let requests: Vec<MergeRequest> = self.0.get_pages(|page| {
+            format!(
+                "{}/merge_requests?state={}&per_page={}&page={}",
+                project, state, PER_PAGE, page
+            )
+        })?;
+
+        Ok(requests.into_iter().map(PullRequest::from).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaProtection {
+    // Gitea 1.19 renamed branch_name to rule_name and allowed globs in it.
+    #[serde(alias = "branch_name")]
+    rule_name: String,
+}
+
+struct Gitea(Api);
+
+impl Provider for Gitea {
+    fn name(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn protected_branches(&self) -> Result<Vec<String>> {
+        let api = &self.0;
+        let protections: Vec<GiteaProtection> = api.get(&format!(
+            "{}/repos/{}/branch_protections",
+            api.base, api.project
+        ))?;
+
+        Ok(sorted(protections.into_iter().map(|p| p.rule_name)))
+    }
+
+    fn default_branch(&self) -> Result<Option<String>> {
+        let api = &self.0;
+        let repo: Repo = api.get(&format!("{}/repos/{}", api.base, api.project))?;
+
+        Ok(repo.default_branch)
+    }
+
+    fn open_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        self.pulls("open")
+    }
+
+    fn closed_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        self.pulls("closed")
+    }
+}
+
+impl Gitea {
+    fn pulls(&self, state: &str) -> Result<Vec<PullRequest>> {
+        let api = &self.0;
+        let pulls: Vec<ApiPullRequest> = api.get_pages(|page| {
+            format!(
+                "{}/repos/{}/pulls?state={}&limit={}&page={}",
+                api.base, api.project, state, PER_PAGE, page
+            )
+        })?;
+
+        Ok(pulls.into_iter().map(PullRequest::from).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketRepository {
+    mainbranch: Option<Named>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketPage<T> {
+    values: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketRestriction {
+    pattern: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketCommit {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketSource {
+    branch: Named,
+    commit: Option<BitbucketCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketPullRequest {
+    #[serde(default)]
+    id: u64,
+    source: BitbucketSource,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    // Bitbucket has no close date; a declined request is not updated again.
+    updated_on: Option<DateTime<Utc>>,
+}
+
+impl From<BitbucketPullRequest> for PullRequest {
+    fn from(pr: BitbucketPullRequest) -> Self {
+        let state = match pr.state.as_str() {
+            "OPEN" => PullRequestState::Open,
+            "MERGED" => PullRequestState::Merged,
+            _ => PullRequestState::Closed,
+        };
+
+        PullRequest {
+            number: pr.id,
+            branch: pr.source.branch.name,
+            head: pr.source.commit.map(|c| c.hash).unwrap_or_default(),
+            state,
+            draft: pr.draft,
+            // Bitbucket pull requests have no labels.
+            labels: Vec::new(),
+            closed_at: (state != PullRequestState::Open)
+                .then_some(pr.updated_on)
+                .flatten(),
+        }
+    }
+}
+
+/// Bitbucket Cloud. Bitbucket Data Center has a different API.
+struct Bitbucket(Api);
+
+impl Bitbucket {
+    // Bitbucket pages by following the `next` link.
+    fn get_all<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut next = Some(format!(
+            "{}/repositories/{}/{}",
+            self.0.base, self.0.project, path
+        ));
+
+        while let Some(url) = next {
+            let page: BitbucketPage<T> = self.0.get(&url)?;
+            items.extend(page.values);
+            next = page.next;
+        }
+
+        Ok(items)
+    }
+}
+
+impl Provider for Bitbucket {
+    fn name(&self) -> &'static str {
+        "Bitbucket"
+    }
+
+    fn protected_branches(&self) -> Result<Vec<String>> {
+        let restrictions: Vec<BitbucketRestriction> =
+            self.get_all(&format!("branch-restrictions?pagelen={}", PER_PAGE))?;
+
+        // Restrictions on a branch type (feature, release, ...) carry no
+        // pattern and are skipped.
+        Ok(sorted(restrictions.into_iter().filter_map(|r| r.pattern)))
+    }
+
+    fn default_branch(&self) -> Result<Option<String>> {
+        let repo: BitbucketRepository = self
+            .0
+            .get(&format!("{}/repositories/{}", self.0.base, self.0.project))?;
+
+        Ok(repo.mainbranch.map(|branch| branch.name))
+    }
+
+    fn open_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        let pulls: Vec<BitbucketPullRequest> =
+            self.get_all(&format!("pullrequests?state=OPEN&pagelen={}", PER_PAGE))?;
+
+        Ok(pulls.into_iter().map(PullRequest::from).collect())
+    }
+
+    fn closed_pull_requests(&self) -> Result<Vec<PullRequest>> {
+        let pulls: Vec<BitbucketPullRequest> = self.get_all(&format!(
+            "pullrequests?state=MERGED&state=DECLINED&pagelen={}",
+            PER_PAGE
+        ))?;
+
+        Ok(pulls.into_iter().map(PullRequest::from).collect())
+    }
+}
+
+// Environment names may hold spaces and slashes ("review/feature-x").
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn sorted(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut names: Vec<String> = names.collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn default_token_env(kind: ServerProvider) -> &'static str {
+    match kind {
+        ServerProvider::GitHub => "GITHUB_TOKEN",
+        ServerProvider::GitLab => "GITLAB_TOKEN",
+        ServerProvider::Gitea => "GITEA_TOKEN",
+        ServerProvider::Bitbucket => "BITBUCKET_TOKEN",
+    }
+}
+
+fn default_api_url(kind: ServerProvider, host: &str) -> String {
+    match kind {
+        ServerProvider::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+        ServerProvider::GitHub => format!("https://{}/api/v3", host),
+        ServerProvider::GitLab => format!("https://{}/api/v4", host),
+        ServerProvider::Gitea => format!("https://{}/api/v1", host),
+        ServerProvider::Bitbucket => "https://api.bitbucket.org/2.0".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_api_url() {
+        assert_eq!(
+            default_api_url(ServerProvider::GitHub, "github.com"),
+            "https://api.github.com"
+        );
+        assert_eq!(
+            default_api_url(ServerProvider::GitHub, "github.example.com"),
+            "https://github.example.com/api/v3"
+        );
+        assert_eq!(
+            default_api_url(ServerProvider::Gitea, "codeberg.org"),
+            "https://codeberg.org/api/v1"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_responses() {
+        let pulls: Vec<ApiPullRequest> = serde_json::from_str(
+            r#"[{"number": 1, "state": "open", "draft": true, "head": {"ref": "feature/x", "sha": "abc"},
+                 "labels": [{"name": "do-not-delete"}], "merged_at": null, "closed_at": null}]"#,
+        )
+        .unwrap();
+        let pr = PullRequest::from(pulls.into_iter().next().unwrap());
+        assert_eq!(pr.branch, "feature/x");
+        assert_eq!(pr.head, "abc");
+        assert_eq!(pr.state, PullRequestState::Open);
+        assert!(pr.draft);
+        assert_eq!(pr.labels, vec!["do-not-delete".to_string()]);
+
+        let requests: Vec<MergeRequest> = serde_json::from_str(
+            r#"[{"source_branch": "fix/z", "sha": "def", "state": "closed", "work_in_progress": false,
+                 "labels": [], "closed_at": "2025-01-02T03:04:05Z"}]"#,
+        )
+        .unwrap();
+        let mr = PullRequest::from(requests.into_iter().next().unwrap());
+        assert_eq!(mr.state, PullRequestState::Closed);
+        assert!(mr.closed_at.is_some());
+
+        let old: Vec<GiteaProtection> =
+            serde_json::from_str(r#"[{"branch_name": "main"}]"#).unwrap();
+        let new: Vec<GiteaProtection> =
+            serde_json::from_str(r#"[{"rule_name": "release/*"}]"#).unwrap();
+        assert_eq!(old[0].rule_name, "main");
+        assert_eq!(new[0].rule_name, "release/*");
+
+        let page: BitbucketPage<BitbucketPullRequest> = serde_json::from_str(
+            r#"{"values": [{"source": {"branch": {"name": "bugfix/y"}, "commit": {"hash": "0123456789ab"}},
+                            "state": "DECLINED", "updated_on": "2025-01-02T03:04:05.123456+00:00"}],
+                "next": null}"#,
+        )
+        .unwrap();
+        assert!(page.next.is_none());
+        let pr = PullRequest::from(page.values.into_iter().next().unwrap());
+        assert_eq!(pr.branch, "bugfix/y");
+        assert_eq!(pr.head, "0123456789ab");
+        assert_eq!(pr.state, PullRequestState::Closed);
+        assert!(pr.closed_at.is_some());
+
+        let environments: GitHubEnvironments = serde_json::from_str(
+            r#"{"total_count": 1, "environments": [{"id": 7, "name": "staging"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(environments.environments[0].name, "staging");
+        let deployments: Vec<GitHubDeployment> = serde_json::from_str(
+            r#"[{"id": 42, "ref": "release/2.4", "sha": "abc", "environment": "staging"}]"#,
+        )
+        .unwrap();
+        assert_eq!(deployments[0].id, 42);
+        assert_eq!(deployments[0].name, "release/2.4");
+
+        let deployments: Vec<Deployment> =
+            serde_json::from_str(r#"[{"environment": "staging", "branch": "release/2.4"}]"#)
+                .unwrap();
+        assert_eq!(deployments[0].branch, "release/2.4");
+
+        assert_eq!(encode_query("review/feature x"), "review%2Ffeature%20x");
+    }
+}
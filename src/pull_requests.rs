@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use git2::Oid;
+use serde::Deserialize;
+
+use crate::config::ServerProtectionConfig;
+use crate::error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullRequestState {
+    Open,
+    Merged,
+    /// Closed (declined) without merging.
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    /// The number the forge shows, e.g. 123 for #123 (GitLab's `!123`).
+    pub number: u64,
+    pub branch: String,
+    /// The source branch's commit when the request was last updated. Bitbucket
+    /// only gives an abbreviated hash.
+    pub head: String,
+    pub state: PullRequestState,
+    pub draft: bool,
+    pub labels: Vec<String>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+/// A branch that an environment is currently running.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Deployment {
+    pub environment: String,
+    pub branch: String,
+}
+
+/// What the pull requests say about local branches.
+#[derive(Debug, Default)]
+pub struct PullRequestRules {
+    /// Branches to keep, with the reason shown in the report.
+    pub keep: Vec<(String, String)>,
+    /// Branches whose pull request was closed without merging longer ago than
+    /// `delete_closed_after`, with the commit the request ended on.
+    pub abandoned: Vec<(String, String)>,
+    /// With `delete_merged_prs`, branches whose pull request was merged, with
+    /// the commit it was merged at and its number.
+    pub merged: Vec<(String, String, u64)>,
+}
+
+impl PullRequestRules {
+    pub fn from_pull_requests(
+        pull_requests: &[PullRequest],
+        config: &ServerProtectionConfig,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let keep_labels = config.keep_labels.as_deref().unwrap_or_default();
+        let closed_before = config.delete_closed_after()?.map(|age| age.before(now));
+        let delete_merged = config.delete_merged_prs();
+        let mut rules = PullRequestRules::default();
+
+        for pr in pull_requests {
+            let label = pr
+                .labels
+                .iter()
+                .find(|label| keep_labels.iter().any(|k| k.eq_ignore_ascii_case(label)));
+
+            let reason = match (label, pr.state) {
+                (Some(label), _) => Some(format!("PR label {}", label)),
+                (None, PullRequestState::Open) if pr.draft && config.draft_prs() => {
+                    Some("draft PR".to_string())
+                }
+                (None, PullRequestState::Open) if !pr.draft && config.open_prs() => {
+                    Some("open PR".to_string())
+                }
+                _ => None,
+            };
+
+            if let Some(reason) = reason {
+                rules.keep.push((pr.branch.clone(), reason));
+            } else if pr.state == PullRequestState::Closed
+                && closed_before
+                    .zip(pr.closed_at)
+                    .is_some_and(|(before, closed_at)| closed_at < before)
+            {
+                rules.abandoned.push((pr.branch.clone(), pr.head.clone()));
+            } else if pr.state == PullRequestState::Merged && delete_merged {
+                rules
+                    .merged
+                    .push((pr.branch.clone(), pr.head.clone(), pr.number));
+            }
+        }
+
+        // A branch reopened in a newer request, or labelled in any, stays.
+        let keep = std::mem::take(&mut rules.keep);
+        rules
+            .abandoned
+            .retain(|(branch, _)| !keep.iter().any(|(kept, _)| kept == branch));
+        rules
+            .merged
+            .retain(|(branch, _, _)| !keep.iter().any(|(kept, _)| kept == branch));
+        rules.keep = keep;
+
+        Ok(rules)
+    }
+
+    /// Keeps deployed branches ahead of any pull request reason, even if their
+    /// pull request was abandoned.
+    pub fn keep_deployed(&mut self, deployments: &[Deployment]) {
+        let mut deployed: Vec<(String, String)> = Vec::new();
+        for deployment in deployments {
+            match deployed
+                .iter_mut()
+                .find(|(name, _)| *name == deployment.branch)
+            {
+                Some((_, reason)) => {
+                    reason.push_str(", ");
+                    reason.push_str(&deployment.environment);
+                }
+                None => deployed.push((
+                    deployment.branch.clone(),
+                    format!("deployed to {}", deployment.environment),
+                )),
+            }
+        }
+
+        self.abandoned
+            .retain(|(branch, _)| !deployed.iter().any(|(name, _)| name == branch));
+        self.merged
+            .retain(|(branch, _, _)| !deployed.iter().any(|(name, _)| name == branch));
+        deployed.append(&mut self.keep);
+        self.keep = deployed;
+    }
+
+    pub fn keep_reason(&self, branch: &str) -> Option<&str> {
+        self.keep
+            .iter()
+            .find(|(name, _)| name == branch)
+            .map(|(_, reason)| reason.as_str())
+    }
+
+    /// Whether the branch still points where its closed pull request left
+    /// it; a branch that got commits afterwards is not abandoned.
+    pub fn is_abandoned(&self, branch: &str, tip: Oid) -> bool {
+        let tip = tip.to_string();
+        self.abandoned
+            .iter()
+            .any(|(name, head)| name == branch && !head.is_empty() && tip.starts_with(head))
+    }
+
+    /// The number of the merged pull request the branch still points at the
+    /// end of, however it was merged (squashed and rebased alike).
+    pub fn merged_pull_request(&self, branch: &str, tip: Oid) -> Option<u64> {
+        let tip = tip.to_string();
+        self.merged
+            .iter()
+            .find(|(name, head, _)| name == branch && !head.is_empty() && tip.starts_with(head))
+            .map(|(_, _, number)| *number)
+    }
+}
+
+/// Whether any configured rule needs merged and closed pull requests.
+pub fn needs_closed_pull_requests(config: &ServerProtectionConfig) -> bool {
+    config.delete_closed_after.is_some()
+        || config.delete_merged_prs()
+        || config.keep_labels.as_ref().is_some_and(|l| !l.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pull_request(branch: &str, state: PullRequestState, days_closed: i64) -> PullRequest {
+        PullRequest {
+            number: 7,
+            branch: branch.to_string(),
+            head: "0123456789ab".to_string(),
+            state,
+            draft: false,
+            labels: Vec::new(),
+            closed_at: (state != PullRequestState::Open)
+                .then(|| Utc::now() - chrono::Duration::days(days_closed)),
+        }
+    }
+
+    #[test]
+    fn test_pull_request_rules() {
+        let config = ServerProtectionConfig {
+            open_prs: Some(false),
+            draft_prs: Some(true),
+            keep_labels: Some(vec!["do-not-delete".to_string()]),
+            delete_closed_after: Some("30d".to_string()),
+            ..ServerProtectionConfig::default()
+        };
+        let mut draft = pull_request("spike/draft", PullRequestState::Open, 0);
+        draft.draft = true;
+        let mut labelled = pull_request("spike/keep", PullRequestState::Closed, 90);
+        labelled.labels = vec!["Do-Not-Delete".to_string()];
+        let reopened = pull_request("feature/again", PullRequestState::Open, 0);
+
+        let rules = PullRequestRules::from_pull_requests(
+            &[
+                draft,
+                labelled,
+                pull_request("feature/open", PullRequestState::Open, 0),
+                pull_request("feature/old", PullRequestState::Closed, 45),
+                pull_request("feature/recent", PullRequestState::Closed, 10),
+                pull_request("feature/merged", PullRequestState::Merged, 45),
+                pull_request("feature/again", PullRequestState::Closed, 45),
+                PullRequest {
+                    draft: true,
+                    ..reopened
+                },
+            ],
+            &config,
+            Utc::now(),
+        )
+        .unwrap();
+
+        assert_eq!(rules.keep_reason("spike/draft"), Some("draft PR"));
+        assert_eq!(
+            rules.keep_reason("spike/keep"),
+            Some("PR label Do-Not-Delete")
+        );
+        assert_eq!(rules.keep_reason("feature/open"), None);
+        assert_eq!(rules.keep_reason("feature/again"), Some("draft PR"));
+
+        let tip = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        assert!(rules.is_abandoned("feature/old", tip));
+        assert!(!rules.is_abandoned("feature/old", Oid::zero()));
+        assert!(!rules.is_abandoned("feature/recent", tip));
+        assert!(!rules.is_abandoned("feature/merged", tip));
+        assert!(!rules.is_abandoned("feature/again", tip));
+        assert!(!rules.is_abandoned("spike/keep", tip));
+    }
+
+    #[test]
+    fn test_merged_pull_requests() {
+        let config = ServerProtectionConfig {
+            delete_merged_prs: Some(true),
+            ..ServerProtectionConfig::default()
+        };
+        let mut squashed = pull_request("feature/squashed", PullRequestState::Merged, 3);
+        squashed.number = 123;
+        let rules = PullRequestRules::from_pull_requests(
+            &[
+                squashed,
+                pull_request("feature/closed", PullRequestState::Closed, 3),
+                pull_request("feature/again", PullRequestState::Merged, 3),
+                pull_request("feature/again", PullRequestState::Open, 0),
+            ],
+            &config,
+            Utc::now(),
+        )
+        .unwrap();
+
+        let tip = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        assert_eq!(
+            rules.merged_pull_request("feature/squashed", tip),
+            Some(123)
+        );
+        // Commits added after the merge are not in it.
+        assert_eq!(
+            rules.merged_pull_request("feature/squashed", Oid::zero()),
+            None
+        );
+        assert_eq!(rules.merged_pull_request("feature/closed", tip), None);
+        assert_eq!(rules.merged_pull_request("feature/again", tip), None);
+
+        let rules = PullRequestRules::from_pull_requests(
+            &[pull_request(
+                "feature/squashed",
+                PullRequestState::Merged,
+                3,
+            )],
+            &ServerProtectionConfig::default(),
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(rules.merged_pull_request("feature/squashed", tip), None);
+    }
+
+    #[test]
+    fn test_keep_deployed() {
+        let config = ServerProtectionConfig {
+            delete_closed_after: Some("30d".to_string()),
+            ..ServerProtectionConfig::default()
+        };
+        let mut rules = PullRequestRules::from_pull_requests(
+            &[
+                pull_request("release/2.4", PullRequestState::Open, 0),
+                pull_request("hotfix/login", PullRequestState::Closed, 45),
+            ],
+            &config,
+            Utc::now(),
+        )
+        .unwrap();
+
+        let deployment = |environment: &str, branch: &str| Deployment {
+            environment: environment.to_string(),
+            branch: branch.to_string(),
+        };
+        rules.keep_deployed(&[
+            deployment("staging", "release/2.4"),
+            deployment("qa", "release/2.4"),
+            deployment("demo", "hotfix/login"),
+        ]);
+
+        assert_eq!(
+            rules.keep_reason("release/2.4"),
+            Some("deployed to staging, qa")
+        );
+        assert_eq!(rules.keep_reason("hotfix/login"), Some("deployed to demo"));
+        let tip = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        assert!(!rules.is_abandoned("hotfix/login", tip));
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use git2::Repository;
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::git_operations::{BranchInfo, UpstreamStatus, get_branch_author, get_upstream_status};
+
+pub const AGE_BUCKETS: [&str; 5] = [
+    "< 1 week",
+    "1-4 weeks",
+    "1-3 months",
+    "3-12 months",
+    "> 1 year",
+];
+
+pub struct BranchStats {
+    pub total: usize,
+    pub merged: usize,
+    pub unmerged: usize,
+    pub by_age: [usize; AGE_BUCKETS.len()],
+    pub by_author: Vec<(String, usize)>,
+    pub upstream_tracking: usize,
+    pub upstream_gone: usize,
+    pub upstream_none: usize,
+}
+
+pub fn collect_stats(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    now: DateTime<Utc>,
+) -> Result<BranchStats> {
+    let mut stats = BranchStats {
+        total: branches.len(),
+        merged: 0,
+        unmerged: 0,
+        by_age: [0; AGE_BUCKETS.len()],
+        by_author: Vec::new(),
+        upstream_tracking: 0,
+        upstream_gone: 0,
+        upstream_none: 0,
+    };
+
+    let mut authors: HashMap<String, usize> = HashMap::new();
+
+    for branch in branches {
+        if branch.is_merged {
+            stats.merged += 1;
+        } else {
+            stats.unmerged += 1;
+        }
+
+        let days = (now - branch.last_commit_date).num_days();
+        stats.by_age[age_bucket(days)] += 1;
+
+        *authors
+            .entry(get_branch_author(repo, &branch.name)?)
+            .or_default() += 1;
+
+        match get_upstream_status(repo, &branch.name)? {
+            UpstreamStatus::Tracking => stats.upstream_tracking += 1,
+            UpstreamStatus::Gone => stats.upstream_gone += 1,
+            UpstreamStatus::None => stats.upstream_none += 1,
+        }
+    }
+
+    stats.by_author = authors.into_iter().collect();
+    stats
+        .by_author
+        .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    Ok(stats)
+}
+
+pub fn age_bucket(days: i64) -> usize {
+    match days {
+        ..7 => 0,
+        7..28 => 1,
+        28..90 => 2,
+        90..365 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age_bucket() {
+        assert_eq!(AGE_BUCKETS[age_bucket(0)], "< 1 week");
+        assert_eq!(AGE_BUCKETS[age_bucket(6)], "< 1 week");
+        assert_eq!(AGE_BUCKETS[age_bucket(7)], "1-4 weeks");
+        assert_eq!(AGE_BUCKETS[age_bucket(30)], "1-3 months");
+        assert_eq!(AGE_BUCKETS[age_bucket(120)], "3-12 months");
+        assert_eq!(AGE_BUCKETS[age_bucket(365)], "> 1 year");
+        assert_eq!(AGE_BUCKETS[age_bucket(-1)], "< 1 week");
+    }
+}
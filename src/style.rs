@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+
+use crate::config::Period;
+#[cfg(feature = "cli")]
+pub use marks::{arrow, bar_cell, delete_mark, keep_mark, set_ascii};
+
+// The report's marks, which only the command line draws.
+#[cfg(feature = "cli")]
+mod marks {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use colored::{ColoredString, Colorize};
+
+    static ASCII: AtomicBool = AtomicBool::new(false);
+
+    /// Switches the report's marks to plain ASCII, for terminals that cannot
+    /// show ✗ and ✓.
+    pub fn set_ascii(ascii: bool) {
+        ASCII.store(ascii, Ordering::Relaxed);
+    }
+
+    fn glyph(unicode: &'static str, ascii: &'static str) -> &'static str {
+        if ASCII.load(Ordering::Relaxed) {
+            ascii
+        } else {
+            unicode
+        }
+    }
+
+    /// Marks a branch or worktree that will be deleted, or an error.
+    pub fn delete_mark() -> ColoredString {
+        glyph("✗", "x").red()
+    }
+
+    /// Marks a protected branch or worktree, or a passing check.
+    pub fn keep_mark() -> ColoredString {
+        glyph("✓", "+").green()
+    }
+
+    /// One cell of a histogram bar.
+    pub fn bar_cell() -> &'static str {
+        glyph("█", "#")
+    }
+
+    /// Between a branch's old and new name.
+    pub fn arrow() -> &'static str {
+        glyph("→", "->")
+    }
+}
+
+/// How stale a branch looks at a glance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeSeverity {
+    Recent,
+    Aging,
+    Stale,
+}
+
+/// Age thresholds for coloring: green below `recent`, yellow below
+/// `stale`, and red from then on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgeColors {
+    pub recent: Period,
+    pub stale: Period,
+}
+
+impl Default for AgeColors {
+    fn default() -> Self {
+        AgeColors {
+            recent: Period::days(30),
+            stale: Period::days(90),
+        }
+    }
+}
+
+impl AgeColors {
+    /// How a branch last active at `date` looks at `now`.
+    pub fn severity(&self, date: DateTime<Utc>, now: DateTime<Utc>) -> AgeSeverity {
+        if date > self.recent.before(now) {
+            AgeSeverity::Recent
+        } else if date > self.stale.before(now) {
+            AgeSeverity::Aging
+        } else {
+            AgeSeverity::Stale
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    pub fn paint(
+        &self,
+        date: DateTime<Utc>,
+        now: DateTime<Utc>,
+        text: &str,
+    ) -> colored::ColoredString {
+        use colored::Colorize;
+
+        match self.severity(date, now) {
+            AgeSeverity::Recent => text.green(),
+            AgeSeverity::Aging => text.yellow(),
+            AgeSeverity::Stale => text.red(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_age_severity() {
+        let colors = AgeColors::default();
+        let now = Utc::now();
+        let ago = |days| now - Duration::days(days);
+
+        assert_eq!(colors.severity(ago(-1), now), AgeSeverity::Recent);
+        assert_eq!(colors.severity(ago(29), now), AgeSeverity::Recent);
+        assert_eq!(colors.severity(ago(30), now), AgeSeverity::Aging);
+        assert_eq!(colors.severity(ago(89), now), AgeSeverity::Aging);
+        assert_eq!(colors.severity(ago(90), now), AgeSeverity::Stale);
+
+        let colors = AgeColors {
+            recent: Period::days(7),
+            stale: Period::days(14),
+        };
+        assert_eq!(colors.severity(ago(10), now), AgeSeverity::Aging);
+        assert_eq!(colors.severity(ago(14), now), AgeSeverity::Stale);
+    }
+}
@@ -0,0 +1,50 @@
+//! Repository fixtures shared by the unit tests.
+
+use chrono::{DateTime, Utc};
+use git2::{Oid, Repository, RepositoryInitOptions, Signature};
+use std::path::PathBuf;
+
+/// A new repository in the temp directory with one commit on `main`. `name`
+/// must be unique across the tests, which run in parallel.
+pub fn init_test_repo(name: &str) -> (PathBuf, Repository) {
+    let path = std::env::temp_dir().join(format!(
+        "git-tidy-test-repo-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+
+    let repo =
+        Repository::init_opts(&path, RepositoryInitOptions::new().initial_head("main")).unwrap();
+    commit_on_head(&repo, "initial commit");
+
+    (path, repo)
+}
+
+pub fn commit_on_head(repo: &Repository, message: &str) -> Oid {
+    commit_on_head_at(repo, message, Utc::now())
+}
+
+/// Commits the index to HEAD, authored and committed at `time`.
+pub fn commit_on_head_at(repo: &Repository, message: &str, time: DateTime<Utc>) -> Oid {
+    let signature = Signature::new(
+        "Test",
+        "test@example.com",
+        &git2::Time::new(time.timestamp(), 0),
+    )
+    .unwrap();
+    let tree_id = repo.index().unwrap().write_tree().unwrap();
+    let tree = repo.find_tree(tree_id).unwrap();
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )
+    .unwrap()
+}
@@ -0,0 +1,1787 @@
+use chrono::{DateTime, Utc};
+use git2::{Oid, Repository};
+use glob::Pattern;
+use log::debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::{Config, DateBound};
+use crate::error::{GitTidyError, Result};
+use crate::filters::{FilterExpr, in_date_range, issue_key};
+use crate::git_operations::{
+    BranchInfo, MergeDates, RemoteBranch, apply_merge_dates, apply_reflog_dates,
+    archive_planned_branch, case_collisions, current_user_email, delete_planned_branch,
+    delete_remote_branches, ensure_no_operation_in_progress, get_checked_out_branches,
+    get_detached_head, get_remote_branches, get_tagged_commits, has_description_marker,
+    ignores_case, is_tip_tagged, list_branch_names, list_branches, list_remote_branches,
+    list_stashed_branches, remote_default_branches, resolve_merge_targets, scan_branches,
+    unmerged_commits,
+};
+use crate::output::{Plan, PlanAction, PlanEntry, PlanSummary};
+use crate::pull_requests::PullRequestRules;
+
+/// The reason orphan branches are kept, or given when they are deleted.
+pub const ORPHAN: &str = "orphan";
+
+/// Asked which of the given issue keys are still open.
+pub type TicketLookup = Box<dyn Fn(&[String]) -> Result<Vec<String>>>;
+
+/// Asked whether to go ahead with a branch, e.g. by prompting for it.
+pub type ConfirmBranch<'a> = &'a dyn Fn(&Deletion) -> Result<Confirmation>;
+
+/// What a cleanup looks at and which branches it may remove; the library
+/// side of the command-line flags. Forge and tracker lookups are left to
+/// the caller, who passes in their results.
+pub struct Options {
+    pub config: Config,
+    /// Evaluate ages relative to this time instead of now.
+    pub as_of: Option<DateTime<Utc>>,
+    pub merged: bool,
+    /// Also delete branches that are not merged, discarding their commits.
+    pub include_unmerged: bool,
+    /// Also delete orphan branches, which share no history with the merge
+    /// target.
+    pub include_orphans: bool,
+    /// Skip working out which branches are merged, which dominates the run
+    /// time in very large repositories. Every branch then counts as
+    /// unmerged, apart from ones at the same commit as another branch.
+    pub no_merge_check: bool,
+    /// Overrides the preset's and the config's `older_than`.
+    pub older_than: Option<DateBound>,
+    /// Only branches merged longer ago than this, counted from the merge.
+    pub older_than_merge: Option<DateBound>,
+    pub since: Option<DateBound>,
+    pub until: Option<DateBound>,
+    pub where_expr: Option<FilterExpr>,
+    /// A preset from the config's `[presets]`, or a built-in one.
+    pub preset: Option<String>,
+    /// Only branches matching one of these globs are candidates.
+    pub only: Vec<Pattern>,
+    /// Only branches owned by the repository's `user.email`.
+    pub mine: bool,
+    /// Plan branches on `remote_names` (origin by default) instead of local
+    /// branches.
+    pub remote_only: bool,
+    pub remote_names: Vec<String>,
+    /// Also delete each deleted branch's remote branch.
+    pub delete_remote: bool,
+    /// Move branches to the archive namespace instead of deleting them.
+    pub archive: bool,
+    pub reflog_age: bool,
+    pub ignore_stashes: bool,
+    /// Only these branches, which are still subject to every protection and
+    /// filter.
+    pub selection: Option<Vec<String>>,
+    /// What the forge's pull requests and deployments say about branches.
+    pub pull_requests: PullRequestRules,
+    /// Default branches the forge reports, as (remote, branch), protected
+    /// like the ones the remotes' HEAD refs name.
+    pub default_branches: Vec<(String, String)>,
+    /// Looks up the issue keys in branch names (per the `[jira]` projects);
+    /// branches whose ticket is still open are protected.
+    pub open_tickets: Option<TicketLookup>,
+}
+
+/// A branch the plan removes.
+#[derive(Clone)]
+pub struct Deletion {
+    pub branch: BranchInfo,
+    /// Why a branch that is not plainly merged may go, e.g. "redundant:
+    /// main" or "PR closed without merging".
+    pub reason: Option<String>,
+    /// The branch's full ref name, e.g. `refs/heads/feature`, which
+    /// `execute` removes.
+    pub reference: String,
+    /// A branch that stays and forked from this one's unmerged commits,
+    /// which deleting this branch leaves as the only ref holding them.
+    pub shares_unmerged_with: Option<String>,
+    /// The remote branches deleted along with it, or instead of it with
+    /// `remote_only`.
+    pub remote_branches: Vec<RemoteBranch>,
+}
+
+/// A branch the plan leaves alone, and why.
+#[derive(Clone)]
+pub struct Kept {
+    pub branch: BranchInfo,
+    pub reason: String,
+}
+
+/// What a cleanup would do, from `plan`. It holds on to the options it was
+/// made with, which `execute` follows.
+pub struct TidyPlan<'a> {
+    options: &'a Options,
+    /// The time ages were measured from.
+    pub now: DateTime<Utc>,
+    pub merge_targets: Vec<Oid>,
+    /// The abbreviated commit HEAD is detached at, if it is.
+    pub detached_head: Option<String>,
+    /// Whether merges were checked in a shallow clone, where branches
+    /// merged before its oldest commit look unmerged.
+    pub shallow: bool,
+    /// Names in `Options::selection` that are not branches.
+    pub missing: Vec<String>,
+    // Only `plan` decides what `execute` removes, as it removes them without
+    // checking their protection and merge status again.
+    to_delete: Vec<Deletion>,
+    /// Candidates the filters left out.
+    pub kept: Vec<Kept>,
+    /// Orphan branches, kept unless `include_orphans` is set.
+    pub orphans: Vec<BranchInfo>,
+    pub protected: Vec<Kept>,
+    /// Branches whose name differs only in case from another's, which on a
+    /// case-insensitive filesystem share its ref file, so removing either
+    /// could remove both. The reason names the other branch.
+    pub case_collisions: Vec<Kept>,
+}
+
+/// What `execute` should do next with a branch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Confirmation {
+    Yes,
+    /// Skip this branch.
+    No,
+    /// Skip this and the remaining branches of this pass.
+    Quit,
+}
+
+/// Hooks for a front end to prompt and report progress while `execute`
+/// works through the plan. Without them every planned branch is removed
+/// without asking, and failures are retried once.
+#[derive(Default)]
+pub struct ExecuteOptions<'a> {
+    /// Called before each branch is attempted. An `Err(Cancelled)` stops
+    /// the run, leaving remote branches untouched.
+    pub confirm: Option<ConfirmBranch<'a>>,
+    /// Called as each branch is dealt with.
+    pub on_outcome: Option<&'a dyn Fn(&Outcome)>,
+    /// Asked whether to retry the given number of failures once.
+    pub confirm_retry: Option<&'a dyn Fn(usize) -> Result<bool>>,
+    /// Checked before each branch; once it returns true, the remaining
+    /// branches are not attempted (e.g. after Ctrl-C).
+    pub stop: Option<&'a dyn Fn() -> bool>,
+}
+
+/// What happened to one branch during `execute`.
+pub enum Outcome<'a> {
+    /// The confirmation said no.
+    Skipped(&'a BranchInfo),
+    /// Deleted, or archived with `Options::archive`.
+    Removed(&'a BranchInfo),
+    /// Not removed. Unless its tip moved since the plan was made, it is
+    /// also in `Report::failed`.
+    Failed(&'a BranchInfo, &'a GitTidyError),
+    RemoteDeleted(&'a RemoteBranch),
+    RemoteFailed(&'a RemoteBranch, &'a GitTidyError),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub branch: String,
+    pub error: String,
+}
+
+/// What `execute` did, branch by branch.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub deleted: Vec<String>,
+    pub archived: Vec<String>,
+    /// As `remote/branch`.
+    pub remote_deleted: Vec<String>,
+    pub failed: Vec<Failure>,
+    /// Branches left alone once `ExecuteOptions::stop` returned true.
+    pub not_attempted: Vec<String>,
+    /// Deleted and failed counts per remote.
+    pub remote_results: BTreeMap<String, (usize, usize)>,
+    /// The commits the deleted branches pointed at.
+    pub deleted_tips: Vec<Oid>,
+    /// A confirmation was cancelled, which ended the run.
+    pub cancelled: bool,
+}
+
+impl Options {
+    pub fn new(config: Config) -> Self {
+        Options {
+            config,
+            as_of: None,
+            merged: false,
+            include_unmerged: false,
+            include_orphans: false,
+            no_merge_check: false,
+            older_than: None,
+            older_than_merge: None,
+            since: None,
+            until: None,
+            where_expr: None,
+            preset: None,
+            only: Vec::new(),
+            mine: false,
+            remote_only: false,
+            remote_names: Vec::new(),
+            delete_remote: false,
+            archive: false,
+            reflog_age: false,
+            ignore_stashes: false,
+            selection: None,
+            pull_requests: PullRequestRules::default(),
+            default_branches: Vec::new(),
+            open_tickets: None,
+        }
+    }
+
+    /// The remotes `remote_only` cleans up, origin unless `remote_names`
+    /// says otherwise.
+    pub fn remote_only_remotes(&self) -> Vec<&str> {
+        if self.remote_names.is_empty() {
+            vec!["origin"]
+        } else {
+            self.remote_names.iter().map(String::as_str).collect()
+        }
+    }
+
+    // Branches whose pull request was merged on the forge, or abandoned,
+    // count as merged.
+    fn is_deletable(&self, branch: &BranchInfo) -> bool {
+        branch.has_no_unique_work()
+            || self.pull_requests.is_abandoned(&branch.name, branch.tip)
+            || self
+                .pull_requests
+                .merged_pull_request(&branch.name, branch.tip)
+                .is_some()
+            || (branch.is_orphan && self.include_orphans)
+    }
+}
+
+impl Deletion {
+    fn to_entry(&self, action: PlanAction, now: DateTime<Utc>) -> PlanEntry {
+        let mut entry = PlanEntry::new(&self.branch, action, self.reason.clone(), now);
+        entry.warning = self
+            .shares_unmerged_with
+            .as_ref()
+            .map(|other| format!("shares unmerged commits with {}", other));
+        entry
+    }
+}
+
+impl TidyPlan<'_> {
+    pub fn to_delete(&self) -> &[Deletion] {
+        &self.to_delete
+    }
+
+    pub fn action(&self) -> PlanAction {
+        if self.options.archive {
+            PlanAction::Archive
+        } else {
+            PlanAction::Delete
+        }
+    }
+
+    /// Every branch with its action and reason, for `output::render`.
+    pub fn to_output(&self) -> Plan {
+        let entries =
+            self.to_delete
+                .iter()
+                .map(|d| d.to_entry(self.action(), self.now))
+                .chain(self.kept.iter().map(|k| {
+                    PlanEntry::new(
+                        &k.branch,
+                        PlanAction::Keep,
+                        Some(k.reason.clone()),
+                        self.now,
+                    )
+                }))
+                .chain(self.orphans.iter().map(|b| {
+                    PlanEntry::new(b, PlanAction::Keep, Some(ORPHAN.to_string()), self.now)
+                }))
+                .chain(self.protected.iter().map(|k| {
+                    PlanEntry::new(
+                        &k.branch,
+                        PlanAction::Protected,
+                        Some(k.reason.clone()),
+                        self.now,
+                    )
+                }))
+                .chain(self.case_collisions.iter().map(|k| {
+                    PlanEntry::new(
+                        &k.branch,
+                        PlanAction::CaseCollision,
+                        Some(k.reason.clone()),
+                        self.now,
+                    )
+                }))
+                .collect();
+
+        Plan::new(self.now, entries)
+    }
+
+    pub fn summary(&self) -> PlanSummary {
+        let mut summary = PlanSummary::default();
+        for _ in &self.to_delete {
+            summary.add(self.action(), None);
+        }
+        for kept in &self.kept {
+            summary.add(PlanAction::Keep, Some(&kept.reason));
+        }
+        for _ in &self.orphans {
+            summary.add(PlanAction::Keep, Some(ORPHAN));
+        }
+        for protected in &self.protected {
+            summary.add(PlanAction::Protected, Some(&protected.reason));
+        }
+        for collision in &self.case_collisions {
+            summary.add(PlanAction::CaseCollision, Some(&collision.reason));
+        }
+        summary
+    }
+}
+
+/// Lists the branches and sorts them into those to delete, those the
+/// filters keep, orphans, and protected ones, without changing anything.
+pub fn plan<'a>(repo: &Repository, options: &'a Options) -> Result<TidyPlan<'a>> {
+    stream_plan(repo, options, &mut |_| Ok(()))
+}
+
+/// Like [`plan`], but hands each branch's entry to `on_entry` as soon as the
+/// branch is analyzed and classified, in name order rather than by age, so a
+/// caller can start on a very large repository's branches before the scan
+/// is done.
+pub fn stream_plan<'a>(
+    repo: &Repository,
+    options: &'a Options,
+    on_entry: &mut dyn FnMut(PlanEntry) -> Result<()>,
+) -> Result<TidyPlan<'a>> {
+    let config = &options.config;
+
+    for remote in &options.remote_names {
+        repo.find_remote(remote)?;
+    }
+
+    let now = options.as_of.unwrap_or_else(Utc::now);
+    let checked_out = get_checked_out_branches(repo)?;
+    let detached_head = get_detached_head(repo)?;
+
+    let merge_targets = if options.no_merge_check {
+        debug!("Skipping the merge check; every branch counts as unmerged");
+        Vec::new()
+    } else {
+        resolve_merge_targets(repo, config)?
+    };
+    let selected = |branch_name: &str| {
+        options
+            .selection
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|name| name == branch_name))
+    };
+
+    let protected_patterns = config.get_protected_patterns()?;
+
+    // Whatever each remote calls its default branch, e.g. trunk, is kept
+    // along with the default protected names.
+    let mut default_branches = Vec::new();
+    if config.default_protection() {
+        default_branches = remote_default_branches(repo)?;
+        default_branches.extend(options.default_branches.iter().cloned());
+    }
+
+    let branch_names = || -> Result<Vec<String>> {
+        if !options.remote_only {
+            return list_branch_names(repo, None);
+        }
+        let mut names = Vec::new();
+        for remote in options.remote_only_remotes() {
+            names.extend(list_branch_names(repo, Some(remote))?);
+        }
+        Ok(names)
+    };
+
+    // The lookup is batched, so it needs every name before the scan.
+    let open_tickets = match &options.open_tickets {
+        Some(lookup) => {
+            let mut keys: Vec<String> = branch_names()?
+                .iter()
+                .filter(|name| selected(name))
+                .filter_map(|name| issue_key(name, config.jira.projects()))
+                .collect();
+            keys.sort();
+            keys.dedup();
+            lookup(&keys)?
+        }
+        None => Vec::new(),
+    };
+    // Where names differing only in case share a ref file, deleting either
+    // branch could remove the other.
+    let case_collisions = if ignores_case(repo) {
+        case_collisions(&branch_names()?)
+    } else {
+        Vec::new()
+    };
+
+    let open_ticket = |branch_name: &str| {
+        issue_key(branch_name, config.jira.projects()).filter(|key| open_tickets.contains(key))
+    };
+    // An archive namespace under refs/heads holds branches already archived.
+    let is_archive_branch = |branch_name: &str| {
+        config
+            .archived_name(&format!("refs/heads/{}", branch_name))
+            .is_some()
+    };
+
+    let tagged_commits = if config.protect_tagged() {
+        get_tagged_commits(repo)?
+    } else {
+        Vec::new()
+    };
+
+    let mut delete_only_patterns = config.get_delete_only_patterns()?;
+    delete_only_patterns.extend(options.only.iter().cloned());
+
+    let preset = options
+        .preset
+        .as_deref()
+        .map(|name| config.get_preset(name))
+        .transpose()?;
+    let merged_only =
+        !options.include_unmerged || options.merged || preset.as_ref().is_some_and(|p| p.merged);
+    let older_than = options
+        .older_than
+        .or(preset.as_ref().and_then(|p| p.older_than))
+        .or(config.older_than()?)
+        .map(|bound| bound.resolve(now));
+    let older_than_merge = options.older_than_merge.map(|bound| bound.resolve(now));
+    if let Some(preset) = &preset {
+        delete_only_patterns.extend(preset.only.iter().cloned());
+    }
+
+    let user_email = if options.mine {
+        Some(current_user_email(repo)?)
+    } else {
+        None
+    };
+    let is_mine = |b: &BranchInfo| {
+        user_email
+            .as_ref()
+            .is_none_or(|email| b.owner.eq_ignore_ascii_case(email))
+    };
+
+    let since = options.since.map(|bound| bound.resolve(now));
+    let until = options.until.map(|bound| bound.resolve(now));
+
+    let stashed_branches = if options.ignore_stashes {
+        Vec::new()
+    } else {
+        list_stashed_branches(repo)?
+    };
+
+    let protect_reason = |branch: &BranchInfo| -> Result<Option<String>> {
+        let checkout = checked_out.iter().find(|c| c.branch == branch.name);
+
+        let reason = if checkout.is_some_and(|c| c.is_current) {
+            "current".to_string()
+        } else if let Some(checkout) = checkout {
+            format!("checked out in {}", checkout.checkout)
+        } else if protected_patterns.iter().any(|p| p.is_match(&branch.name))
+            && !config.is_unprotected(&branch.name)
+        {
+            "regex pattern".to_string()
+        } else if config.is_protected(&branch.name) {
+            "glob pattern".to_string()
+        } else if config.get_protected_branches().contains(&branch.name) {
+            "protected".to_string()
+        } else if let Some((remote, _)) = default_branches
+            .iter()
+            .find(|(_, default)| *default == branch.name)
+            && !config.is_unprotected(&branch.name)
+        {
+            format!("default branch of {}", remote)
+        } else if config.is_ignored(&branch.name) {
+            "ignored".to_string()
+        } else if config.is_merge_target(&branch.name)? {
+            "merge target".to_string()
+        } else if !tagged_commits.is_empty()
+            && is_tip_tagged(repo, branch.tip, branch.is_merged, &tagged_commits)
+        {
+            "tagged".to_string()
+        } else if let Some(until) = config.exception_until(&branch.name, now)? {
+            format!("exception until {}", until)
+        } else if let Some(marker) = config.description_marker()
+            && has_description_marker(repo, &branch.name, marker)?
+        {
+            "description".to_string()
+        } else if let Some(reason) = options.pull_requests.keep_reason(&branch.name) {
+            reason.to_string()
+        } else if let Some(key) = open_ticket(&branch.name) {
+            format!("open ticket {}", key)
+        } else if is_archive_branch(&branch.name) {
+            "archived".to_string()
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(reason))
+    };
+
+    // The filters in the order they apply, so a branch several of them
+    // leave out is kept for the first one's reason.
+    let keep_reason = |branch: &BranchInfo| {
+        let reason = if !delete_only_patterns.is_empty()
+            && !delete_only_patterns.iter().any(|p| p.matches(&branch.name))
+        {
+            "not allowlisted"
+        } else if !is_mine(branch) {
+            "not mine"
+        } else if options
+            .where_expr
+            .as_ref()
+            .is_some_and(|expr| !expr.matches(branch, now))
+        {
+            "not matching --where"
+        } else if !options.is_deletable(branch) && merged_only {
+            "not merged"
+        } else if !branch.is_empty
+            && older_than.is_some_and(|older_than| branch.last_commit_date > older_than)
+        {
+            "too new"
+        } else if !branch.is_empty
+            && older_than_merge.is_some_and(|older_than| branch.merge_date() > older_than)
+        {
+            "merged too recently"
+        } else if !in_date_range(branch, since, None) {
+            "before --since"
+        } else if !in_date_range(branch, None, until) {
+            "after --until"
+        } else if stashed_branches.contains(&branch.name) {
+            "has stash"
+        } else {
+            return None;
+        };
+        Some(reason)
+    };
+
+    let action = if options.archive {
+        PlanAction::Archive
+    } else {
+        PlanAction::Delete
+    };
+    let mut seen: Vec<String> = Vec::new();
+    let mut to_delete = Vec::new();
+    let mut kept = Vec::new();
+    let mut orphans = Vec::new();
+    let mut protected = Vec::new();
+    let mut collisions = Vec::new();
+    // The branches this run leaves in place, and the unmerged candidates to
+    // check against them once every branch has been seen.
+    let mut remaining: Vec<(String, Oid)> = Vec::new();
+    let mut unmerged = Vec::new();
+
+    scan(repo, options, &merge_targets, &mut |branch| {
+        if !selected(&branch.name) {
+            remaining.push((branch.name, branch.tip));
+            return Ok(());
+        }
+        if options.selection.is_some() {
+            seen.push(branch.name.clone());
+        }
+
+        if let Some((_, other)) = case_collisions
+            .iter()
+            .find(|(name, _)| *name == branch.name)
+        {
+            let reason = format!("collides with {}", other);
+            on_entry(PlanEntry::new(
+                &branch,
+                PlanAction::CaseCollision,
+                Some(reason.clone()),
+                now,
+            ))?;
+            remaining.push((branch.name.clone(), branch.tip));
+            collisions.push(Kept { branch, reason });
+        } else if let Some(reason) = protect_reason(&branch)? {
+            on_entry(PlanEntry::new(
+                &branch,
+                PlanAction::Protected,
+                Some(reason.clone()),
+                now,
+            ))?;
+            remaining.push((branch.name.clone(), branch.tip));
+            protected.push(Kept { branch, reason });
+        } else if branch.is_orphan && !options.include_orphans {
+            // Nothing relates an orphan to the merge target, so nothing says
+            // it is done with; it is only deleted when asked for.
+            on_entry(PlanEntry::new(
+                &branch,
+                PlanAction::Keep,
+                Some(ORPHAN.to_string()),
+                now,
+            ))?;
+            remaining.push((branch.name.clone(), branch.tip));
+            orphans.push(branch);
+        } else if let Some(reason) = keep_reason(&branch) {
+            on_entry(PlanEntry::new(
+                &branch,
+                PlanAction::Keep,
+                Some(reason.to_string()),
+                now,
+            ))?;
+            remaining.push((branch.name.clone(), branch.tip));
+            kept.push(Kept {
+                branch,
+                reason: reason.to_string(),
+            });
+        } else {
+            let remote_branches = if options.remote_only {
+                branch
+                    .remote
+                    .iter()
+                    .map(|remote| RemoteBranch {
+                        remote: remote.clone(),
+                        branch: branch.name.clone(),
+                        tip: branch.tip,
+                    })
+                    .collect()
+            } else if options.delete_remote {
+                get_remote_branches(
+                    repo,
+                    &branch.name,
+                    branch.tip,
+                    &options.remote_names,
+                    &merge_targets,
+                )?
+            } else {
+                Vec::new()
+            };
+
+            debug!(
+                "{}: will be {}",
+                branch.name,
+                if options.archive {
+                    "archived"
+                } else {
+                    "deleted"
+                }
+            );
+            let reason = delete_reason(&branch, &options.pull_requests);
+            let deletion = Deletion {
+                reference: format!("refs/heads/{}", branch.name),
+                branch,
+                reason,
+                shares_unmerged_with: None,
+                remote_branches,
+            };
+            // Whether a branch that stays depends on its commits is only
+            // known once every branch has been seen.
+            if !deletion.branch.has_no_unique_work() && !merge_targets.is_empty() {
+                unmerged.push(deletion);
+            } else {
+                on_entry(deletion.to_entry(action, now))?;
+                to_delete.push(deletion);
+            }
+        }
+        Ok(())
+    })?;
+
+    // An unmerged candidate that forked off the same unmerged work as a
+    // branch that stays leaves that branch the only ref on the shared line,
+    // e.g. the base of a stack of branches. It is still deleted, with a
+    // warning naming the other branch.
+    if !unmerged.is_empty() {
+        let mut holders: HashMap<Oid, Vec<usize>> = HashMap::new();
+        for (i, (_, tip)) in remaining.iter().enumerate() {
+            for commit in unmerged_commits(repo, *tip, &merge_targets)? {
+                holders.entry(commit).or_default().push(i);
+            }
+        }
+        for deletion in &mut unmerged {
+            let tip = deletion.branch.tip;
+            let commits = unmerged_commits(repo, tip, &merge_targets)?;
+            // A branch that contains the candidate, or that it contains,
+            // loses nothing by it going.
+            let containing = holders.get(&tip).map_or(&[][..], Vec::as_slice);
+            deletion.shares_unmerged_with = commits
+                .iter()
+                .filter_map(|commit| holders.get(commit))
+                .flatten()
+                .filter(|i| !containing.contains(i) && !commits.contains(&remaining[**i].1))
+                .min()
+                .map(|i| remaining[*i].0.clone());
+            if let Some(other) = &deletion.shares_unmerged_with {
+                debug!(
+                    "{}: shares unmerged commits with {}",
+                    deletion.branch.name, other
+                );
+            }
+        }
+    }
+    for deletion in unmerged {
+        on_entry(deletion.to_entry(action, now))?;
+        to_delete.push(deletion);
+    }
+
+    // Newest first, as `list_branches_by_age` lists them.
+    to_delete.sort_by_key(|d| std::cmp::Reverse(d.branch.last_commit_date));
+    kept.sort_by_key(|k| std::cmp::Reverse(k.branch.last_commit_date));
+    orphans.sort_by_key(|b| std::cmp::Reverse(b.last_commit_date));
+    protected.sort_by_key(|k| std::cmp::Reverse(k.branch.last_commit_date));
+    collisions.sort_by_key(|k| std::cmp::Reverse(k.branch.last_commit_date));
+
+    let missing = match &options.selection {
+        Some(names) => names
+            .iter()
+            .filter(|name| !seen.contains(name))
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+
+    Ok(TidyPlan {
+        options,
+        now,
+        shallow: repo.is_shallow() && !options.no_merge_check,
+        merge_targets,
+        detached_head,
+        missing,
+        to_delete,
+        kept,
+        orphans,
+        protected,
+        case_collisions: collisions,
+    })
+}
+
+/// The branches `plan` starts from, dated and owned as the options and
+/// config say.
+pub fn list_branches_by_age(
+    repo: &Repository,
+    options: &Options,
+    merge_targets: &[Oid],
+) -> Result<Vec<BranchInfo>> {
+    let config = &options.config;
+    let mut branches = if options.remote_only {
+        let mut branches = Vec::new();
+        for remote in options.remote_only_remotes() {
+            branches.extend(list_remote_branches(repo, remote, merge_targets)?);
+        }
+        branches.sort_by_key(|b| std::cmp::Reverse(b.last_commit_date));
+        branches
+    } else {
+        list_branches(repo, merge_targets)?
+    };
+    // Remote-tracking reflogs record fetches, not pushes.
+    if !options.remote_only && (options.reflog_age || config.reflog_age()) {
+        apply_reflog_dates(repo, &mut branches)?;
+    }
+    if options.older_than_merge.is_some() {
+        apply_merge_dates(repo, &mut branches, merge_targets)?;
+    }
+    for branch in &mut branches {
+        if let Some(owner) = config.branch_owner(&branch.name) {
+            branch.owner = owner.to_string();
+        }
+    }
+
+    Ok(branches)
+}
+
+// Like `list_branches_by_age`, handing over each branch as soon as it is
+// analyzed.
+fn scan(
+    repo: &Repository,
+    options: &Options,
+    merge_targets: &[Oid],
+    on_branch: &mut dyn FnMut(BranchInfo) -> Result<()>,
+) -> Result<()> {
+    let config = &options.config;
+    // Remote-tracking reflogs record fetches, not pushes.
+    let reflog_age = !options.remote_only && (options.reflog_age || config.reflog_age());
+    let merge_dates = match options.older_than_merge {
+        Some(_) => Some(MergeDates::new(repo, merge_targets)?),
+        None => None,
+    };
+
+    let mut analyzed = |mut branch: BranchInfo| {
+        if reflog_age {
+            apply_reflog_dates(repo, std::slice::from_mut(&mut branch))?;
+        }
+        if let Some(merge_dates) = &merge_dates
+            && branch.is_merged
+        {
+            branch.merged_at = merge_dates.merged_at(repo, branch.tip)?;
+        }
+        if let Some(owner) = config.branch_owner(&branch.name) {
+            branch.owner = owner.to_string();
+        }
+        on_branch(branch)
+    };
+
+    if options.remote_only {
+        for remote in options.remote_only_remotes() {
+            scan_branches(repo, Some(remote), merge_targets, &mut analyzed)?;
+        }
+        Ok(())
+    } else {
+        scan_branches(repo, None, merge_targets, &mut analyzed)
+    }
+}
+
+/// Why a branch with unmerged-looking history is still safe to delete.
+pub fn delete_reason(branch: &BranchInfo, pull_requests: &PullRequestRules) -> Option<String> {
+    if branch.is_orphan {
+        Some(ORPHAN.to_string())
+    } else if pull_requests.is_abandoned(&branch.name, branch.tip) && !branch.has_no_unique_work() {
+        Some("PR closed without merging".to_string())
+    } else if let Some(number) = pull_requests.merged_pull_request(&branch.name, branch.tip)
+        && !branch.has_no_unique_work()
+    {
+        Some(format!("PR #{} merged", number))
+    } else if !branch.has_no_unique_work() {
+        Some("not merged".to_string())
+    } else if branch.is_empty {
+        Some("no unique commits".to_string())
+    } else {
+        branch
+            .redundant
+            .as_ref()
+            .map(|redundant| format!("redundant: {}", redundant))
+    }
+}
+
+/// Deletes or archives the planned branches, then pushes the remote
+/// deletions, one push per remote. Failures other than refusals get one
+/// more pass at the end, e.g. after a lock was released.
+pub fn execute(
+    repo: &mut Repository,
+    plan: &TidyPlan,
+    execute_options: &ExecuteOptions,
+) -> Result<Report> {
+    ensure_no_operation_in_progress(repo)?;
+
+    let options = plan.options;
+    let config = &options.config;
+    let stop = || execute_options.stop.is_some_and(|stop| stop());
+    let notify = |outcome: Outcome| {
+        if let Some(on_outcome) = execute_options.on_outcome {
+            on_outcome(&outcome);
+        }
+    };
+
+    let mut report = Report::default();
+    // Remote deletions wait until the local ones are done so each remote
+    // gets a single push.
+    let mut pending_remote: Vec<RemoteBranch> = Vec::new();
+    let mut pending: Vec<&Deletion> = plan.to_delete.iter().collect();
+    let mut retried = false;
+
+    'attempts: loop {
+        let mut retry: Vec<&Deletion> = Vec::new();
+        let mut retry_remote: Vec<RemoteBranch> = Vec::new();
+
+        let mut queue = pending.into_iter();
+        for deletion in queue.by_ref() {
+            let branch = &deletion.branch;
+            if stop() {
+                report.not_attempted.push(branch.name.clone());
+                break;
+            }
+
+            if let Some(confirm) = execute_options.confirm {
+                match confirm(deletion) {
+                    Ok(Confirmation::Yes) => {}
+                    Ok(Confirmation::No) => {
+                        notify(Outcome::Skipped(branch));
+                        continue;
+                    }
+                    Ok(Confirmation::Quit) => break,
+                    Err(GitTidyError::Cancelled) => {
+                        report.cancelled = true;
+                        break 'attempts;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if branch.remote.is_some() {
+                // Pushed with the other remote deletions below, which skip a
+                // branch the remote no longer has at the planned tip.
+                pending_remote.extend(deletion.remote_branches.iter().cloned());
+                continue;
+            }
+
+            // `plan` settled protection and merge status; only a branch
+            // that moved since is refused here.
+            let result = if options.archive {
+                archive_planned_branch(
+                    repo,
+                    &deletion.reference,
+                    branch.tip,
+                    &config.archive_ref(&branch.name, plan.now),
+                )
+            } else {
+                delete_planned_branch(repo, &deletion.reference, branch.tip)
+            };
+            match result {
+                Ok(()) => {
+                    notify(Outcome::Removed(branch));
+                    if options.archive {
+                        report.archived.push(branch.name.clone());
+                    } else {
+                        report.deleted.push(branch.name.clone());
+                        report.deleted_tips.push(branch.tip);
+                        pending_remote.extend(deletion.remote_branches.iter().cloned());
+                    }
+                }
+                Err(e @ GitTidyError::TipMoved { .. }) => notify(Outcome::Failed(branch, &e)),
+                Err(e) => {
+                    notify(Outcome::Failed(branch, &e));
+                    report.failed.push(Failure {
+                        branch: branch.name.clone(),
+                        error: e.to_string(),
+                    });
+                    if !e.is_refusal() {
+                        retry.push(deletion);
+                    }
+                }
+            }
+        }
+
+        // The remote deletions are left for the user rather than pushed after
+        // a stop.
+        if stop() {
+            report
+                .not_attempted
+                .extend(queue.map(|deletion| deletion.branch.name.clone()));
+            report
+                .not_attempted
+                .extend(pending_remote.drain(..).map(|r| r.to_string()));
+            break;
+        }
+
+        if !pending_remote.is_empty() {
+            let results = delete_remote_branches(repo, &pending_remote, &config.credentials);
+            for (remote_branch, result) in pending_remote.drain(..).zip(results) {
+                let counts = report
+                    .remote_results
+                    .entry(remote_branch.remote.clone())
+                    .or_default();
+                match result {
+                    Ok(()) => {
+                        counts.0 += 1;
+                        notify(Outcome::RemoteDeleted(&remote_branch));
+                        report.remote_deleted.push(remote_branch.to_string());
+                    }
+                    // Like a local branch that moved, kept without counting
+                    // as a failure.
+                    Err(e @ GitTidyError::TipMoved { .. }) => {
+                        notify(Outcome::RemoteFailed(&remote_branch, &e))
+                    }
+                    Err(e) => {
+                        counts.1 += 1;
+                        notify(Outcome::RemoteFailed(&remote_branch, &e));
+                        report.failed.push(Failure {
+                            branch: remote_branch.to_string(),
+                            error: e.to_string(),
+                        });
+                        if !e.is_refusal() {
+                            retry_remote.push(remote_branch);
+                        }
+                    }
+                }
+            }
+        }
+
+        let retry_count = retry.len() + retry_remote.len();
+        if retried || retry_count == 0 {
+            break;
+        }
+        if let Some(confirm_retry) = execute_options.confirm_retry {
+            match confirm_retry(retry_count) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(GitTidyError::Cancelled) => {
+                    report.cancelled = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let retrying: Vec<String> = retry
+            .iter()
+            .map(|deletion| deletion.branch.name.clone())
+            .chain(retry_remote.iter().map(|r| r.to_string()))
+            .collect();
+        report.failed.retain(|f| !retrying.contains(&f.branch));
+        for remote_branch in &retry_remote {
+            if let Some(counts) = report.remote_results.get_mut(&remote_branch.remote) {
+                counts.1 -= 1;
+            }
+        }
+        pending = retry;
+        pending_remote = retry_remote;
+        retried = true;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CredentialsConfig, Period};
+    use crate::filters::parse_filter_expr;
+    use crate::git_operations::fetch_and_prune;
+    use crate::test_support::{commit_on_head, commit_on_head_at, init_test_repo};
+    use git2::Signature;
+
+    #[test]
+    fn test_plan_and_execute() {
+        let (path, mut repo) = init_test_repo("plan");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["merged", "moved", "keep/me", "other"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/other").unwrap();
+        commit_on_head(&repo, "unmerged work");
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut config = Config::new();
+        config.protected_branches.additional = Some(vec!["keep/*".to_string()]);
+        let mut options = Options::new(config);
+        options.ignore_stashes = true;
+        let plan = plan(&repo, &options).unwrap();
+
+        let mut to_delete: Vec<(&str, Option<&str>)> = plan
+            .to_delete()
+            .iter()
+            .map(|d| (d.branch.name.as_str(), d.reason.as_deref()))
+            .collect();
+        to_delete.sort();
+        assert_eq!(
+            to_delete,
+            vec![
+                ("merged", Some("no unique commits")),
+                ("moved", Some("no unique commits"))
+            ]
+        );
+        assert_eq!(
+            plan.kept
+                .iter()
+                .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("other", "not merged")]
+        );
+        let mut protected: Vec<(&str, &str)> = plan
+            .protected
+            .iter()
+            .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+            .collect();
+        protected.sort();
+        assert_eq!(
+            protected,
+            vec![("keep/me", "glob pattern"), ("main", "current")]
+        );
+        assert_eq!(plan.summary().delete, 2);
+
+        // Work added after planning keeps a branch without counting as a
+        // failure.
+        repo.set_head("refs/heads/moved").unwrap();
+        commit_on_head(&repo, "pushed while prompting");
+        repo.set_head("refs/heads/main").unwrap();
+
+        let report = execute(&mut repo, &plan, &ExecuteOptions::default()).unwrap();
+        assert_eq!(report.deleted, vec!["merged".to_string()]);
+        assert!(report.failed.is_empty());
+        assert!(repo.find_branch("merged", git2::BranchType::Local).is_err());
+        assert!(repo.find_branch("moved", git2::BranchType::Local).is_ok());
+        assert!(repo.find_branch("other", git2::BranchType::Local).is_ok());
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_execute_remote_only_skips_moved_branches() {
+        // libgit2 can only push to bare repositories over the local transport.
+        let server_path = std::env::temp_dir().join(format!(
+            "git-tidy-test-tidy-remote-only-server-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&server_path);
+        let server = Repository::init_bare(&server_path).unwrap();
+
+        let (path, mut repo) = init_test_repo("remote-only");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["done", "busy"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.remote("origin", server_path.to_str().unwrap())
+            .unwrap()
+            .push(
+                &["refs/heads/main", "refs/heads/done", "refs/heads/busy"],
+                None,
+            )
+            .unwrap();
+        fetch_and_prune(&repo, &[], &CredentialsConfig::default()).unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.remote_only = true;
+        options.ignore_stashes = true;
+        let plan = plan(&repo, &options).unwrap();
+        let mut to_delete: Vec<&str> = plan
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        to_delete.sort();
+        assert_eq!(to_delete, vec!["busy", "done"]);
+
+        // Someone pushes to busy after the plan was made.
+        repo.set_head("refs/heads/busy").unwrap();
+        commit_on_head(&repo, "pushed since");
+        repo.set_head("refs/heads/main").unwrap();
+        repo.find_remote("origin")
+            .unwrap()
+            .push(&["refs/heads/busy"], None)
+            .unwrap();
+
+        let report = execute(&mut repo, &plan, &ExecuteOptions::default()).unwrap();
+        assert_eq!(report.remote_deleted, vec!["origin/done".to_string()]);
+        assert!(report.failed.is_empty());
+        assert!(server.find_branch("done", git2::BranchType::Local).is_err());
+        assert!(server.find_branch("busy", git2::BranchType::Local).is_ok());
+
+        let _ = std::fs::remove_dir_all(&server_path);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_protects_checked_out_branches() {
+        let (path, mut repo) = init_test_repo("plan-checked-out");
+        let worktree_path = path.with_extension("review");
+        let _ = std::fs::remove_dir_all(&worktree_path);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["done", "review", "wip"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/wip").unwrap();
+        commit_on_head(&repo, "work in progress");
+        repo.set_head("refs/heads/main").unwrap();
+        let reference = repo.find_reference("refs/heads/review").unwrap();
+        repo.worktree(
+            "review",
+            &worktree_path,
+            Some(git2::WorktreeAddOptions::new().reference(Some(&reference))),
+        )
+        .unwrap();
+        drop(reference);
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        let planned = plan(&repo, &options).unwrap();
+
+        let mut protected: Vec<(&str, &str)> = planned
+            .protected
+            .iter()
+            .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+            .collect();
+        protected.sort();
+        assert_eq!(
+            protected,
+            vec![
+                ("main", "current"),
+                ("review", "checked out in worktree review")
+            ]
+        );
+        assert_eq!(
+            planned
+                .kept
+                .iter()
+                .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("wip", "not merged")]
+        );
+
+        options.include_unmerged = true;
+        let planned = plan(&repo, &options).unwrap();
+        let report = execute(&mut repo, &planned, &ExecuteOptions::default()).unwrap();
+        let mut deleted = report.deleted.clone();
+        deleted.sort();
+        assert_eq!(deleted, vec!["done".to_string(), "wip".to_string()]);
+        assert!(repo.find_branch("review", git2::BranchType::Local).is_ok());
+
+        let _ = std::fs::remove_dir_all(worktree_path);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_protects_tagged_branches() {
+        let (path, repo) = init_test_repo("tagged");
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["release-cut", "before-tag", "untagged", "hotfix"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+
+        repo.set_head("refs/heads/release-cut").unwrap();
+        let release_tip = commit_on_head(&repo, "release fix");
+        repo.tag_lightweight(
+            "v1.0.0",
+            &repo.find_object(release_tip, None).unwrap(),
+            false,
+        )
+        .unwrap();
+
+        // Tagged before the branch moved on.
+        repo.set_head("refs/heads/before-tag").unwrap();
+        let prep = commit_on_head(&repo, "prep");
+        repo.tag(
+            "v0.9.0",
+            &repo.find_object(prep, None).unwrap(),
+            &signature,
+            "annotated",
+            false,
+        )
+        .unwrap();
+        commit_on_head(&repo, "after tag");
+
+        repo.set_head("refs/heads/untagged").unwrap();
+        commit_on_head(&repo, "work in progress");
+
+        // The release was cut from a commit on top of the branch.
+        repo.set_head("refs/heads/hotfix").unwrap();
+        let hotfix_tip = commit_on_head(&repo, "hotfix");
+        let hotfix_commit = repo.find_commit(hotfix_tip).unwrap();
+        let released = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "release",
+                &hotfix_commit.tree().unwrap(),
+                &[&hotfix_commit],
+            )
+            .unwrap();
+        repo.tag_lightweight("v1.0.1", &repo.find_object(released, None).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut config = Config::new();
+        config.protected_branches.protect_tagged = Some(true);
+        let mut options = Options::new(config);
+        options.ignore_stashes = true;
+        options.include_unmerged = true;
+        let planned = plan(&repo, &options).unwrap();
+
+        let mut protected: Vec<(&str, &str)> = planned
+            .protected
+            .iter()
+            .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+            .collect();
+        protected.sort();
+        assert_eq!(
+            protected,
+            vec![
+                ("hotfix", "tagged"),
+                ("main", "current"),
+                ("release-cut", "tagged")
+            ]
+        );
+        let mut to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        to_delete.sort();
+        assert_eq!(to_delete, vec!["before-tag", "untagged"]);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_warns_of_shared_unmerged_commits() {
+        let (path, repo) = init_test_repo("shared");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["base", "solo"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/solo").unwrap();
+        commit_on_head(&repo, "solo work");
+        repo.set_head("refs/heads/base").unwrap();
+        commit_on_head(&repo, "shared work");
+        let shared = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("child", &shared, false).unwrap();
+        drop(shared);
+        commit_on_head(&repo, "more base work");
+        repo.set_head("refs/heads/child").unwrap();
+        commit_on_head(&repo, "child work");
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.include_unmerged = true;
+        options.ignore_stashes = true;
+        options.only = vec![Pattern::new("base").unwrap(), Pattern::new("solo").unwrap()];
+        let planned = plan(&repo, &options).unwrap();
+
+        let mut shared: Vec<(&str, Option<&str>)> = planned
+            .to_delete()
+            .iter()
+            .map(|d| (d.branch.name.as_str(), d.shares_unmerged_with.as_deref()))
+            .collect();
+        shared.sort();
+        assert_eq!(shared, vec![("base", Some("child")), ("solo", None)]);
+        assert!(planned.protected.iter().all(|k| k.branch.name == "main"));
+
+        // Nothing is left depending on the shared commits when both go.
+        options.only.push(Pattern::new("child").unwrap());
+        let planned = plan(&repo, &options).unwrap();
+        let mut to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        to_delete.sort();
+        assert_eq!(to_delete, vec!["base", "child", "solo"]);
+        assert!(
+            planned
+                .to_delete()
+                .iter()
+                .all(|d| d.shares_unmerged_with.is_none())
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_protects_remote_default_branches() {
+        let (path, repo) = init_test_repo("remote-default");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["trunk", "done"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/trunk", head.id(), true, "fetch")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/trunk",
+            true,
+            "clone",
+        )
+        .unwrap();
+        drop(head);
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        let planned = plan(&repo, &options).unwrap();
+        let to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        assert_eq!(to_delete, vec!["done"]);
+        assert!(
+            planned
+                .protected
+                .iter()
+                .any(|k| k.branch.name == "trunk" && k.reason == "default branch of origin")
+        );
+
+        options.config.protected_branches.default_protection = Some(false);
+        let planned = plan(&repo, &options).unwrap();
+        assert_eq!(planned.to_delete().len(), 2);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_protects_case_collisions() {
+        let (path, repo) = init_test_repo("case");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["Feature", "feature", "done"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        repo.config()
+            .unwrap()
+            .set_bool("core.ignorecase", false)
+            .unwrap();
+        assert_eq!(plan(&repo, &options).unwrap().to_delete().len(), 3);
+
+        repo.config()
+            .unwrap()
+            .set_bool("core.ignorecase", true)
+            .unwrap();
+        let planned = plan(&repo, &options).unwrap();
+        let to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        assert_eq!(to_delete, vec!["done"]);
+        assert_eq!(planned.to_delete()[0].reference, "refs/heads/done");
+        let mut collisions: Vec<(&str, &str)> = planned
+            .case_collisions
+            .iter()
+            .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+            .collect();
+        collisions.sort();
+        assert_eq!(
+            collisions,
+            vec![
+                ("Feature", "collides with feature"),
+                ("feature", "collides with Feature")
+            ]
+        );
+        assert!(!planned.protected.iter().any(|k| k.branch.name == "Feature"));
+        assert_eq!(planned.summary().case_collisions, 2);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_only_globs() {
+        let (path, repo) = init_test_repo("only");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["dependabot/npm/lodash", "feature/api", "bugfix/login"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        options.only = vec![
+            Pattern::new("feature/*").unwrap(),
+            Pattern::new("dependabot/*").unwrap(),
+        ];
+        let planned = plan(&repo, &options).unwrap();
+
+        let mut to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        to_delete.sort();
+        assert_eq!(to_delete, vec!["dependabot/npm/lodash", "feature/api"]);
+        assert_eq!(
+            planned
+                .kept
+                .iter()
+                .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("bugfix/login", "not allowlisted")]
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_where() {
+        let (path, repo) = init_test_repo("where");
+        let now = Utc::now();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["feature/old", "feature/new", "bugfix/old"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        for (name, days) in [("feature/old", 60), ("feature/new", 5), ("bugfix/old", 60)] {
+            repo.set_head(&format!("refs/heads/{}", name)).unwrap();
+            commit_on_head_at(&repo, name, now - chrono::Duration::days(days));
+        }
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        options.include_unmerged = true;
+        options.as_of = Some(now);
+        let to_delete = |options: &Options| -> Vec<String> {
+            let mut names: Vec<String> = plan(&repo, options)
+                .unwrap()
+                .to_delete()
+                .iter()
+                .map(|d| d.branch.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        options.where_expr = Some(parse_filter_expr("age > 30d && name =~ \"^feature/\"").unwrap());
+        assert_eq!(to_delete(&options), vec!["feature/old"]);
+        let planned = plan(&repo, &options).unwrap();
+        assert!(
+            planned
+                .kept
+                .iter()
+                .any(|k| k.branch.name == "bugfix/old" && k.reason == "not matching --where")
+        );
+
+        options.where_expr = Some(parse_filter_expr("age < 1mo1w").unwrap());
+        assert_eq!(to_delete(&options), vec!["feature/new"]);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_older_than() {
+        let (path, repo) = init_test_repo("older-than");
+        let now = Utc::now();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["old", "exact", "new", "empty"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        for (name, days) in [("old", 50), ("exact", 30), ("new", 15)] {
+            repo.set_head(&format!("refs/heads/{}", name)).unwrap();
+            commit_on_head_at(&repo, name, now - chrono::Duration::days(days));
+        }
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        options.include_unmerged = true;
+        options.as_of = Some(now);
+        options.older_than = Some(DateBound::Ago(Period::days(30)));
+        let outcome = |options: &Options| -> (Vec<String>, Vec<(String, String)>) {
+            let planned = plan(&repo, options).unwrap();
+            let mut to_delete: Vec<String> = planned
+                .to_delete()
+                .iter()
+                .map(|d| d.branch.name.clone())
+                .collect();
+            to_delete.sort();
+            let mut kept: Vec<(String, String)> = planned
+                .kept
+                .iter()
+                .map(|k| (k.branch.name.clone(), k.reason.clone()))
+                .collect();
+            kept.sort();
+            (to_delete, kept)
+        };
+
+        // A branch exactly at the cutoff goes, and one never committed to
+        // goes however recently it was created.
+        assert_eq!(
+            outcome(&options),
+            (
+                vec!["empty".to_string(), "exact".to_string(), "old".to_string()],
+                vec![("new".to_string(), "too new".to_string())]
+            )
+        );
+
+        // Ages count back from --as-of.
+        options.as_of = Some(now - chrono::Duration::days(30));
+        options.older_than = Some(DateBound::Ago(Period::days(15)));
+        assert_eq!(
+            outcome(&options),
+            (
+                vec!["empty".to_string(), "old".to_string()],
+                vec![
+                    ("exact".to_string(), "too new".to_string()),
+                    ("new".to_string(), "too new".to_string())
+                ]
+            )
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_protects_named_branches() {
+        let (path, repo) = init_test_repo("protected-names");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["master", "develop", "release/1.0", "feature-1", "feature-2"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/feature-1").unwrap();
+
+        let mut config = Config::new();
+        config.protected_branches.patterns = Some(vec!["^release/".to_string()]);
+        let mut options = Options::new(config);
+        options.ignore_stashes = true;
+        let planned = plan(&repo, &options).unwrap();
+
+        let mut protected: Vec<(&str, &str)> = planned
+            .protected
+            .iter()
+            .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+            .collect();
+        protected.sort();
+        assert_eq!(
+            protected,
+            vec![
+                ("develop", "protected"),
+                ("feature-1", "current"),
+                ("main", "protected"),
+                ("master", "protected"),
+                ("release/1.0", "regex pattern"),
+            ]
+        );
+        assert_eq!(
+            planned
+                .to_delete()
+                .iter()
+                .map(|d| d.branch.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["feature-2"]
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_older_than_merge() {
+        let (path, repo) = init_test_repo("merge-age");
+        let now = Utc::now();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["merged-long-ago", "merged-yesterday"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+
+        // Both last committed to long ago, and merged 60 days and a day ago.
+        for (name, merged_days) in [("merged-long-ago", 60), ("merged-yesterday", 1)] {
+            repo.set_head(&format!("refs/heads/{}", name)).unwrap();
+            let tip = commit_on_head_at(&repo, name, now - chrono::Duration::days(90));
+            repo.set_head("refs/heads/main").unwrap();
+            let main = repo.head().unwrap().peel_to_commit().unwrap();
+            let branch = repo.find_commit(tip).unwrap();
+            let time = git2::Time::new((now - chrono::Duration::days(merged_days)).timestamp(), 0);
+            let merger = Signature::new("Test", "test@example.com", &time).unwrap();
+            repo.commit(
+                Some("HEAD"),
+                &merger,
+                &merger,
+                &format!("Merge {}", name),
+                &main.tree().unwrap(),
+                &[&main, &branch],
+            )
+            .unwrap();
+        }
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        options.as_of = Some(now);
+        options.older_than_merge = Some(DateBound::Ago(Period::days(30)));
+        let planned = plan(&repo, &options).unwrap();
+
+        let to_delete: Vec<&str> = planned
+            .to_delete()
+            .iter()
+            .map(|d| d.branch.name.as_str())
+            .collect();
+        assert_eq!(to_delete, vec!["merged-long-ago"]);
+        assert_eq!(
+            planned
+                .kept
+                .iter()
+                .map(|k| (k.branch.name.as_str(), k.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("merged-yesterday", "merged too recently")]
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_plan_without_merge_check() {
+        let (path, repo) = init_test_repo("no-merge-check");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["merged", "other"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/other").unwrap();
+        commit_on_head(&repo, "unmerged work");
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        options.no_merge_check = true;
+        let reasons = |plan: &TidyPlan| {
+            let mut to_delete: Vec<(String, Option<String>)> = plan
+                .to_delete()
+                .iter()
+                .map(|d| (d.branch.name.clone(), d.reason.clone()))
+                .collect();
+            to_delete.sort();
+            to_delete
+        };
+
+        let plan = plan(&repo, &options).unwrap();
+        assert!(plan.merge_targets.is_empty());
+        assert_eq!(
+            reasons(&plan),
+            vec![(
+                "merged".to_string(),
+                Some("redundant: same commit as main".to_string())
+            )]
+        );
+        assert_eq!(plan.kept[0].reason, "not merged");
+
+        options.include_unmerged = true;
+        let plan = super::plan(&repo, &options).unwrap();
+        assert_eq!(
+            reasons(&plan),
+            vec![
+                (
+                    "merged".to_string(),
+                    Some("redundant: same commit as main".to_string())
+                ),
+                ("other".to_string(), Some("not merged".to_string()))
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_stream_plan() {
+        let (path, repo) = init_test_repo("stream-plan");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["b-merged", "a-merged", "wip"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        drop(head);
+        repo.set_head("refs/heads/wip").unwrap();
+        commit_on_head(&repo, "unmerged work");
+        repo.set_head("refs/heads/main").unwrap();
+
+        let mut options = Options::new(Config::new());
+        options.ignore_stashes = true;
+        let mut streamed: Vec<(String, PlanAction)> = Vec::new();
+        let plan = stream_plan(&repo, &options, &mut |entry| {
+            streamed.push((entry.name, entry.action));
+            Ok(())
+        })
+        .unwrap();
+
+        // In name order, each branch once.
+        assert_eq!(
+            streamed,
+            vec![
+                ("a-merged".to_string(), PlanAction::Delete),
+                ("b-merged".to_string(), PlanAction::Delete),
+                ("main".to_string(), PlanAction::Protected),
+                ("wip".to_string(), PlanAction::Keep),
+            ]
+        );
+        let output = plan.to_output();
+        let mut planned: Vec<(String, PlanAction)> = output
+            .branches
+            .into_iter()
+            .map(|entry| (entry.name, entry.action))
+            .collect();
+        planned.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(planned, streamed);
+        // An unmerged candidate rather than an orphan.
+        assert!(plan.orphans.is_empty());
+        assert_eq!(plan.kept[0].reason, "not merged");
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,122 @@
+use keyring::Entry;
+use log::debug;
+use std::process::Command;
+
+use crate::config::ServerProvider;
+use crate::error::{GitTidyError, Result};
+
+// Tokens are stored under this service name, one entry per forge host.
+const SERVICE: &str = "git-tidy";
+
+/// The host `auth login` stores a provider's token for when no `--host` is
+/// given: the provider's public instance.
+pub fn default_host(provider: ServerProvider) -> Option<&'static str> {
+    match provider {
+        ServerProvider::GitHub => Some("github.com"),
+        ServerProvider::GitLab => Some("gitlab.com"),
+        ServerProvider::Bitbucket => Some("bitbucket.org"),
+        ServerProvider::Gitea => None,
+    }
+}
+
+/// The token `git-tidy auth login` stored for `host`, if any.
+pub fn stored_token(host: &str) -> Result<Option<String>> {
+    match entry(host)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(keyring_error(e)),
+    }
+}
+
+pub fn store_token(host: &str, token: &str) -> Result<()> {
+    entry(host)?.set_password(token).map_err(keyring_error)
+}
+
+/// Removes the token stored for `host`, returning whether there was one.
+pub fn delete_token(host: &str) -> Result<bool> {
+    match entry(host)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(keyring_error(e)),
+    }
+}
+
+/// The token the forge's own command-line tool (`gh` for GitHub, `glab`
+/// for GitLab) is logged in to `host` with, if it is installed and logged
+/// in. That tool knows where it keeps the token, files or keyring alike.
+pub fn cli_token(provider: ServerProvider, host: &str) -> Option<String> {
+    let (program, args) = cli_token_command(provider, host)?;
+    let output = match Command::new(program).args(&args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Could not run {}: {}", program, e);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        debug!("{} has no token for {}", program, host);
+        return None;
+    }
+
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+fn cli_token_command(provider: ServerProvider, host: &str) -> Option<(&'static str, Vec<String>)> {
+    match provider {
+        ServerProvider::GitHub => Some((
+            "gh",
+            vec![
+                "auth".to_string(),
+                "token".to_string(),
+                "--hostname".to_string(),
+                host.to_string(),
+            ],
+        )),
+        ServerProvider::GitLab => Some((
+            "glab",
+            vec![
+                "config".to_string(),
+                "get".to_string(),
+                "token".to_string(),
+                "--host".to_string(),
+                host.to_string(),
+            ],
+        )),
+        ServerProvider::Gitea | ServerProvider::Bitbucket => None,
+    }
+}
+
+fn entry(host: &str) -> Result<Entry> {
+    Entry::new(SERVICE, host).map_err(keyring_error)
+}
+
+fn keyring_error(error: keyring::Error) -> GitTidyError {
+    GitTidyError::Keyring(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_host() {
+        assert_eq!(default_host(ServerProvider::GitHub), Some("github.com"));
+        assert_eq!(default_host(ServerProvider::GitLab), Some("gitlab.com"));
+        assert_eq!(default_host(ServerProvider::Gitea), None);
+    }
+
+    #[test]
+    fn test_cli_token_command() {
+        let (program, args) =
+            cli_token_command(ServerProvider::GitHub, "github.example.com").unwrap();
+        assert_eq!(program, "gh");
+        assert_eq!(args, ["auth", "token", "--hostname", "github.example.com"]);
+
+        let (program, args) = cli_token_command(ServerProvider::GitLab, "gitlab.com").unwrap();
+        assert_eq!(program, "glab");
+        assert_eq!(args, ["config", "get", "token", "--host", "gitlab.com"]);
+
+        assert!(cli_token_command(ServerProvider::Bitbucket, "bitbucket.org").is_none());
+    }
+}